@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+-- OAuth で取得した Annict のアクセストークン
+ALTER TABLE subscribers ADD COLUMN access_token TEXT;
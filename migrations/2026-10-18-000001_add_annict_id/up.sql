@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+-- Annict のユーザー ID
+-- ユーザー名は変更されうるので、こちらでユーザーを識別する
+-- 既存の行は起動時に Annict に問い合わせて埋める (annict::backfill_annict_ids)
+ALTER TABLE subscribers ADD COLUMN annict_id BIGINT;
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE subscribers DROP COLUMN last_activity_id;
+ALTER TABLE subscribers ADD COLUMN last_activity_date TIMESTAMP (0) WITH TIME ZONE;
+
+DROP TABLE delivered_activities;
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+
+-- 通知済みのアクティビティ
+-- 購読者ごと・チャンネルごとに、通知したアクティビティの Annict の ID を記録する
+CREATE TABLE delivered_activities (
+    subscriber_id INTEGER NOT NULL REFERENCES subscribers (id) ON DELETE CASCADE,
+    channel_id BIGINT NOT NULL,
+    activity_id BIGINT NOT NULL,
+    delivered_at TIMESTAMP (0) WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (subscriber_id, channel_id, activity_id)
+);
+
+-- 最後まで通知し終えたアクティビティの ID
+-- 作成日時の比較の代わりに使う
+ALTER TABLE subscribers DROP COLUMN last_activity_date;
+ALTER TABLE subscribers ADD COLUMN last_activity_id BIGINT;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+-- 通知したメッセージの ID と、その埋め込みの内容 (JSON)
+-- アクティビティが編集されたときにメッセージを更新するために使う
+ALTER TABLE delivered_activities ADD COLUMN message_ids BIGINT[] NOT NULL DEFAULT '{}';
+ALTER TABLE delivered_activities ADD COLUMN content TEXT;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE delivered_activities DROP COLUMN deleted;
+ALTER TABLE channels DROP COLUMN deleted_action;
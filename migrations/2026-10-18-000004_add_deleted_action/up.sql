@@ -0,0 +1,8 @@
+-- Your SQL goes here
+
+-- Annict でアクティビティが削除されたときの、通知したメッセージの扱い
+-- 0: 何もしない, 1: 削除する, 2: 取り消し線を引く, 3: 削除されたと表示する
+ALTER TABLE channels ADD COLUMN deleted_action INTEGER NOT NULL DEFAULT 0;
+
+-- Annict で削除されたアクティビティか
+ALTER TABLE delivered_activities ADD COLUMN deleted BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX delivered_activities_digest_message_id_idx;
+ALTER TABLE delivered_activities DROP COLUMN digest_message_id;
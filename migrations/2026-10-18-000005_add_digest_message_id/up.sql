@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+-- まとめて通知したアクティビティの、要約のメッセージの ID
+-- まとめて通知した場合、message_ids は空になる
+ALTER TABLE delivered_activities ADD COLUMN digest_message_id BIGINT;
+CREATE INDEX delivered_activities_digest_message_id_idx
+    ON delivered_activities (channel_id, digest_message_id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+COMMENT ON COLUMN subscribers.last_activity_id IS NULL;
+DROP TABLE outbox;
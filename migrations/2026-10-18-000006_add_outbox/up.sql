@@ -0,0 +1,17 @@
+-- Your SQL goes here
+
+-- 取得したが、まだ送信し終えていないアクティビティ
+-- 取得したアクティビティは subscribers.last_activity_id の更新と同じトランザクションで加え、
+-- すべてのチャンネルに送信し終えたら sent_at を記録する
+CREATE TABLE outbox (
+    subscriber_id INTEGER NOT NULL REFERENCES subscribers (id) ON DELETE CASCADE,
+    activity_id BIGINT NOT NULL,
+    payload TEXT NOT NULL,
+    created_at TIMESTAMP (0) WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    sent_at TIMESTAMP (0) WITH TIME ZONE,
+    PRIMARY KEY (subscriber_id, activity_id)
+);
+CREATE INDEX outbox_unsent_idx ON outbox (subscriber_id, activity_id) WHERE sent_at IS NULL;
+
+-- last_activity_id は送信待ちに加え終えたアクティビティの ID になる
+COMMENT ON COLUMN subscribers.last_activity_id IS '送信待ちに加え終えたアクティビティの ID';
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+
+-- Discord に投稿した通知のメッセージの履歴
+-- 1 つのアクティビティの中身ごとに 1 つのメッセージを投稿するので、メッセージごとに記録する
+CREATE TABLE notifications (
+    id SERIAL PRIMARY KEY,
+    subscriber_id INTEGER NOT NULL REFERENCES subscribers (id) ON DELETE CASCADE,
+    channel_id BIGINT NOT NULL,
+    activity_id BIGINT NOT NULL,
+    message_id BIGINT NOT NULL,
+    activity_type INTEGER NOT NULL,
+    work_title TEXT NOT NULL,
+    work_url TEXT NOT NULL,
+    notified_at TIMESTAMP (0) WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX notifications_subscriber_id_idx ON notifications (subscriber_id, notified_at);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE subscribers DROP CONSTRAINT subscribers_guild_id_fkey;
+ALTER TABLE channels DROP CONSTRAINT channels_guild_id_fkey;
+DROP TABLE guilds;
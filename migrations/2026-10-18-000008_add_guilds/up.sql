@@ -0,0 +1,29 @@
+-- Your SQL goes here
+
+-- サーバーごとの設定
+CREATE TABLE guilds (
+    guild_id BIGINT PRIMARY KEY,
+    locale TEXT NOT NULL DEFAULT 'ja',
+    -- `+09:00` のような UTC からのオフセット
+    timezone TEXT NOT NULL DEFAULT '+09:00',
+    -- 新しく通知設定するチャンネルで最初に選ばれている通知の種類
+    default_notify_flag INTEGER NOT NULL DEFAULT 31,
+    -- 溜まったアクティビティをまとめて通知する件数の閾値 (NULL は環境変数 DIGEST_THRESHOLD、0 はまとめない)
+    digest_threshold INTEGER,
+    -- 溜まったアクティビティを要約にまとめて通知する時刻 (サーバーのタイムゾーンで 0〜23 時、NULL はすぐに通知する)
+    digest_hour INTEGER,
+    -- Annict アカウントと連携できるロール (NULL は全員)
+    link_role_id BIGINT
+);
+
+INSERT INTO guilds (guild_id)
+SELECT guild_id FROM channels
+UNION
+SELECT guild_id FROM subscribers;
+
+ALTER TABLE channels
+    ADD CONSTRAINT channels_guild_id_fkey
+    FOREIGN KEY (guild_id) REFERENCES guilds (guild_id) ON DELETE CASCADE;
+ALTER TABLE subscribers
+    ADD CONSTRAINT subscribers_guild_id_fkey
+    FOREIGN KEY (guild_id) REFERENCES guilds (guild_id) ON DELETE CASCADE;
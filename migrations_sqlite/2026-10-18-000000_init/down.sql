@@ -0,0 +1,4 @@
+DROP TABLE outbox;
+DROP TABLE delivered_activities;
+DROP TABLE subscribers;
+DROP TABLE channels;
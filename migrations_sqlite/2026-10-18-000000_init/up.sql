@@ -0,0 +1,52 @@
+-- PostgreSQL の migrations/ をすべて反映したものと同じスキーマを作る
+-- 日時は diesel の TimestamptzSqlite と同じ `YYYY-MM-DD HH:MM:SS.SSS+00:00` の形式の文字列で保存する
+
+CREATE TABLE channels (
+    guild_id BIGINT NOT NULL,
+    channel_id BIGINT NOT NULL,
+    notify_flag INTEGER NOT NULL DEFAULT 31,
+    -- 0: 何もしない, 1: 削除する, 2: 取り消し線を引く, 3: 削除されたと表示する
+    deleted_action INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (guild_id, channel_id)
+);
+
+CREATE TABLE subscribers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- discord
+    user_id BIGINT NOT NULL,
+    guild_id BIGINT NOT NULL,
+    -- annict
+    annict_name TEXT NOT NULL,
+    end_cursor TEXT,
+    access_token TEXT,
+    annict_id BIGINT,
+    -- 送信待ちに加え終えたアクティビティの ID
+    last_activity_id BIGINT
+);
+
+CREATE UNIQUE INDEX user_and_guild ON subscribers (user_id, guild_id);
+
+CREATE TABLE delivered_activities (
+    subscriber_id INTEGER NOT NULL REFERENCES subscribers (id) ON DELETE CASCADE,
+    channel_id BIGINT NOT NULL,
+    activity_id BIGINT NOT NULL,
+    delivered_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f+00:00', 'now')),
+    -- 配列の代わりに JSON の配列で保存する
+    message_ids TEXT NOT NULL DEFAULT '[]',
+    content TEXT,
+    deleted BOOLEAN NOT NULL DEFAULT FALSE,
+    digest_message_id BIGINT,
+    PRIMARY KEY (subscriber_id, channel_id, activity_id)
+);
+CREATE INDEX delivered_activities_digest_message_id_idx
+    ON delivered_activities (channel_id, digest_message_id);
+
+CREATE TABLE outbox (
+    subscriber_id INTEGER NOT NULL REFERENCES subscribers (id) ON DELETE CASCADE,
+    activity_id BIGINT NOT NULL,
+    payload TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f+00:00', 'now')),
+    sent_at TEXT,
+    PRIMARY KEY (subscriber_id, activity_id)
+);
+CREATE INDEX outbox_unsent_idx ON outbox (subscriber_id, activity_id) WHERE sent_at IS NULL;
@@ -0,0 +1,12 @@
+CREATE TABLE notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    subscriber_id INTEGER NOT NULL REFERENCES subscribers (id) ON DELETE CASCADE,
+    channel_id BIGINT NOT NULL,
+    activity_id BIGINT NOT NULL,
+    message_id BIGINT NOT NULL,
+    activity_type INTEGER NOT NULL,
+    work_title TEXT NOT NULL,
+    work_url TEXT NOT NULL,
+    notified_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f+00:00', 'now'))
+);
+CREATE INDEX notifications_subscriber_id_idx ON notifications (subscriber_id, notified_at);
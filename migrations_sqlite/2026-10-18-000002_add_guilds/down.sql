@@ -0,0 +1,37 @@
+PRAGMA foreign_keys = OFF;
+
+BEGIN;
+
+CREATE TABLE channels_old (
+    guild_id BIGINT NOT NULL,
+    channel_id BIGINT NOT NULL,
+    notify_flag INTEGER NOT NULL DEFAULT 31,
+    deleted_action INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (guild_id, channel_id)
+);
+INSERT INTO channels_old SELECT guild_id, channel_id, notify_flag, deleted_action FROM channels;
+DROP TABLE channels;
+ALTER TABLE channels_old RENAME TO channels;
+
+CREATE TABLE subscribers_old (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id BIGINT NOT NULL,
+    guild_id BIGINT NOT NULL,
+    annict_name TEXT NOT NULL,
+    end_cursor TEXT,
+    access_token TEXT,
+    annict_id BIGINT,
+    last_activity_id BIGINT
+);
+INSERT INTO subscribers_old
+SELECT id, user_id, guild_id, annict_name, end_cursor, access_token, annict_id, last_activity_id
+FROM subscribers;
+DROP TABLE subscribers;
+ALTER TABLE subscribers_old RENAME TO subscribers;
+CREATE UNIQUE INDEX user_and_guild ON subscribers (user_id, guild_id);
+
+DROP TABLE guilds;
+
+COMMIT;
+
+PRAGMA foreign_keys = ON;
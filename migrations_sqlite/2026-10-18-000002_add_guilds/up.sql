@@ -0,0 +1,59 @@
+-- SQLite では既存のテーブルに外部キー制約を足せないので、channels と subscribers を作り直す
+-- 作り直す間に参照している行が消えないよう、外部キー制約を無効にしておく
+PRAGMA foreign_keys = OFF;
+
+BEGIN;
+
+CREATE TABLE guilds (
+    guild_id BIGINT PRIMARY KEY,
+    locale TEXT NOT NULL DEFAULT 'ja',
+    -- `+09:00` のような UTC からのオフセット
+    timezone TEXT NOT NULL DEFAULT '+09:00',
+    default_notify_flag INTEGER NOT NULL DEFAULT 31,
+    digest_threshold INTEGER,
+    digest_hour INTEGER,
+    link_role_id BIGINT
+);
+
+INSERT INTO guilds (guild_id)
+SELECT guild_id FROM channels
+UNION
+SELECT guild_id FROM subscribers;
+
+CREATE TABLE channels_new (
+    guild_id BIGINT NOT NULL REFERENCES guilds (guild_id) ON DELETE CASCADE,
+    channel_id BIGINT NOT NULL,
+    notify_flag INTEGER NOT NULL DEFAULT 31,
+    -- 0: 何もしない, 1: 削除する, 2: 取り消し線を引く, 3: 削除されたと表示する
+    deleted_action INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (guild_id, channel_id)
+);
+INSERT INTO channels_new SELECT guild_id, channel_id, notify_flag, deleted_action FROM channels;
+DROP TABLE channels;
+ALTER TABLE channels_new RENAME TO channels;
+
+CREATE TABLE subscribers_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- discord
+    user_id BIGINT NOT NULL,
+    guild_id BIGINT NOT NULL REFERENCES guilds (guild_id) ON DELETE CASCADE,
+    -- annict
+    annict_name TEXT NOT NULL,
+    end_cursor TEXT,
+    access_token TEXT,
+    annict_id BIGINT,
+    -- 送信待ちに加え終えたアクティビティの ID
+    last_activity_id BIGINT
+);
+INSERT INTO subscribers_new
+SELECT id, user_id, guild_id, annict_name, end_cursor, access_token, annict_id, last_activity_id
+FROM subscribers;
+DROP TABLE subscribers;
+ALTER TABLE subscribers_new RENAME TO subscribers;
+CREATE UNIQUE INDEX user_and_guild ON subscribers (user_id, guild_id);
+
+PRAGMA foreign_key_check;
+
+COMMIT;
+
+PRAGMA foreign_keys = ON;
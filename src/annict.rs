@@ -1,135 +1,309 @@
-use crate::{db, models::Subscriber, Result};
+use query::{ActivityRange, UserRef};
 
+use crate::{models::Subscriber, source::History, store::Store};
+
+pub use client::*;
+pub use error::*;
 pub use models::*;
+pub use oauth::*;
+pub use retry::*;
+pub use source::*;
 
+mod client;
+mod error;
 mod models;
+mod oauth;
 mod query;
+mod retry;
+mod source;
+#[cfg(test)]
+mod test;
 
-/// ユーザーをデータベースに登録する。
-/// ユーザーがいなくて登録できなかった場合は `false` を返す。
-pub async fn register_user(username: impl AsRef<str>, user_id: u64, guild_id: u64) -> Result<bool> {
-    let res = query::with_after(username.as_ref(), Some(1), None).await?;
+/// 過去のアクティビティを遡るときに 1 回で取得する数。
+const HISTORY_PAGE_SIZE: i32 = 50;
 
-    let user = match res {
-        Response::Data(data) => data.user,
-        Response::Errors(e) => return Err(format!("{:?}", e).into()),
-    };
+/// 新しいアクティビティを最新のものから取得するときに 1 回で取得する数。
+const NEW_PAGE_SIZE: i32 = 50;
 
-    let Some(user) = user else {
-        return Ok(false);
-    };
+/// アクセストークン `access_token` の持ち主の Annict ユーザーを、
+/// Discord のユーザーと紐付けてデータベースに登録する。
+/// 登録した Annict のユーザー名を返す。
+pub async fn register_user(
+    client: &AnnictClient,
+    store: &dyn Store,
+    access_token: &str,
+    user_id: u64,
+    guild_id: u64,
+) -> AnnictResult<String> {
+    let client = client.with_token(access_token);
+    let (annict_id, username) = query::viewer(&client).await?;
+    let user = query::activities(&client, UserRef::Name(&username), ActivityRange::Last(1)).await?;
 
-    let mut conn = db::connect()?;
-    db::insert_or_update_subscriber(
-        &mut conn,
-        user_id,
-        guild_id,
-        username,
-        user.activities.page_info.end_cursor.as_deref(),
-        user.activities
-            .edges
-            .first()
-            .map(|edge| edge.item.created_at()),
-    )?;
-    Ok(true)
+    let (edges, end_cursor) = user.into_activities();
+    store
+        .insert_or_update_subscriber(
+            user_id,
+            guild_id,
+            annict_id,
+            &username,
+            end_cursor.as_deref(),
+            edges.first().map(|activity| activity.id),
+            Some(access_token),
+        )
+        .await
+        .map_err(AnnictError::Database)?;
+    Ok(username)
 }
 
-pub async fn get_new_activities(subscriber: &Subscriber) -> Result<Vec<ActivityItem>> {
-    let activity_connection = match query::with_after(
-        &subscriber.annict_name,
-        None,
-        subscriber.end_cursor.as_deref(),
-    )
-    .await?
-    {
-        Response::Data(data) => {
-            let Some(user) = data.user else {
-                return Err(format!("no such user: {}", subscriber.annict_name).into());
-            };
-            user.activities
+/// Annict のユーザー ID が分かっていない購読者について、ユーザー ID を調べて保存する。
+///
+/// アクセストークンがある場合はその持ち主を、そうでなければ今のユーザー名のユーザーを調べる。
+/// 調べられなかった購読者はログに残して飛ばす。
+pub async fn backfill_annict_ids(client: &AnnictClient, store: &dyn Store) -> AnnictResult<()> {
+    let subscribers = store
+        .get_subscribers_without_annict_id()
+        .await
+        .map_err(AnnictError::Database)?;
+    for subscriber in subscribers {
+        let result = match &subscriber.access_token {
+            Some(token) => query::viewer(&client.with_token(token)).await,
+            None => query::user_id(client, &subscriber.annict_name)
+                .await
+                .map(|annict_id| (annict_id, subscriber.annict_name.clone())),
+        };
+        match result {
+            Ok((annict_id, username)) => {
+                store
+                    .update_annict_user(subscriber.id, annict_id, &username)
+                    .await
+                    .map_err(AnnictError::Database)?;
+            }
+            Err(e) => tracing::warn!(
+                "Annict ユーザー {} の ID を取得できませんでした: {}",
+                subscriber.annict_name,
+                e,
+            ),
         }
-        Response::Errors(e) => return Err(format!("{:?}", e).into()),
-    };
+    }
+    Ok(())
+}
 
-    // before 探索に使うカーソル
-    // after のアクティビティがない場合は before=None で探索すればよいし、
-    // そうでなければ、after のアクティビティの最新のものより古いものを見れば良い
-    let mut cursor = activity_connection
-        .edges
-        .first()
-        .map(|edge| edge.cursor.clone());
+/// 同じ Annict ユーザーの購読者 `subscribers` (サーバーごとに 1 人) について、
+/// 新しいアクティビティを古い順に 1 回だけ取得し、次回の取得を始めるカーソルと合わせて返す。
+///
+/// 購読者ごとに送信待ちに加え終えたところが違う場合は、最も遅れている購読者に合わせて返すので、
+/// それぞれの購読者について [Subscriber::is_new_activity] で絞り込んでから通知すること。
+pub async fn get_new_activities(
+    client: &AnnictClient,
+    store: &dyn Store,
+    subscribers: &[&Subscriber],
+) -> AnnictResult<(Vec<Activity>, Option<String>)> {
+    get_new_activities_batch(client, store, &[subscribers.to_vec()])
+        .await?
+        .pop()
+        .expect("Annict ユーザー 1 人分の結果が返る")
+}
 
-    let after_activities: Vec<_> = activity_connection
-        .edges
-        .into_iter()
-        .map(|edge| edge.item)
+/// `accounts` の Annict ユーザーそれぞれについて [get_new_activities] と同じ処理を行う。
+/// 最初の取得は 1 回のリクエストにまとめるので、`accounts` の数は
+/// [AnnictClient::batch_size] 以下にしておくこと。
+///
+/// 結果は `accounts` と同じ順番で返す。
+/// まとめたリクエスト自体が失敗した場合は全体としてエラーを返す。
+pub async fn get_new_activities_batch(
+    client: &AnnictClient,
+    store: &dyn Store,
+    accounts: &[Vec<&Subscriber>],
+) -> AnnictResult<Vec<AnnictResult<(Vec<Activity>, Option<String>)>>> {
+    // 最も遅れている購読者のカーソルから取得する
+    let representatives: Vec<_> = accounts
+        .iter()
+        .map(|subscribers| {
+            *subscribers
+                .iter()
+                .min_by_key(|subscriber| subscriber.last_activity_id)
+                .expect("購読者が 1 人以上いる")
+        })
+        .collect();
+    // 送信待ちに加え終えたアクティビティの ID が分かっている場合は、最新のものから取得して ID で比べる
+    let users: Vec<_> = representatives
+        .iter()
+        .map(|subscriber| {
+            let user = user_ref(subscriber);
+            let range = match subscriber.last_activity_id {
+                Some(_) => ActivityRange::Last(NEW_PAGE_SIZE),
+                None => ActivityRange::After(subscriber.end_cursor.as_deref()),
+            };
+            (user, range)
+        })
         .collect();
 
-    let mut conn = db::connect()?;
-
-    // 元々 end_cursor が None の場合は現時点ですべて取得し終えているので、特に何もする必要はない
-    if subscriber.end_cursor.is_none() {
-        db::update_subscriber_info(
-            &mut conn,
-            subscriber.id,
-            activity_connection.page_info.end_cursor.as_deref(),
-            after_activities.first().map(|act| act.created_at()),
-        )?;
-        return Ok(after_activities);
+    let mut results = vec![];
+    for ((subscribers, representative), user) in accounts
+        .iter()
+        .zip(representatives.iter())
+        .zip(query::batch_activities(client, &users).await?)
+    {
+        results.push(match user {
+            Ok(user) => {
+                collect_new_activities(client, store, subscribers, representative, user).await
+            }
+            Err(e) => Err(e),
+        });
     }
+    Ok(results)
+}
 
-    // 元々の end_cursor が Some だった場合、データの削除等が起こったときに取得できていない
-    // アクティビティがある可能性があるので、過去も振り返って確認する
-    // 既に見たアクティビティあるので、last_activity_date は必ず Some => unwrap は必ず成功
-    let last_activity_date = db::get_last_activity_date(&mut conn, subscriber.id)?.unwrap();
-    let mut end_cursor = activity_connection.page_info.end_cursor;
-    let mut reversed_before_activities = vec![];
-    loop {
-        let res =
-            query::query_with_before(&subscriber.annict_name, Some(1), cursor.as_deref()).await?;
-        let user = match res {
-            Response::Data(data) => data.user,
-            Response::Errors(e) => return Err(format!("{:?}", e).into()),
-        };
-        let Some(user) = user else {
-            return Err(format!("no such user {}", subscriber.annict_name).into());
-        };
+/// 購読者 `subscriber` の Annict ユーザーの最近 `count` 件のアクティビティを古い順に返す。
+/// 中身が `null` だったアクティビティの ID も合わせて返す。
+/// 購読者の情報は更新しない。
+pub async fn get_recent_activities(
+    client: &AnnictClient,
+    subscriber: &Subscriber,
+    count: i32,
+) -> AnnictResult<(Vec<Activity>, Vec<i64>)> {
+    let user = query::activities(client, user_ref(subscriber), ActivityRange::Last(count)).await?;
+    let unknown_ids = user.null_activity_ids();
+    Ok((user.into_activities().0, unknown_ids))
+}
 
-        let Some(edge) = user.activities.edges.into_iter().next() else {
-            // これ以上過去にアクティビティはないので終了
+/// 購読者 `subscriber` の Annict ユーザーの過去のアクティビティのうち、`history` の範囲のものを古い順に返す。
+/// 購読者の情報は更新しない。
+pub async fn get_past_activities(
+    client: &AnnictClient,
+    subscriber: &Subscriber,
+    history: &History,
+) -> AnnictResult<Vec<Activity>> {
+    // 新しいものから 1 ページずつ遡る
+    let mut cursor = None;
+    let mut reversed_activities = vec![];
+    'pages: loop {
+        let range = match cursor.as_deref() {
+            Some(before) => ActivityRange::Before(before, HISTORY_PAGE_SIZE),
+            None => ActivityRange::Last(HISTORY_PAGE_SIZE),
+        };
+        let user = query::activities(client, user_ref(subscriber), range).await?;
+        // 中身が null のアクティビティも含めて、最も古いもののカーソルから遡る
+        let Some(start_cursor) = user.start_cursor() else {
+            // これ以上過去にアクティビティはない
             break;
         };
+        cursor = Some(start_cursor);
+
+        let (activities, _) = user.into_activities();
 
-        cursor = Some(edge.cursor);
-        if end_cursor.is_none() {
-            // end_cursor が None の場合はこれが一番新しいアクティビティなのでセット
-            end_cursor = cursor.clone();
+        for activity in activities.into_iter().rev() {
+            if !history.includes(activity.item.created_at(), reversed_activities.len()) {
+                break 'pages;
+            }
+            reversed_activities.push(activity);
         }
-        if edge.item.created_at() <= last_activity_date {
-            // 過去に最後まで見たアクティビティよりも過去のデータなら、これより前は見ている
-            break;
+    }
+    reversed_activities.reverse();
+    Ok(reversed_activities)
+}
+
+/// タイトルに `title` を含む作品を、視聴者の多い順に `cursor` の位置から `per_page` 件検索する。
+pub async fn search_works(
+    client: &AnnictClient,
+    title: &str,
+    cursor: &PageCursor,
+    per_page: i64,
+) -> AnnictResult<WorkPage> {
+    query::search_works(client, title, cursor, per_page).await
+}
+
+/// 購読者 `subscriber` の Annict ユーザーの指定方法を返す。
+/// ユーザー ID が分かっていればユーザー名が変わっても同じユーザーを指すように ID で指定する。
+fn user_ref(subscriber: &Subscriber) -> UserRef<'_> {
+    match subscriber.annict_id {
+        Some(annict_id) => UserRef::Id(annict_id),
+        None => UserRef::Name(&subscriber.annict_name),
+    }
+}
+
+/// `representative` について [get_new_activities_batch] で取得した `user` のアクティビティに、
+/// 取りこぼしたアクティビティを加えて古い順に返し、同じ Annict ユーザーの購読者 `subscribers` の
+/// ユーザー名などを更新する。次回の取得を始めるカーソルも合わせて返す。
+/// `representative` は `subscribers` の中で最も通知が遅れている購読者にすること。
+///
+/// 返したアクティビティは、カーソルと一緒に [Store::enqueue_activities] で送信待ちに加えること。
+/// 加えられるまでは、同じアクティビティを再び返す。
+async fn collect_new_activities(
+    client: &AnnictClient,
+    store: &dyn Store,
+    subscribers: &[&Subscriber],
+    representative: &Subscriber,
+    user: UserWithActivities,
+) -> AnnictResult<(Vec<Activity>, Option<String>)> {
+    // ユーザー名が変わっていたり、ID が分かっていなかったりしたら更新する
+    let annict_id = user.user.annict_id;
+    let username = user.user.username.clone();
+    for subscriber in subscribers {
+        if subscriber.annict_id != Some(annict_id) || subscriber.annict_name != username {
+            if subscriber.annict_name != username {
+                tracing::info!(
+                    "Annict ユーザー {} のユーザー名が {} に変更されました",
+                    subscriber.annict_name,
+                    username,
+                );
+            }
+            store
+                .update_annict_user(subscriber.id, annict_id, &username)
+                .await
+                .map_err(AnnictError::Database)?;
         }
-        reversed_before_activities.push(edge.item);
     }
-    let activities: Vec<_> = reversed_before_activities
+
+    let Some(last_activity_id) = representative.last_activity_id else {
+        let (edges, end_cursor) = user.into_activities();
+        // 通知済みのアクティビティの ID が分からない場合 (ID を記録する前からの購読者) は、
+        // after で取得できたものだけを新しいものとする
+        return Ok((edges, end_cursor));
+    };
+
+    // 最新のものから取得したページに送信待ちに加え終えたところが含まれていれば (またはページが最後なら)、
+    // それより新しいものはすべて取得できている
+    // そうでなければ、ページの最も古いものから 1 ページずつ過去に遡って、加え終えたところまでを確認する
+    // ID は新しいものほど大きいので、加え終えたところより前は通知し終えている
+    let reached = |user: &UserWithActivities| {
+        user.min_activity_id()
+            .is_none_or(|id| id <= last_activity_id)
+            || user.activity_count() < NEW_PAGE_SIZE as usize
+    };
+    let mut done = reached(&user);
+    let mut cursor = user.start_cursor();
+    let (edges, end_cursor) = user.into_activities();
+    let mut reversed_before_activities = vec![];
+    while !done {
+        let Some(before) = cursor else {
+            break;
+        };
+        let user = query::activities(
+            client,
+            UserRef::Id(annict_id),
+            ActivityRange::Before(&before, NEW_PAGE_SIZE),
+        )
+        .await?;
+        done = reached(&user);
+        cursor = user.start_cursor();
+        let (activities, _) = user.into_activities();
+        reversed_before_activities.extend(
+            activities
+                .into_iter()
+                .rev()
+                .filter(|activity| activity.id > last_activity_id),
+        );
+    }
+
+    let activities = reversed_before_activities
         .into_iter()
         .rev()
-        .chain(after_activities.into_iter())
+        .chain(
+            edges
+                .into_iter()
+                .filter(|activity| activity.id > last_activity_id),
+        )
         .collect();
-
-    db::update_subscriber_info(
-        &mut conn,
-        subscriber.id,
-        end_cursor.as_deref(),
-        Some(
-            activities
-                .last()
-                .map(|act| act.created_at())
-                // アクティビティが消えても、
-                // 過去のアクティビティよりも古いアクティビティが生えることは無い
-                .unwrap_or_else(|| last_activity_date),
-        ),
-    )?;
-
-    Ok(activities)
+    Ok((activities, end_cursor))
 }
@@ -0,0 +1,275 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use custom_debug::Debug;
+use reqwest::{header::RETRY_AFTER, Client, StatusCode};
+use serde::Serialize;
+use tokio::time;
+
+use crate::{get_env, get_env_opt, parse_duration, Result};
+
+use super::{AnnictError, AnnictResult, RetryPolicy};
+
+/// Annict の GraphQL API のエンドポイント。
+pub const DEFAULT_ENDPOINT: &str = "https://api.annict.com/graphql";
+
+/// リクエストに付与する既定の User-Agent。
+pub const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// 1 回のリクエストでまとめて取得するユーザー数の既定値。
+pub const DEFAULT_BATCH_SIZE: usize = 10;
+
+/// Annict の GraphQL API にクエリを投げるクライアント。
+///
+/// 起動時に一度だけ作成し、以降はこれを使い回す。
+/// 中身の [Client] は [Arc](std::sync::Arc) で共有されているため、`clone` は安価。
+#[derive(Debug, Clone)]
+pub struct AnnictClient {
+    #[debug(skip)]
+    http: Client,
+    endpoint: String,
+    #[debug(skip)]
+    token: String,
+    retry_policy: RetryPolicy,
+    batch_size: usize,
+}
+
+impl AnnictClient {
+    /// トークン `token` を使うクライアントのビルダーを返す。
+    pub fn builder(token: impl Into<String>) -> AnnictClientBuilder {
+        AnnictClientBuilder {
+            token: token.into(),
+            endpoint: DEFAULT_ENDPOINT.into(),
+            timeout: None,
+            user_agent: DEFAULT_USER_AGENT.into(),
+            retry_policy: RetryPolicy::default(),
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    /// 環境変数からクライアントを作成する。
+    ///
+    /// - `ANNICT_TOKEN`: アクセストークン (必須)
+    /// - `ANNICT_ENDPOINT`: GraphQL のエンドポイント
+    /// - `ANNICT_TIMEOUT`: リクエストのタイムアウト (`30s` など)
+    /// - `ANNICT_USER_AGENT`: リクエストの User-Agent
+    /// - `ANNICT_MAX_RETRIES`: 一時的な失敗に対する再試行の最大回数
+    /// - `ANNICT_RETRY_BASE_DELAY`: 再試行までの待ち時間の基準値 (`500ms` など)
+    /// - `ANNICT_RETRY_MAX_DELAY`: 再試行までの待ち時間の上限 (`30s` など)
+    /// - `ANNICT_BATCH_SIZE`: 1 回のリクエストでまとめて取得するユーザー数
+    pub fn from_env() -> Result<Self> {
+        let mut builder = Self::builder(get_env("ANNICT_TOKEN")?);
+        if let Some(endpoint) = get_env_opt("ANNICT_ENDPOINT")? {
+            builder = builder.endpoint(endpoint);
+        }
+        if let Some(timeout) = get_env_opt("ANNICT_TIMEOUT")? {
+            builder = builder.timeout(parse_env_duration("ANNICT_TIMEOUT", &timeout)?);
+        }
+        if let Some(user_agent) = get_env_opt("ANNICT_USER_AGENT")? {
+            builder = builder.user_agent(user_agent);
+        }
+        if let Some(batch_size) = get_env_opt("ANNICT_BATCH_SIZE")? {
+            builder = builder.batch_size(
+                batch_size
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|&size| size > 0)
+                    .ok_or_else(|| {
+                        format!(
+                            "環境変数 `ANNICT_BATCH_SIZE` (\"{}\") の形式が不正です",
+                            batch_size
+                        )
+                    })?,
+            );
+        }
+
+        let mut retry_policy = RetryPolicy::default();
+        if let Some(max_retries) = get_env_opt("ANNICT_MAX_RETRIES")? {
+            retry_policy.max_retries = max_retries.trim().parse().map_err(|_| {
+                format!(
+                    "環境変数 `ANNICT_MAX_RETRIES` (\"{}\") の形式が不正です",
+                    max_retries
+                )
+            })?;
+        }
+        if let Some(base_delay) = get_env_opt("ANNICT_RETRY_BASE_DELAY")? {
+            retry_policy.base_delay = parse_env_duration("ANNICT_RETRY_BASE_DELAY", &base_delay)?;
+        }
+        if let Some(max_delay) = get_env_opt("ANNICT_RETRY_MAX_DELAY")? {
+            retry_policy.max_delay = parse_env_duration("ANNICT_RETRY_MAX_DELAY", &max_delay)?;
+        }
+
+        builder.retry_policy(retry_policy).build()
+    }
+
+    /// トークンだけを `token` に差し替えたクライアントを返す。
+    /// ユーザーのアクセストークンで問い合わせる場合に使う。
+    pub fn with_token(&self, token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+            ..self.clone()
+        }
+    }
+
+    /// クライアントが叩くエンドポイント。
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// 1 回のリクエストでまとめて取得するユーザー数。
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// `query` をエンドポイントに POST し、レスポンスの本文を返す。
+    /// 一時的な失敗は [RetryPolicy] に従って再試行する。
+    pub(super) async fn post_query(&self, query: &impl Serialize) -> AnnictResult<String> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+
+        let mut retries = 0;
+        loop {
+            let error = match self.post_query_once(count, query).await {
+                Ok(response) => return Ok(response),
+                Err(e) => e,
+            };
+            let Some(delay) = self.retry_policy.delay(retries, &error) else {
+                return Err(error);
+            };
+
+            retries += 1;
+            tracing::debug!(
+                "request({}) の {} 回目の再試行を {} ミリ秒後に行います: {}",
+                count,
+                retries,
+                delay.as_millis(),
+                error,
+            );
+            time::sleep(delay).await;
+        }
+    }
+
+    async fn post_query_once(&self, count: usize, query: &impl Serialize) -> AnnictResult<String> {
+        let request = self
+            .http
+            .post(&self.endpoint)
+            .bearer_auth(&self.token)
+            .header("Content-Type", "application/json")
+            .json(query);
+        tracing::trace!("request({}) = {:?}", count, request);
+
+        let response = request.send().await?;
+        match response.status() {
+            StatusCode::UNAUTHORIZED => return Err(AnnictError::Unauthorized),
+            StatusCode::TOO_MANY_REQUESTS => {
+                let retry_after = response
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(parse_retry_after);
+                return Err(AnnictError::RateLimited { retry_after });
+            }
+            _ => {}
+        }
+
+        let response = response.error_for_status()?.text().await?;
+        tracing::trace!("response({}) = {:?}", count, response);
+
+        Ok(response)
+    }
+}
+
+/// [AnnictClient] のビルダー。
+#[derive(Debug)]
+pub struct AnnictClientBuilder {
+    #[debug(skip)]
+    token: String,
+    endpoint: String,
+    timeout: Option<Duration>,
+    user_agent: String,
+    retry_policy: RetryPolicy,
+    batch_size: usize,
+}
+
+impl AnnictClientBuilder {
+    /// GraphQL のエンドポイントを設定する。
+    /// 既定値は [DEFAULT_ENDPOINT]。
+    pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into();
+        self
+    }
+
+    /// リクエストのタイムアウトを設定する。
+    /// 既定ではタイムアウトしない。
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// リクエストの User-Agent を設定する。
+    /// 既定値は [DEFAULT_USER_AGENT]。
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// 一時的な失敗に対する再試行の方針を設定する。
+    /// 既定値は [RetryPolicy::default]。
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// 1 回のリクエストでまとめて取得するユーザー数を設定する。
+    /// 既定値は [DEFAULT_BATCH_SIZE]。
+    ///
+    /// # Panics
+    ///
+    /// `batch_size` が 0 の場合。
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch_size は 1 以上である必要があります");
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn build(self) -> Result<AnnictClient> {
+        let mut http = Client::builder().user_agent(self.user_agent);
+        if let Some(timeout) = self.timeout {
+            http = http.timeout(timeout);
+        }
+
+        Ok(AnnictClient {
+            http: http.build()?,
+            endpoint: self.endpoint,
+            token: self.token,
+            retry_policy: self.retry_policy,
+            batch_size: self.batch_size,
+        })
+    }
+}
+
+/// `Retry-After` ヘッダーの値を待ち時間に変換する。
+/// 秒数と HTTP 日付のどちらの形式にも対応し、過去の日付は待ち時間 0 とする。
+pub(super) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+fn parse_env_duration(key: &str, value: &str) -> Result<Duration> {
+    parse_duration(value)
+        .map_err(|_| format!("環境変数 `{}` (\"{}\") の形式が不正です", key, value).into())
+}
@@ -0,0 +1,97 @@
+use std::{
+    error,
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+
+use graphql_client::Error;
+
+pub type AnnictResult<T> = std::result::Result<T, AnnictError>;
+
+/// Annict とのやり取りで起こるエラー。
+#[derive(Debug)]
+pub enum AnnictError {
+    /// 問い合わせたユーザーが存在しない。
+    NotFound,
+
+    /// トークンが無効 (失効・取り消し) である。
+    Unauthorized,
+
+    /// リクエストが多すぎて制限された。
+    /// `retry_after` は `Retry-After` ヘッダで指定された待ち時間。
+    RateLimited { retry_after: Option<Duration> },
+
+    /// 通信に失敗したか、エラーを示すステータスが返ってきた。
+    Transport(reqwest::Error),
+
+    /// レスポンスを解釈できなかった。
+    Decode {
+        body: String,
+        source: serde_json::Error,
+    },
+
+    /// GraphQL のエラーが返ってきた。
+    GraphQL { errors: Vec<Error> },
+
+    /// 取得したデータの保存・読み込みに失敗した。
+    Database(Box<dyn error::Error + Send + Sync>),
+}
+
+impl AnnictError {
+    /// 時間を置いて再試行すれば成功する可能性があるエラーかどうか。
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::RateLimited { .. } => true,
+            // ステータスが無いものは接続の失敗やタイムアウト
+            Self::Transport(e) => e.status().is_none_or(|status| status.is_server_error()),
+            _ => false,
+        }
+    }
+}
+
+impl Display for AnnictError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => f.write_str("Annict ユーザーが見つかりません"),
+            Self::Unauthorized => f.write_str("Annict のトークンが無効です"),
+            Self::RateLimited {
+                retry_after: Some(retry_after),
+            } => write!(
+                f,
+                "Annict のレート制限に達しました ({} 秒後に再試行可能)",
+                retry_after.as_secs()
+            ),
+            Self::RateLimited { retry_after: None } => {
+                f.write_str("Annict のレート制限に達しました")
+            }
+            Self::Transport(e) => write!(f, "Annict との通信に失敗しました: {}", e),
+            Self::Decode { body, source } => write!(
+                f,
+                "Annict のレスポンスを解釈できませんでした: {} (body = {:?})",
+                source, body
+            ),
+            Self::GraphQL { errors } => {
+                let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+                write!(f, "Annict がエラーを返しました: {}", messages.join(", "))
+            }
+            Self::Database(e) => write!(f, "データベースの操作に失敗しました: {}", e),
+        }
+    }
+}
+
+impl error::Error for AnnictError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Transport(e) => Some(e),
+            Self::Decode { source, .. } => Some(source),
+            Self::Database(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for AnnictError {
+    fn from(value: reqwest::Error) -> Self {
+        Self::Transport(value)
+    }
+}
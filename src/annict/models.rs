@@ -1,9 +1,18 @@
 use std::fmt::{self, Display, Formatter};
 
-use chrono::{DateTime, Local};
-use custom_debug::Debug;
+use chrono::Local;
 use serde::{Deserialize, Serialize};
-use serenity::all::Colour;
+
+// レスポンスの型は queries.graphql のフラグメントからビルド時に生成される
+pub use super::query::user_activities::{
+    ActivityConnection, ActivityConnectionEdges as ActivityEdge, ActivityItem,
+    ActivityItemOnMultipleRecord as MultipleRecord, ActivityItemOnReview as Review,
+    ActivityItemOnStatus as Status, Record, RecordEpisode as Episode, User,
+    UserActivitiesUser as UserWithActivities, Work, WorkImage,
+};
+
+/// スキーマの `DateTime` スカラーに対応する型。
+pub type DateTime = chrono::DateTime<Local>;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
@@ -26,6 +35,29 @@ impl Display for SeasonName {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Media {
+    Movie,
+    Other,
+    Ova,
+    Tv,
+    Web,
+}
+
+impl Display for Media {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Media::Movie => "映画",
+            Media::Other => "その他",
+            Media::Ova => "OVA",
+            Media::Tv => "TV",
+            Media::Web => "Web",
+        };
+        f.write_str(s)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum StatusState {
@@ -37,19 +69,6 @@ pub enum StatusState {
     Watching,
 }
 
-impl StatusState {
-    pub fn to_colour(&self) -> Colour {
-        match self {
-            Self::NoState => Colour::new(0x4dd4ac),
-            Self::OnHold => Colour::new(0xdc2626),
-            Self::StopWatching => Colour::new(0x52525b),
-            Self::WannaWatch => Colour::new(0xea580c),
-            Self::Watched => Colour::new(0x16a34a),
-            Self::Watching => Colour::new(0x0284c7),
-        }
-    }
-}
-
 impl Display for StatusState {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let s = match self {
@@ -64,47 +83,6 @@ impl Display for StatusState {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Work {
-    pub title: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct MultipleRecord {
-    pub records: RecordConnection,
-    pub created_at: DateTime<Local>,
-    pub work: Work,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RecordConnection {
-    pub edges: Vec<RecordEdge>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RecordEdge {
-    pub node: Record,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Record {
-    pub work: Work,
-    pub created_at: DateTime<Local>,
-
-    #[debug(skip_if = Option::is_none)]
-    #[debug(with = opt_fmt)]
-    pub comment: Option<String>,
-
-    pub episode: Episode,
-
-    #[debug(skip_if = Option::is_none)]
-    #[debug(with = opt_fmt)]
-    pub rating_state: Option<RatingState>,
-}
-
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RatingState {
@@ -114,17 +92,6 @@ pub enum RatingState {
     Great,
 }
 
-impl RatingState {
-    pub fn to_colour(&self) -> Colour {
-        match self {
-            Self::Average => Colour::new(0xff6d00),
-            Self::Bad => Colour::new(0x757575),
-            Self::Good => Colour::new(0x00c853),
-            Self::Great => Colour::new(0x00b0ff),
-        }
-    }
-}
-
 impl Display for RatingState {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let s = match self {
@@ -137,70 +104,8 @@ impl Display for RatingState {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Episode {
-    #[debug(skip_if = Option::is_none)]
-    #[debug(with = opt_fmt)]
-    pub number: Option<i32>,
-
-    #[debug(skip_if = Option::is_none)]
-    #[debug(with = opt_fmt)]
-    pub number_text: Option<String>,
-
-    #[debug(skip_if = Option::is_none)]
-    #[debug(with = opt_fmt)]
-    pub title: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Review {
-    pub work: Work,
-    pub created_at: DateTime<Local>,
-
-    pub body: String,
-
-    #[debug(skip_if = Option::is_none)]
-    #[debug(with = opt_fmt)]
-    pub rating_overall_state: Option<RatingState>,
-
-    #[debug(skip_if = Option::is_none)]
-    #[debug(with = opt_fmt)]
-    pub rating_animation_state: Option<RatingState>,
-
-    #[debug(skip_if = Option::is_none)]
-    #[debug(with = opt_fmt)]
-    pub rating_character_state: Option<RatingState>,
-
-    #[debug(skip_if = Option::is_none)]
-    #[debug(with = opt_fmt)]
-    pub rating_story_state: Option<RatingState>,
-
-    #[debug(skip_if = Option::is_none)]
-    #[debug(with = opt_fmt)]
-    pub rating_music_state: Option<RatingState>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Status {
-    pub work: Work,
-    pub created_at: DateTime<Local>,
-    pub state: StatusState,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(tag = "__typename")]
-pub enum ActivityItem {
-    MultipleRecord(MultipleRecord),
-    Record(Record),
-    Review(Review),
-    Status(Status),
-}
-
 impl ActivityItem {
-    pub fn created_at(&self) -> DateTime<Local> {
+    pub fn created_at(&self) -> DateTime {
         match self {
             Self::MultipleRecord(r) => r.created_at,
             Self::Record(r) => r.created_at,
@@ -210,74 +115,172 @@ impl ActivityItem {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ActivityEdge {
-    pub item: ActivityItem,
-    pub cursor: String,
+impl Work {
+    /// Annict 上の作品ページの URL。
+    pub fn url(&self) -> String {
+        format!("https://annict.com/works/{}", self.annict_id)
+    }
+
+    /// 作品の画像の URL。
+    pub fn image_url(&self) -> Option<&str> {
+        self.image
+            .as_ref()
+            .and_then(|image| image.recommended_image_url.as_deref())
+            // 画像が無い場合は空文字列になっていることがある
+            .filter(|url| !url.is_empty())
+    }
+
+    /// `2024年秋` のような放送・公開時期。
+    pub fn season(&self) -> Option<String> {
+        match (self.season_year, self.season_name) {
+            (Some(year), Some(name)) => Some(format!("{}年{}", year, name)),
+            (Some(year), None) => Some(format!("{}年", year)),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Record {
+    /// Annict 上のエピソードページの URL。
+    pub fn episode_url(&self) -> String {
+        format!("{}/episodes/{}", self.work.url(), self.episode.annict_id)
+    }
+
+    /// `5/12` のような視聴の進み具合。
+    /// 話数が分からない場合は `None` を返す。
+    pub fn progress(&self) -> Option<String> {
+        let number = self.episode.number?;
+        (self.work.episodes_count > 0).then(|| format!("{}/{}", number, self.work.episodes_count))
+    }
+}
+
+/// 作品の検索結果の 1 ページ分。
+#[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct ActivityConnection {
-    pub edges: Vec<ActivityEdge>,
+pub struct WorkPage {
+    #[serde(rename = "edges", deserialize_with = "deserialize_work_edges")]
+    pub works: Vec<Work>,
     pub page_info: PageInfo,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// ページ送りの情報。
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PageInfo {
+    pub has_next_page: bool,
+    pub has_previous_page: bool,
     pub start_cursor: Option<String>,
     pub end_cursor: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct User {
-    pub username: String,
-    pub name: String,
+/// 取得するページの指定。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PageCursor {
+    /// 最初のページ。
+    First,
 
-    #[debug(skip_if = Option::is_none)]
-    #[debug(with = opt_fmt)]
-    pub avatar_url: Option<String>,
+    /// カーソルより後のページ。
+    After(String),
+
+    /// カーソルより前のページ。
+    Before(String),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct UserWithActivities {
-    #[serde(flatten)]
-    pub user: User,
+/// `null` を除いて `edges { node }` を作品の列にする。
+fn deserialize_work_edges<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Work>, D::Error> {
+    #[derive(Deserialize)]
+    struct Edge {
+        node: Option<Work>,
+    }
 
-    pub activities: ActivityConnection,
+    let edges: Option<Vec<Option<Edge>>> = Deserialize::deserialize(deserializer)?;
+    Ok(edges
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|edge| edge.node)
+        .collect())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UserQuery<T: std::fmt::Debug> {
-    pub user: Option<T>,
+/// Annict の ID とカーソルの付いたアクティビティ。
+#[derive(Debug, Clone)]
+pub struct Activity {
+    /// Annict のアクティビティの ID。新しいアクティビティほど大きい。
+    pub id: i64,
+    pub cursor: String,
+    pub item: ActivityItem,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Error {
-    pub message: String,
+impl UserWithActivities {
+    /// アクティビティの列と、最後のアクティビティのカーソルを返す。
+    /// `null` のアクティビティは除く。
+    pub fn into_activities(self) -> (Vec<Activity>, Option<String>) {
+        let Some(activities) = self.activities else {
+            return (vec![], None);
+        };
+        let edges = activities
+            .edges
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|edge| {
+                Some(Activity {
+                    id: edge.annict_id,
+                    cursor: edge.cursor,
+                    item: edge.item?,
+                })
+            })
+            .collect();
+        (edges, activities.page_info.end_cursor)
+    }
 
-    #[debug(skip_if = Option::is_none)]
-    #[debug(with = opt_fmt)]
-    pub locations: Option<Vec<Location>>,
-}
+    /// 最も古いアクティビティのカーソルを返す。中身が `null` のアクティビティも含めて考える。
+    pub fn start_cursor(&self) -> Option<String> {
+        self.activities
+            .iter()
+            .flat_map(|activities| activities.edges.iter().flatten().flatten())
+            .next()
+            .map(|edge| edge.cursor.clone())
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Location {
-    pub line: i32,
-    pub column: i32,
-}
+    /// 中身が `null` のものも含めたアクティビティの数を返す。
+    pub fn activity_count(&self) -> usize {
+        self.activities
+            .iter()
+            .flat_map(|activities| activities.edges.iter().flatten().flatten())
+            .count()
+    }
 
-/// `T` は `Data` に収容される型。
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub enum Response<T: std::fmt::Debug> {
-    Data(T),
-    Errors(Vec<Error>),
+    /// 中身が `null` のものも含めたアクティビティの ID のうち、最も小さいものを返す。
+    pub fn min_activity_id(&self) -> Option<i64> {
+        self.activities
+            .iter()
+            .flat_map(|activities| activities.edges.iter().flatten().flatten())
+            .map(|edge| edge.annict_id)
+            .min()
+    }
+
+    /// 中身が `null` のアクティビティの ID を返す。
+    pub fn null_activity_ids(&self) -> Vec<i64> {
+        self.activities
+            .iter()
+            .flat_map(|activities| activities.edges.iter().flatten().flatten())
+            .filter(|edge| edge.item.is_none())
+            .map(|edge| edge.annict_id)
+            .collect()
+    }
 }
 
-fn opt_fmt<T: fmt::Debug>(value: &Option<T>, f: &mut Formatter) -> fmt::Result {
-    write!(f, "{:?}", value.as_ref().unwrap())
+impl MultipleRecord {
+    /// `null` を除いたレコードを返す。
+    pub fn into_records(self) -> impl Iterator<Item = Record> {
+        self.records
+            .and_then(|records| records.edges)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|edge| edge.node)
+    }
 }
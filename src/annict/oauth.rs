@@ -0,0 +1,260 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    routing::get,
+    Router,
+};
+use custom_debug::Debug;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use tokio::{net::TcpListener, sync::oneshot};
+
+use crate::{get_env, get_env_opt, store::Store, Result};
+
+use super::{AnnictClient, AnnictError, AnnictResult, DEFAULT_USER_AGENT};
+
+/// Annict の OAuth のエンドポイントがある URL。
+pub const DEFAULT_OAUTH_URL: &str = "https://annict.com";
+
+/// 連携用のリンクの有効期限。
+pub const LINK_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// コールバックを受け付けるパス。
+/// `redirect_uri` はこのパスを指すように設定する。
+pub const CALLBACK_PATH: &str = "/callback";
+
+/// Annict の OAuth (認可コードフロー) でアカウントの連携を行う。
+///
+/// [AnnictOAuth::start_link] で作った認可ページのリンクからユーザーが連携を許可すると、
+/// [AnnictOAuth::serve] で立てたサーバーにコールバックが来て、ユーザーが登録される。
+#[derive(Debug, Clone)]
+pub struct AnnictOAuth {
+    #[debug(skip)]
+    http: Client,
+    base_url: String,
+    /// 認可ページの URL。`base_url` から作り、作るときに検証しておく。
+    authorize_url: Url,
+    client_id: String,
+    #[debug(skip)]
+    client_secret: String,
+    redirect_uri: String,
+    #[debug(skip)]
+    pending: Arc<Mutex<HashMap<String, PendingLink>>>,
+}
+
+/// OAuth のエンドポイントがある URL `base_url` から、認可ページの URL を作る。
+fn authorize_url(base_url: &str) -> Result<Url> {
+    Ok(Url::parse(&format!("{}/oauth/authorize", base_url))?)
+}
+
+/// 認可を待っている連携。
+struct PendingLink {
+    user_id: u64,
+    guild_id: u64,
+    sender: oneshot::Sender<AnnictResult<String>>,
+}
+
+impl AnnictOAuth {
+    pub fn new(
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        redirect_uri: impl Into<String>,
+    ) -> Self {
+        Self {
+            http: Client::builder()
+                .user_agent(DEFAULT_USER_AGENT)
+                .build()
+                .unwrap_or_default(),
+            base_url: DEFAULT_OAUTH_URL.into(),
+            // 定数から作るので失敗しない
+            authorize_url: authorize_url(DEFAULT_OAUTH_URL).unwrap(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            redirect_uri: redirect_uri.into(),
+            pending: Default::default(),
+        }
+    }
+
+    /// 環境変数から作成する。
+    ///
+    /// - `ANNICT_CLIENT_ID`: アプリケーションの ID (必須)
+    /// - `ANNICT_CLIENT_SECRET`: アプリケーションのシークレット (必須)
+    /// - `ANNICT_REDIRECT_URI`: コールバック URL (必須)
+    /// - `ANNICT_OAUTH_URL`: OAuth のエンドポイントがある URL
+    pub fn from_env() -> Result<Self> {
+        let mut oauth = Self::new(
+            get_env("ANNICT_CLIENT_ID")?,
+            get_env("ANNICT_CLIENT_SECRET")?,
+            get_env("ANNICT_REDIRECT_URI")?,
+        );
+        if let Some(base_url) = get_env_opt("ANNICT_OAUTH_URL")? {
+            oauth = oauth.base_url(&base_url).map_err(|_| {
+                format!(
+                    "環境変数 `ANNICT_OAUTH_URL` (\"{}\") の形式が不正です",
+                    base_url
+                )
+            })?;
+        }
+        Ok(oauth)
+    }
+
+    /// OAuth のエンドポイントがある URL を設定する。
+    /// 既定値は [DEFAULT_OAUTH_URL]。URL として不正な場合はエラーを返す。
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Result<Self> {
+        let base_url = base_url.into();
+        self.authorize_url = authorize_url(&base_url)?;
+        self.base_url = base_url;
+        Ok(self)
+    }
+
+    /// Discord のユーザー `user_id` とサーバー `guild_id` についての連携を開始し、
+    /// 認可ページの URL と、連携した Annict のユーザー名を受け取る [oneshot::Receiver] を返す。
+    ///
+    /// [LINK_TTL] 以内に連携されなかった場合は、受け取り側を破棄すること。
+    pub fn start_link(
+        &self,
+        user_id: u64,
+        guild_id: u64,
+    ) -> (String, oneshot::Receiver<AnnictResult<String>>) {
+        let state = format!("{:032x}", rand::random::<u128>());
+        let (sender, receiver) = oneshot::channel();
+
+        let mut pending = self.pending.lock().unwrap();
+        // 受け取り側が破棄されたものは期限切れ
+        pending.retain(|_, link| !link.sender.is_closed());
+        pending.insert(
+            state.clone(),
+            PendingLink {
+                user_id,
+                guild_id,
+                sender,
+            },
+        );
+
+        let mut url = self.authorize_url.clone();
+        url.query_pairs_mut().extend_pairs([
+            ("client_id", self.client_id.as_str()),
+            ("response_type", "code"),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("scope", "read"),
+            ("state", state.as_str()),
+        ]);
+
+        (url.into(), receiver)
+    }
+
+    /// 認可コード `code` をアクセストークンと交換する。
+    pub(super) async fn exchange_code(&self, code: &str) -> AnnictResult<String> {
+        let response = self
+            .http
+            .post(format!("{}/oauth/token", self.base_url))
+            .form(&[
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.as_str()),
+                ("grant_type", "authorization_code"),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                ("code", code),
+            ])
+            .send()
+            .await?;
+        if matches!(
+            response.status(),
+            reqwest::StatusCode::BAD_REQUEST | reqwest::StatusCode::UNAUTHORIZED
+        ) {
+            // 認可コードが無効
+            return Err(AnnictError::Unauthorized);
+        }
+
+        let body = response.error_for_status()?.text().await?;
+        match serde_json::from_str::<TokenResponse>(&body) {
+            Ok(token) => Ok(token.access_token),
+            Err(source) => Err(AnnictError::Decode { body, source }),
+        }
+    }
+
+    /// コールバックを受け付ける [Router] を返す。
+    /// 連携されたユーザーは `annict` を使って調べ、`store` に登録する。
+    pub fn router(self, annict: AnnictClient, store: Arc<dyn Store>) -> Router {
+        Router::new()
+            .route(CALLBACK_PATH, get(callback))
+            .with_state((self, annict, store))
+    }
+
+    /// `addr` でコールバックを受け付けるサーバーを開始する。
+    pub async fn serve(
+        self,
+        annict: AnnictClient,
+        store: Arc<dyn Store>,
+        addr: SocketAddr,
+    ) -> Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        tracing::info!("OAuth のコールバックを {} で待ち受けます", addr);
+        Ok(axum::serve(listener, self.router(annict, store)).await?)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    #[debug(skip)]
+    access_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackParams {
+    state: Option<String>,
+    code: Option<String>,
+    error: Option<String>,
+}
+
+async fn callback(
+    State((oauth, annict, store)): State<(AnnictOAuth, AnnictClient, Arc<dyn Store>)>,
+    Query(params): Query<CallbackParams>,
+) -> (StatusCode, &'static str) {
+    let link = params
+        .state
+        .and_then(|state| oauth.pending.lock().unwrap().remove(&state))
+        .filter(|link| !link.sender.is_closed());
+    let Some(link) = link else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "リンクが無効か、有効期限が切れています。もう一度 Discord で連携を行ってください。",
+        );
+    };
+
+    let result = match (params.code, params.error) {
+        (Some(code), None) => match oauth.exchange_code(&code).await {
+            Ok(token) => {
+                super::register_user(&annict, store.as_ref(), &token, link.user_id, link.guild_id)
+                    .await
+            }
+            Err(e) => Err(e),
+        },
+        // 連携を拒否された
+        _ => Err(AnnictError::Unauthorized),
+    };
+
+    let response = match &result {
+        Ok(_) => (
+            StatusCode::OK,
+            "Annict アカウントとの連携が完了しました。Discord に戻ってください。",
+        ),
+        Err(AnnictError::Unauthorized) => (StatusCode::FORBIDDEN, "連携が許可されませんでした。"),
+        Err(e) => {
+            tracing::warn!("{}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "連携に失敗しました。時間を置いてもう一度お試しください。",
+            )
+        }
+    };
+    // 受け取り側が既に破棄されていても、連携自体は済んでいるので問題ない
+    let _ = link.sender.send(result);
+    response
+}
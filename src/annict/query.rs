@@ -1,181 +1,308 @@
-use std::sync::{
-    atomic::{AtomicUsize, Ordering},
-    LazyLock,
+use std::collections::HashMap;
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use graphql_client::{Error, GraphQLQuery, PathFragment, Response};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+
+use super::{
+    models::{
+        DateTime, Media, PageCursor, RatingState, SeasonName, StatusState, UserWithActivities,
+        WorkPage,
+    },
+    AnnictClient, AnnictError, AnnictResult,
 };
 
-use reqwest::Client;
-use serde::Serialize;
+/// ユーザーとそのアクティビティを取得するクエリ。
+/// `after` と `before` はどちらか一方だけを指定する。
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/annict/schema.graphql",
+    query_path = "src/annict/queries.graphql",
+    extern_enums("Media", "RatingState", "SeasonName", "StatusState"),
+    response_derives = "Debug, Clone"
+)]
+pub struct UserActivities;
 
-use crate::{annict::models::UserQuery, get_env, Result};
+/// トークンの持ち主のユーザーを取得するクエリ。
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/annict/schema.graphql",
+    query_path = "src/annict/queries.graphql",
+    response_derives = "Debug"
+)]
+pub struct Viewer;
 
-use super::models::{Response, UserWithActivities};
+/// ユーザー名からユーザーを取得するクエリ。
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/annict/schema.graphql",
+    query_path = "src/annict/queries.graphql",
+    response_derives = "Debug"
+)]
+pub struct UserByName;
 
-static CLIENT: LazyLock<Client> = LazyLock::new(Client::new);
+/// タイトルで作品を検索するクエリ。
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/annict/schema.graphql",
+    query_path = "src/annict/queries.graphql",
+    extern_enums("Media", "SeasonName"),
+    response_derives = "Debug"
+)]
+pub struct SearchWorks;
 
-pub(super) async fn with_after(
-    username: &str,
-    last: Option<i32>,
-    after: Option<&str>,
-) -> Result<Response<UserQuery<UserWithActivities>>> {
-    let query = Query {
-        query: r#"query User ($name: String!, $last: Int, $after: String) {
-                user(username: $name) { ...userFrag }
-            }
+/// Annict のユーザーの指定方法。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum UserRef<'a> {
+    /// ユーザー ID で指定する。ユーザー名が変わっても同じユーザーを指す。
+    Id(i64),
 
-            fragment userFrag on User {
-                username
-                name
-                activities(last: $last, after: $after) {
-                    edges { item { ...activityFrag } cursor }
-                    pageInfo { endCursor }
-                }
-                avatarUrl
-            }
+    /// ユーザー名で指定する。
+    Name(&'a str),
+}
 
-            fragment recordFrag on Record {
-                work { ...workFrag }
-                createdAt
-                comment
-                episode { number numberText title }
-                rating
-                ratingState
-            }
+/// 取得するアクティビティの範囲。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ActivityRange<'a> {
+    /// カーソルより後のもの。`None` の場合は最初から。
+    After(Option<&'a str>),
 
-            fragment activityFrag on ActivityItem {
-                __typename
-                ... on MultipleRecord {
-                    createdAt
-                    records { edges { node { ...recordFrag } } }
-                    work { ...workFrag }
-                }
-                ... on Record { ...recordFrag }
-                ... on Review {
-                    work { ...workFrag }
-                    body
-                    createdAt
-                    ratingAnimationState
-                    ratingCharacterState
-                    ratingMusicState
-                    ratingOverallState
-                    ratingStoryState
-                }
-                ... on Status { work { ...workFrag } createdAt state }
-            }
+    /// 最新のものから指定した件数。
+    Last(i32),
 
-            fragment workFrag on Work {
-                title
-            }
-        "#,
-        variables: UserVariableForAfter {
-            name: username,
-            last,
-            after,
-        },
-    };
+    /// カーソルより前のもののうち、新しいものから指定した件数。
+    Before(&'a str, i32),
+}
 
-    let res = post_query(query).await?;
-    Ok(serde_json::from_str(&res)?)
+impl<'a> ActivityRange<'a> {
+    /// `activities` の引数 `after`・`last`・`before` に渡す値を返す。
+    fn into_variables(self) -> (Option<&'a str>, Option<i32>, Option<&'a str>) {
+        match self {
+            ActivityRange::After(after) => (after, None, None),
+            ActivityRange::Last(last) => (None, Some(last), None),
+            ActivityRange::Before(before, last) => (None, Some(last), Some(before)),
+        }
+    }
 }
 
-pub(super) async fn query_with_before(
-    username: &str,
-    last: Option<i32>,
-    before: Option<&str>,
-) -> Result<Response<UserQuery<UserWithActivities>>> {
-    let query = Query {
-        query: r#"query User ($name: String!, $last: Int, $before: String) {
-                user(username: $name) { ...userFrag }
-            }
+/// `client` のトークンの持ち主のユーザー ID とユーザー名を返す。
+pub(super) async fn viewer(client: &AnnictClient) -> AnnictResult<(i64, String)> {
+    let query = Viewer::build_query(viewer::Variables);
+    let res: viewer::ResponseData = decode(&client.post_query(&query).await?)?;
+    let viewer = res.viewer.ok_or(AnnictError::NotFound)?;
+    Ok((viewer.annict_id, viewer.username))
+}
 
-            fragment userFrag on User {
-                username
-                name
-                activities(last: $last, before: $before) {
-                    edges { item { ...activityFrag } cursor }
-                    pageInfo { endCursor }
-                }
-                avatarUrl
-            }
+/// ユーザー名が `username` のユーザーのユーザー ID を返す。
+pub(super) async fn user_id(client: &AnnictClient, username: &str) -> AnnictResult<i64> {
+    let query = UserByName::build_query(user_by_name::Variables {
+        name: username.into(),
+    });
+    let res: user_by_name::ResponseData = decode(&client.post_query(&query).await?)?;
+    Ok(res.user.ok_or(AnnictError::NotFound)?.annict_id)
+}
 
-            fragment recordFrag on Record {
-                work { ...workFrag }
-                createdAt
-                comment
-                episode { number numberText title }
-                rating
-                ratingState
-            }
+/// ユーザー ID が `annict_id` のユーザーの、`node` で使う ID を返す。
+///
+/// Annict の ID は `"{型名}-{ID}"` を Base64 でエンコードしたものになっている。
+pub(super) fn user_node_id(annict_id: i64) -> String {
+    BASE64_STANDARD.encode(format!("User-{}", annict_id))
+}
 
-            fragment activityFrag on ActivityItem {
-                __typename
-                ... on MultipleRecord {
-                    createdAt
-                    records { edges { node { ...recordFrag } } }
-                    work { ...workFrag }
-                }
-                ... on Record { ...recordFrag }
-                ... on Review {
-                    work { ...workFrag }
-                    body
-                    createdAt
-                    ratingAnimationState
-                    ratingCharacterState
-                    ratingMusicState
-                    ratingOverallState
-                    ratingStoryState
-                }
-                ... on Status { work { ...workFrag } createdAt state }
-            }
+/// ユーザー `user` とその `range` の範囲のアクティビティを取得する。
+/// ID で指定した場合は [batch_activities] と同じく `node` で取得する。
+pub(super) async fn activities(
+    client: &AnnictClient,
+    user: UserRef<'_>,
+    range: ActivityRange<'_>,
+) -> AnnictResult<UserWithActivities> {
+    let UserRef::Name(username) = user else {
+        return batch_activities(client, &[(user, range)])
+            .await?
+            .pop()
+            .expect("ユーザー 1 人分の結果が返る");
+    };
+    let (after, last, before) = range.into_variables();
+    user_activities(
+        client,
+        user_activities::Variables {
+            name: username.into(),
+            last: last.map(Into::into),
+            after: after.map(Into::into),
+            before: before.map(Into::into),
+        },
+    )
+    .await
+}
+
+/// `users` の各 (ユーザー, 範囲) について、ユーザーとその範囲のアクティビティの取得を、
+/// エイリアスを使って 1 回のリクエストでまとめて行う。
+/// ID で指定されたユーザーは `node` で取得するので、ユーザー名が変わっていても取得できる。
+/// 結果は `users` と同じ順番で返す。
+///
+/// GraphQL のエラーがあっても、それがどのユーザーのものか分かる場合は、そのユーザーの結果だけをエラーにする。
+pub(super) async fn batch_activities(
+    client: &AnnictClient,
+    users: &[(UserRef<'_>, ActivityRange<'_>)],
+) -> AnnictResult<Vec<AnnictResult<UserWithActivities>>> {
+    const OPERATION_NAME: &str = "UserActivitiesBatch";
 
-            fragment workFrag on Work {
-                title
+    // 検証済みの UserActivities のフラグメントを使い回し、
+    // UserActivities の user と同じ形のものをエイリアスを付けて並べる
+    let mut parameters = vec![];
+    let mut selections = String::new();
+    let mut variables = Map::new();
+    for (i, (user, range)) in users.iter().enumerate() {
+        let fields = format!(
+            "...User activities(after: $after{0}, last: $last{0}, before: $before{0}) {{ ...ActivityConnection }}",
+            i
+        );
+        let range_parameters = format!("$after{0}: String, $last{0}: Int, $before{0}: String", i);
+        match user {
+            UserRef::Id(annict_id) => {
+                parameters.push(format!("$id{0}: ID!, {1}", i, range_parameters));
+                selections += &format!(
+                    "user{0}: node(id: $id{0}) {{ ... on User {{ {1} }} }}\n",
+                    i, fields
+                );
+                variables.insert(format!("id{}", i), Value::from(user_node_id(*annict_id)));
             }
-        "#,
-        variables: UserVariableForBefore {
-            name: username,
-            last,
-            before,
-        },
+            UserRef::Name(name) => {
+                parameters.push(format!("$name{0}: String!, {1}", i, range_parameters));
+                selections += &format!("user{0}: user(username: $name{0}) {{ {1} }}\n", i, fields);
+                variables.insert(format!("name{}", i), Value::from(*name));
+            }
+        }
+        let (after, last, before) = range.into_variables();
+        variables.insert(format!("after{}", i), Value::from(after));
+        variables.insert(format!("last{}", i), Value::from(last));
+        variables.insert(format!("before{}", i), Value::from(before));
+    }
+
+    let query = Query {
+        query: format!(
+            "{}\nquery {} ({}) {{ {} }}",
+            user_activities::QUERY,
+            OPERATION_NAME,
+            parameters.join(", "),
+            selections,
+        ),
+        variables,
+        operation_name: OPERATION_NAME,
     };
 
-    let res = post_query(query).await?;
-    Ok(serde_json::from_str(&res)?)
-}
+    let (mut res, errors): (HashMap<String, Option<UserWithActivities>>, _) =
+        decode_partial(&client.post_query(&query).await?)?;
 
-async fn post_query<Q: AsRef<str> + Serialize, V: Serialize>(query: Query<Q, V>) -> Result<String> {
-    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    // エラーの path の先頭はエイリアスになっているので、それでユーザーごとに分ける
+    let mut user_errors: HashMap<String, Vec<Error>> = HashMap::new();
+    let mut other_errors = vec![];
+    for error in errors {
+        match error.path.as_deref() {
+            Some([PathFragment::Key(alias), ..]) if res.contains_key(alias) => {
+                user_errors.entry(alias.clone()).or_default().push(error)
+            }
+            _ => other_errors.push(error),
+        }
+    }
+    // どのユーザーのものか分からないエラーは、全体のエラーとする
+    if !other_errors.is_empty() {
+        return Err(AnnictError::GraphQL {
+            errors: other_errors,
+        });
+    }
 
-    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    Ok((0..users.len())
+        .map(|i| {
+            let alias = format!("user{}", i);
+            if let Some(errors) = user_errors.remove(&alias) {
+                return Err(AnnictError::GraphQL { errors });
+            }
+            res.remove(&alias).flatten().ok_or(AnnictError::NotFound)
+        })
+        .collect())
+}
 
-    let request = CLIENT
-        .post("https://api.annict.com/graphql")
-        .bearer_auth(get_env("ANNICT_TOKEN")?)
-        .header("Content-Type", "application/json")
-        .json(&query);
-    tracing::trace!("request({}) = {:?}", count, request,);
+/// タイトルに `title` を含む作品を、`cursor` の位置から `per_page` 件取得する。
+pub(super) async fn search_works(
+    client: &AnnictClient,
+    title: &str,
+    cursor: &PageCursor,
+    per_page: i64,
+) -> AnnictResult<WorkPage> {
+    // 作品の型を活動の通知と共通にするため、レスポンスは生成された型ではなく WorkPage で受け取る
+    #[derive(serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Data {
+        search_works: Option<WorkPage>,
+    }
 
-    let response = request.send().await?.text().await?;
-    tracing::trace!("response({}) = {:?}", count, response);
+    let mut variables = search_works::Variables {
+        titles: Some(vec![title.into()]),
+        first: None,
+        after: None,
+        last: None,
+        before: None,
+    };
+    match cursor {
+        PageCursor::First => variables.first = Some(per_page),
+        PageCursor::After(after) => {
+            variables.first = Some(per_page);
+            variables.after = Some(after.clone());
+        }
+        PageCursor::Before(before) => {
+            variables.last = Some(per_page);
+            variables.before = Some(before.clone());
+        }
+    }
 
-    Ok(response)
+    let query = SearchWorks::build_query(variables);
+    let res: Data = decode(&client.post_query(&query).await?)?;
+    res.search_works.ok_or(AnnictError::NotFound)
 }
 
-#[derive(Debug, Serialize)]
-struct Query<Q: AsRef<str> + Serialize, V: Serialize> {
-    query: Q,
-    variables: V,
+async fn user_activities(
+    client: &AnnictClient,
+    variables: user_activities::Variables,
+) -> AnnictResult<UserWithActivities> {
+    let query = UserActivities::build_query(variables);
+    let res: user_activities::ResponseData = decode(&client.post_query(&query).await?)?;
+    res.user.ok_or(AnnictError::NotFound)
 }
 
-#[derive(Debug, Serialize)]
-struct UserVariableForAfter<S: AsRef<str>, T: AsRef<str>> {
-    name: S,
-    last: Option<i32>,
-    after: Option<T>,
+/// レスポンスの本文 `body` を解釈し、`data` の中身を返す。
+/// GraphQL のエラーが 1 つでもある場合はエラーにする。
+fn decode<T: DeserializeOwned>(body: &str) -> AnnictResult<T> {
+    match decode_partial(body)? {
+        (data, errors) if errors.is_empty() => Ok(data),
+        (_, errors) => Err(AnnictError::GraphQL { errors }),
+    }
+}
+
+/// レスポンスの本文 `body` を解釈し、`data` の中身と GraphQL のエラーを返す。
+/// `data` が無い場合はエラーにする。
+fn decode_partial<T: DeserializeOwned>(body: &str) -> AnnictResult<(T, Vec<Error>)> {
+    match serde_json::from_str(body) {
+        Ok(Response {
+            data: Some(data),
+            errors,
+            ..
+        }) => Ok((data, errors.unwrap_or_default())),
+        Ok(Response { errors, .. }) => Err(AnnictError::GraphQL {
+            errors: errors.unwrap_or_default(),
+        }),
+        Err(source) => Err(AnnictError::Decode {
+            body: body.into(),
+            source,
+        }),
+    }
 }
 
 #[derive(Debug, Serialize)]
-struct UserVariableForBefore<S: AsRef<str>, T: AsRef<str>> {
-    name: S,
-    last: Option<i32>,
-    before: Option<T>,
+#[serde(rename_all = "camelCase")]
+struct Query<Q: AsRef<str> + Serialize, V: Serialize> {
+    query: Q,
+    variables: V,
+    operation_name: &'static str,
 }
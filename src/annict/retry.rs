@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use super::AnnictError;
+
+/// 一時的な失敗に対する再試行の方針。
+///
+/// `n` 回目の再試行の前には `0..=min(base_delay * 2^n, max_delay)` から一様に選んだ時間だけ待つ。
+/// ただし、レート制限で `Retry-After` が指定された場合はその時間 (`max_delay` が上限) 待つ。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// 最初の試行に加えて行う再試行の最大回数。
+    pub max_retries: u32,
+
+    /// 待ち時間の基準値。
+    pub base_delay: Duration,
+
+    /// 待ち時間の上限。
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// 再試行を行わない方針。
+    pub const NEVER: Self = Self {
+        max_retries: 0,
+        base_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+
+    /// `retries` 回目 (0 始まり) の再試行までに待つ時間を返す。
+    /// `error` を受けて再試行すべきでない場合は `None` を返す。
+    pub(super) fn delay(&self, retries: u32, error: &AnnictError) -> Option<Duration> {
+        if retries >= self.max_retries || !error.is_transient() {
+            return None;
+        }
+
+        if let AnnictError::RateLimited {
+            retry_after: Some(retry_after),
+        } = error
+        {
+            return Some((*retry_after).min(self.max_delay));
+        }
+
+        let ceil = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retries))
+            .min(self.max_delay);
+        Some(Duration::from_millis(rand::random_range(
+            0..=ceil.as_millis() as u64,
+        )))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::{models::Subscriber, source, store::Store};
+
+use super::{ActivityItem, AnnictClient, AnnictError, RatingState, Record, StatusState, Work};
+
+/// Annict を取得元とする [source::ActivitySource]。
+///
+/// 新しいアクティビティを取得するときに購読者の情報を更新するので、保存先も持つ。
+#[derive(Debug, Clone)]
+pub struct AnnictSource {
+    client: AnnictClient,
+    store: Arc<dyn Store>,
+}
+
+impl AnnictSource {
+    pub fn new(client: AnnictClient, store: Arc<dyn Store>) -> Self {
+        Self { client, store }
+    }
+}
+
+#[async_trait]
+impl source::ActivitySource for AnnictSource {
+    fn name(&self) -> &str {
+        "Annict"
+    }
+
+    fn batch_size(&self) -> usize {
+        self.client.batch_size()
+    }
+
+    fn user_url(&self, username: &str) -> String {
+        format!("https://annict.com/@{}", username)
+    }
+
+    async fn new_activities(
+        &self,
+        accounts: &[Vec<&Subscriber>],
+    ) -> source::SourceResult<Vec<source::SourceResult<source::NewActivities>>> {
+        let results =
+            super::get_new_activities_batch(&self.client, self.store.as_ref(), accounts).await?;
+        Ok(results
+            .into_iter()
+            .map(|result| {
+                let (activities, end_cursor) = result?;
+                Ok(source::NewActivities {
+                    activities: activities.into_iter().map(Into::into).collect(),
+                    end_cursor,
+                })
+            })
+            .collect())
+    }
+
+    async fn recent_activities(
+        &self,
+        subscriber: &Subscriber,
+        count: i32,
+    ) -> source::SourceResult<source::RecentActivities> {
+        let (activities, unknown_ids) =
+            super::get_recent_activities(&self.client, subscriber, count).await?;
+        Ok(source::RecentActivities {
+            activities: activities.into_iter().map(Into::into).collect(),
+            unknown_ids,
+        })
+    }
+
+    async fn past_activities(
+        &self,
+        subscriber: &Subscriber,
+        history: &source::History,
+    ) -> source::SourceResult<Vec<source::Activity>> {
+        let activities = super::get_past_activities(&self.client, subscriber, history).await?;
+        Ok(activities.into_iter().map(Into::into).collect())
+    }
+}
+
+impl From<AnnictError> for source::SourceError {
+    fn from(value: AnnictError) -> Self {
+        match value {
+            AnnictError::NotFound => Self::NotFound,
+            e if e.is_transient() => Self::Transient(e.into()),
+            e => Self::Other(e.into()),
+        }
+    }
+}
+
+impl From<super::Activity> for source::Activity {
+    fn from(value: super::Activity) -> Self {
+        let created_at = value.item.created_at();
+        let items = match value.item {
+            ActivityItem::MultipleRecord(records) => records
+                .into_records()
+                .map(|record| source::ActivityItem::Record(record.into()))
+                .collect(),
+            ActivityItem::Record(record) => vec![source::ActivityItem::Record(record.into())],
+            ActivityItem::Review(review) => {
+                let ratings = [
+                    ("映像", review.rating_animation_state),
+                    ("キャラクター", review.rating_character_state),
+                    ("ストーリー", review.rating_story_state),
+                    ("音楽", review.rating_music_state),
+                ]
+                .into_iter()
+                .filter_map(|(name, rating)| Some((name.to_string(), rating?.into())))
+                .collect();
+                vec![source::ActivityItem::Review(source::Review {
+                    work: review.work.into(),
+                    rating: review.rating_overall_state.map(Into::into),
+                    ratings,
+                    body: review.body,
+                })]
+            }
+            ActivityItem::Status(status) => vec![source::ActivityItem::Status(source::Status {
+                work: status.work.into(),
+                state: status.state.into(),
+                label: status.state.to_string(),
+            })],
+        };
+        Self {
+            id: value.id,
+            created_at,
+            items,
+        }
+    }
+}
+
+impl From<Work> for source::Work {
+    fn from(value: Work) -> Self {
+        Self {
+            url: value.url(),
+            image_url: value.image_url().map(Into::into),
+            season: value.season(),
+            media: Some(value.media.to_string()),
+            official_site_url: value.official_site_url.filter(|url| !url.is_empty()),
+            title: value.title,
+        }
+    }
+}
+
+impl From<Record> for source::Record {
+    fn from(value: Record) -> Self {
+        let episode = source::Episode {
+            url: value.episode_url(),
+            number: value
+                .episode
+                .number_text
+                .clone()
+                .or_else(|| Some(format!("第{}話", value.episode.number?))),
+            title: value.episode.title.clone(),
+        };
+        Self {
+            progress: value.progress().map(|progress| format!("{} 話", progress)),
+            episode,
+            work: value.work.into(),
+            rating: value.rating_state.map(Into::into),
+            comment: value.comment,
+        }
+    }
+}
+
+impl From<RatingState> for source::Rating {
+    fn from(value: RatingState) -> Self {
+        match value {
+            RatingState::Bad => Self::Bad,
+            RatingState::Average => Self::Average,
+            RatingState::Good => Self::Good,
+            RatingState::Great => Self::Great,
+        }
+    }
+}
+
+impl From<StatusState> for source::StatusState {
+    fn from(value: StatusState) -> Self {
+        match value {
+            StatusState::NoState => Self::None,
+            StatusState::WannaWatch => Self::Planning,
+            StatusState::Watching => Self::Current,
+            StatusState::OnHold => Self::Paused,
+            StatusState::StopWatching => Self::Dropped,
+            StatusState::Watched => Self::Completed,
+        }
+    }
+}
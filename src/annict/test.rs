@@ -0,0 +1,767 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    Router,
+};
+use serde_json::json;
+use tokio::net::TcpListener;
+
+use crate::{
+    models::Subscriber,
+    source::{self, ActivitySource, History},
+    store::{MemoryStore, Store},
+    Result,
+};
+
+use super::{
+    client::parse_retry_after,
+    query::{self, ActivityRange, UserRef},
+    ActivityItem, AnnictClient, AnnictError, AnnictOAuth, AnnictSource, PageCursor, RetryPolicy,
+    CALLBACK_PATH,
+};
+
+const POLICY: RetryPolicy = RetryPolicy {
+    max_retries: 2,
+    base_delay: Duration::from_millis(1),
+    max_delay: Duration::from_millis(10),
+};
+
+/// スタブサーバーが返すレスポンスのステータス・ヘッダ・本文。
+type StubResponse = (
+    StatusCode,
+    &'static [(&'static str, &'static str)],
+    &'static str,
+);
+
+/// どのパスへのリクエストにも `responses` を先頭から順に返すスタブサーバーを立て、
+/// その URL と受け付けたリクエストの数を返す。
+async fn stub_server(responses: Vec<StubResponse>) -> (String, Arc<AtomicUsize>) {
+    let responses = Arc::new(Mutex::new(VecDeque::from(responses)));
+    let count = Arc::new(AtomicUsize::new(0));
+
+    let app = Router::new().fallback({
+        let count = count.clone();
+        move || async move {
+            count.fetch_add(1, Ordering::SeqCst);
+            let (status, headers, body) = responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .expect("想定より多くのリクエストを受け付けた");
+            let mut header_map = HeaderMap::new();
+            for (name, value) in headers {
+                header_map.insert(
+                    HeaderName::from_static(name),
+                    HeaderValue::from_static(value),
+                );
+            }
+            (status, header_map, body)
+        }
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    (url, count)
+}
+
+fn client(url: String) -> Result<AnnictClient> {
+    AnnictClient::builder("token")
+        .endpoint(format!("{}/graphql", url))
+        .retry_policy(POLICY)
+        .build()
+}
+
+#[tokio::test]
+async fn retry_server_error_test() -> Result<()> {
+    let (endpoint, count) = stub_server(vec![
+        (StatusCode::SERVICE_UNAVAILABLE, &[], ""),
+        (StatusCode::BAD_GATEWAY, &[], ""),
+        (StatusCode::OK, &[], r#"{"data":{}}"#),
+    ])
+    .await;
+
+    let body = client(endpoint)?.post_query(&json!({})).await?;
+    assert_eq!(body, r#"{"data":{}}"#);
+    assert_eq!(count.load(Ordering::SeqCst), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn retry_exhausted_test() -> Result<()> {
+    let (endpoint, count) = stub_server(vec![
+        (StatusCode::SERVICE_UNAVAILABLE, &[], ""),
+        (StatusCode::SERVICE_UNAVAILABLE, &[], ""),
+        (StatusCode::SERVICE_UNAVAILABLE, &[], ""),
+    ])
+    .await;
+
+    let err = client(endpoint)?.post_query(&json!({})).await.unwrap_err();
+    assert!(matches!(err, AnnictError::Transport(_)));
+    assert_eq!(count.load(Ordering::SeqCst), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn retry_after_test() -> Result<()> {
+    let (endpoint, count) = stub_server(vec![
+        (StatusCode::TOO_MANY_REQUESTS, &[("retry-after", "1")], ""),
+        (StatusCode::OK, &[], r#"{"data":{}}"#),
+    ])
+    .await;
+
+    // Retry-After が max_delay より長い場合は max_delay だけ待つ
+    let start = Instant::now();
+    client(endpoint)?.post_query(&json!({})).await?;
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[test]
+fn parse_retry_after_test() {
+    assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+    assert_eq!(
+        parse_retry_after("Sun, 06 Nov 1994 08:49:37 GMT"),
+        Some(Duration::ZERO)
+    );
+    let date = (chrono::Utc::now() + chrono::TimeDelta::seconds(60)).to_rfc2822();
+    let delay = parse_retry_after(&date).unwrap();
+    assert!(delay > Duration::from_secs(50) && delay <= Duration::from_secs(60));
+    assert_eq!(parse_retry_after("soon"), None);
+}
+
+#[tokio::test]
+async fn no_retry_test() -> Result<()> {
+    let (endpoint, count) = stub_server(vec![
+        (StatusCode::UNAUTHORIZED, &[], ""),
+        (StatusCode::NOT_FOUND, &[], ""),
+    ])
+    .await;
+
+    let client = client(endpoint)?;
+    let err = client.post_query(&json!({})).await.unwrap_err();
+    assert!(matches!(err, AnnictError::Unauthorized));
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+
+    let err = client.post_query(&json!({})).await.unwrap_err();
+    assert!(matches!(err, AnnictError::Transport(_)));
+    assert!(!err.is_transient());
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn batch_activities_test() -> Result<()> {
+    let (endpoint, count) = stub_server(vec![(
+        StatusCode::OK,
+        &[],
+        r#"{"data":{
+            "user1":null,
+            "user0":{
+                "annictId":100,
+                "username":"kei519",
+                "name":"kei",
+                "avatarUrl":null,
+                "activities":{
+                    "edges":[{
+                        "annictId":1000,
+                        "item":{
+                            "__typename":"Status",
+                            "work":{
+                                "annictId":1,
+                                "title":"タイトル",
+                                "image":{"recommendedImageUrl":""},
+                                "seasonName":"AUTUMN",
+                                "seasonYear":2024,
+                                "media":"TV",
+                                "episodesCount":12,
+                                "officialSiteUrl":null
+                            },
+                            "createdAt":"2024-10-16T12:00:00Z",
+                            "state":"WATCHING"
+                        },
+                        "cursor":"cursor"
+                    }],
+                    "pageInfo":{"startCursor":null,"endCursor":"cursor"}
+                }
+            }
+        }}"#,
+    )])
+    .await;
+
+    let users = query::batch_activities(
+        &client(endpoint)?,
+        &[
+            (UserRef::Name("kei519"), ActivityRange::After(None)),
+            (UserRef::Id(200), ActivityRange::Last(50)),
+        ],
+    )
+    .await?;
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert_eq!(users.len(), 2);
+
+    assert!(matches!(users[1], Err(AnnictError::NotFound)));
+
+    let user = users.into_iter().next().unwrap()?;
+    assert_eq!(user.user.annict_id, 100);
+    assert_eq!(user.user.username, "kei519");
+    let (edges, end_cursor) = user.into_activities();
+    assert_eq!(edges.len(), 1);
+    assert_eq!(edges[0].id, 1000);
+    let ActivityItem::Status(status) = &edges[0].item else {
+        panic!("unexpected activity {:?}", edges[0].item);
+    };
+    assert_eq!(status.work.url(), "https://annict.com/works/1");
+    assert_eq!(status.work.season().as_deref(), Some("2024年秋"));
+    assert!(status.work.image_url().is_none());
+    assert_eq!(end_cursor.as_deref(), Some("cursor"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn batch_activities_errors_test() -> Result<()> {
+    // エラーがどのユーザーのものか分かる場合は、そのユーザーだけをエラーにする
+    let (endpoint, _) = stub_server(vec![
+        (
+            StatusCode::OK,
+            &[],
+            r#"{
+                "data":{"user0":null,"user1":null},
+                "errors":[{"message":"Not found","path":["user0"]}]
+            }"#,
+        ),
+        (
+            StatusCode::OK,
+            &[],
+            r#"{
+                "data":{"user0":null,"user1":null},
+                "errors":[{"message":"Internal error"}]
+            }"#,
+        ),
+    ])
+    .await;
+    let client = client(endpoint)?;
+    let users = [
+        (UserRef::Id(100), ActivityRange::Last(50)),
+        (UserRef::Name("kei519"), ActivityRange::After(None)),
+    ];
+
+    let results = query::batch_activities(&client, &users).await?;
+    assert!(matches!(&results[0], Err(AnnictError::GraphQL { errors }) if errors.len() == 1));
+    assert!(matches!(results[1], Err(AnnictError::NotFound)));
+
+    // 分からない場合は全体をエラーにする
+    let result = query::batch_activities(&client, &users).await;
+    assert!(matches!(result, Err(AnnictError::GraphQL { .. })));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn new_activities_test() -> Result<()> {
+    let (endpoint, _) = stub_server(vec![(
+        StatusCode::OK,
+        &[],
+        r#"{"data":{
+            "user0":{
+                "annictId":100,
+                "username":"kei519",
+                "name":"kei",
+                "avatarUrl":null,
+                "activities":{
+                    "edges":[{
+                        "annictId":1000,
+                        "item":{
+                            "__typename":"Status",
+                            "work":{"annictId":1,"title":"タイトル","image":null,"seasonName":null,"seasonYear":null,"media":"TV","episodesCount":12,"officialSiteUrl":null},
+                            "createdAt":"2024-10-16T12:00:00Z",
+                            "state":"WATCHING"
+                        },
+                        "cursor":"cursor"
+                    }],
+                    "pageInfo":{"startCursor":null,"endCursor":"cursor"}
+                }
+            }
+        }}"#,
+    )])
+    .await;
+
+    // 同じ Annict ユーザーを 2 つのサーバーで購読していて、ユーザー名が変わっている
+    let store = MemoryStore::new();
+    let first = store
+        .insert_or_update_subscriber(1, 1, 100, "kei", None, None, None)
+        .await?;
+    let second = store
+        .insert_or_update_subscriber(1, 2, 100, "kei", None, None, None)
+        .await?;
+
+    let (activities, end_cursor) =
+        super::get_new_activities(&client(endpoint)?, &store, &[&first, &second]).await?;
+    let ids: Vec<_> = activities.iter().map(|activity| activity.id).collect();
+    assert_eq!(ids, [1000]);
+    assert_eq!(end_cursor.as_deref(), Some("cursor"));
+
+    // どちらの購読者もユーザー名が更新されるが、取得したところは送信待ちに加えるまで変わらない
+    for subscriber in store.get_subscribers().await? {
+        assert_eq!(subscriber.annict_name, "kei519");
+        assert!(subscriber.end_cursor.is_none());
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn new_activities_latest_test() -> Result<()> {
+    // 送信待ちに加え終えたところが最新のページに含まれていれば、遡らずに 1 回の取得で済ませる
+    let (endpoint, count) = stub_server(vec![(
+        StatusCode::OK,
+        &[],
+        r#"{"data":{
+            "user0":{
+                "annictId":100,
+                "username":"kei519",
+                "name":"kei",
+                "avatarUrl":null,
+                "activities":{
+                    "edges":[{
+                        "annictId":999,
+                        "item":null,
+                        "cursor":"cursor999"
+                    },{
+                        "annictId":1000,
+                        "item":{
+                            "__typename":"Status",
+                            "work":{"annictId":1,"title":"タイトル","image":null,"seasonName":null,"seasonYear":null,"media":"TV","episodesCount":12,"officialSiteUrl":null},
+                            "createdAt":"2024-10-16T12:00:00Z",
+                            "state":"WATCHING"
+                        },
+                        "cursor":"cursor1000"
+                    }],
+                    "pageInfo":{"endCursor":"cursor1000"}
+                }
+            }
+        }}"#,
+    )])
+    .await;
+
+    let store = MemoryStore::new();
+    let subscriber = store
+        .insert_or_update_subscriber(1, 1, 100, "kei519", Some("old"), Some(999), None)
+        .await?;
+
+    let (activities, end_cursor) =
+        super::get_new_activities(&client(endpoint)?, &store, &[&subscriber]).await?;
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    let ids: Vec<_> = activities.iter().map(|activity| activity.id).collect();
+    assert_eq!(ids, [1000]);
+    assert_eq!(end_cursor.as_deref(), Some("cursor1000"));
+
+    Ok(())
+}
+
+/// ID が `ids` のアクティビティを古い順に並べた `user0` のページの本文を返す。
+fn activities_page(ids: std::ops::RangeInclusive<i64>) -> &'static str {
+    let edges: Vec<_> = ids
+        .map(|id| {
+            json!({
+                "annictId": id,
+                "item": {
+                    "__typename": "Status",
+                    "work": {"annictId":1,"title":"タイトル","image":null,"seasonName":null,"seasonYear":null,"media":"TV","episodesCount":12,"officialSiteUrl":null},
+                    "createdAt": "2024-10-16T12:00:00Z",
+                    "state": "WATCHING"
+                },
+                "cursor": format!("cursor{}", id)
+            })
+        })
+        .collect();
+    let end_cursor = edges.last().map(|edge| edge["cursor"].clone());
+    json!({"data": {"user0": {
+        "annictId": 100,
+        "username": "kei519",
+        "name": "kei",
+        "avatarUrl": null,
+        "activities": {"edges": edges, "pageInfo": {"endCursor": end_cursor}}
+    }}})
+    .to_string()
+    .leak()
+}
+
+#[tokio::test]
+async fn new_activities_backward_test() -> Result<()> {
+    // 最新のページに送信待ちに加え終えたところが含まれていなければ、50 件ずつ遡る
+    let (endpoint, count) = stub_server(vec![
+        (StatusCode::OK, &[], activities_page(1051..=1100)),
+        (StatusCode::OK, &[], activities_page(1001..=1050)),
+        (StatusCode::OK, &[], activities_page(990..=1000)),
+    ])
+    .await;
+
+    let store = MemoryStore::new();
+    let subscriber = store
+        .insert_or_update_subscriber(1, 1, 100, "kei519", Some("old"), Some(1000), None)
+        .await?;
+
+    let (activities, end_cursor) =
+        super::get_new_activities(&client(endpoint)?, &store, &[&subscriber]).await?;
+    assert_eq!(count.load(Ordering::SeqCst), 3);
+    let ids: Vec<_> = activities.iter().map(|activity| activity.id).collect();
+    assert_eq!(ids, (1001..=1100).collect::<Vec<_>>());
+    assert_eq!(end_cursor.as_deref(), Some("cursor1100"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn search_works_test() -> Result<()> {
+    let (url, count) = stub_server(vec![(
+        StatusCode::OK,
+        &[],
+        r#"{"data":{"searchWorks":{
+            "edges":[
+                {"node":{
+                    "annictId":2,
+                    "title":"タイトル",
+                    "image":null,
+                    "seasonName":null,
+                    "seasonYear":2024,
+                    "media":"MOVIE",
+                    "episodesCount":0,
+                    "officialSiteUrl":null
+                }},
+                null,
+                {"node":null}
+            ],
+            "pageInfo":{
+                "hasNextPage":true,
+                "hasPreviousPage":false,
+                "startCursor":"start",
+                "endCursor":"end"
+            }
+        }}}"#,
+    )])
+    .await;
+
+    let page = super::search_works(&client(url)?, "タイトル", &PageCursor::First, 5).await?;
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert_eq!(page.works.len(), 1);
+    assert_eq!(page.works[0].url(), "https://annict.com/works/2");
+    assert_eq!(page.works[0].season().as_deref(), Some("2024年"));
+    assert!(page.page_info.has_next_page);
+    assert_eq!(page.page_info.end_cursor.as_deref(), Some("end"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn activity_source_test() -> Result<()> {
+    let (url, _) = stub_server(vec![(
+        StatusCode::OK,
+        &[],
+        r##"{"data":{"user0":{
+            "annictId":100,
+            "username":"kei519",
+            "name":"kei",
+            "avatarUrl":null,
+            "activities":{
+                "edges":[{
+                    "annictId":1000,
+                    "item":{
+                        "__typename":"MultipleRecord",
+                        "createdAt":"2024-10-16T12:00:00Z",
+                        "records":{"edges":[
+                            {"node":{
+                                "work":{"annictId":1,"title":"タイトル","image":null,"seasonName":null,"seasonYear":null,"media":"TV","episodesCount":12,"officialSiteUrl":""},
+                                "createdAt":"2024-10-16T12:00:00Z",
+                                "comment":"",
+                                "episode":{"annictId":10,"number":1,"numberText":null,"title":"サブタイトル"},
+                                "ratingState":"GOOD"
+                            }},
+                            null,
+                            {"node":{
+                                "work":{"annictId":1,"title":"タイトル","image":null,"seasonName":null,"seasonYear":null,"media":"TV","episodesCount":12,"officialSiteUrl":""},
+                                "createdAt":"2024-10-16T12:00:00Z",
+                                "comment":null,
+                                "episode":{"annictId":11,"number":2,"numberText":"#2","title":null},
+                                "ratingState":null
+                            }}
+                        ]},
+                        "work":{"annictId":1,"title":"タイトル","image":null,"seasonName":null,"seasonYear":null,"media":"TV","episodesCount":12,"officialSiteUrl":""}
+                    },
+                    "cursor":"cursor1"
+                },{
+                    "annictId":1001,
+                    "item":{
+                        "__typename":"Review",
+                        "work":{"annictId":1,"title":"タイトル","image":null,"seasonName":null,"seasonYear":null,"media":"TV","episodesCount":12,"officialSiteUrl":"https://example.com"},
+                        "body":"感想",
+                        "createdAt":"2024-10-17T12:00:00Z",
+                        "ratingAnimationState":"GREAT",
+                        "ratingCharacterState":null,
+                        "ratingMusicState":"BAD",
+                        "ratingOverallState":"AVERAGE",
+                        "ratingStoryState":null
+                    },
+                    "cursor":"cursor2"
+                },{
+                    "annictId":1002,
+                    "item":null,
+                    "cursor":"cursor3"
+                }],
+                "pageInfo":{"endCursor":"cursor3"}
+            }
+        }}}"##,
+    )])
+    .await;
+
+    let subscriber = Subscriber {
+        id: 1,
+        user_id: 1,
+        guild_id: 1,
+        annict_name: "kei519".into(),
+        end_cursor: None,
+        access_token: None,
+        annict_id: Some(100),
+        last_activity_id: None,
+    };
+    let source: &dyn ActivitySource =
+        &AnnictSource::new(client(url)?, Arc::new(MemoryStore::new()));
+    assert_eq!(source.name(), "Annict");
+    assert_eq!(source.user_url("kei519"), "https://annict.com/@kei519");
+    let recent = source.recent_activities(&subscriber, 3).await?;
+    // 中身が null のアクティビティは ID だけを返す
+    assert_eq!(recent.len(), 3);
+    assert_eq!(recent.unknown_ids, [1002]);
+    assert!(recent.contains(1002));
+    assert_eq!(recent.min_id(), Some(1000));
+    let activities = &recent.activities;
+    assert_eq!(activities.len(), 2);
+
+    // まとめて記録したものは 1 つのアクティビティの複数の中身になる
+    assert_eq!(activities[0].id, 1000);
+    let [source::ActivityItem::Record(first), source::ActivityItem::Record(second)] =
+        &activities[0].items[..]
+    else {
+        panic!("unexpected items {:?}", activities[0].items);
+    };
+    assert_eq!(first.work.url, "https://annict.com/works/1");
+    assert!(first.work.official_site_url.is_none());
+    assert_eq!(first.episode.url, "https://annict.com/works/1/episodes/10");
+    assert_eq!(first.episode.number.as_deref(), Some("第1話"));
+    assert_eq!(first.progress.as_deref(), Some("1/12 話"));
+    assert_eq!(first.rating, Some(source::Rating::Good));
+    assert_eq!(second.episode.number.as_deref(), Some("#2"));
+    assert!(second.rating.is_none());
+
+    assert_eq!(activities[1].id, 1001);
+    let [source::ActivityItem::Review(review)] = &activities[1].items[..] else {
+        panic!("unexpected items {:?}", activities[1].items);
+    };
+    assert_eq!(review.rating, Some(source::Rating::Average));
+    assert_eq!(
+        review.ratings,
+        [
+            ("映像".to_string(), source::Rating::Great),
+            ("音楽".to_string(), source::Rating::Bad),
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn past_activities_test() -> Result<()> {
+    // 新しいものから 1 ページずつ遡り、範囲外のアクティビティに達したら止める
+    // 中身が null のアクティビティだけのページがあっても遡り続ける
+    let (url, count) = stub_server(vec![
+        (
+            StatusCode::OK,
+            &[],
+            r#"{"data":{"user0":{
+            "annictId":100,
+            "username":"kei519",
+            "name":"kei",
+            "avatarUrl":null,
+            "activities":{
+                "edges":[{
+                    "annictId":1002,
+                    "item":{
+                        "__typename":"Status",
+                        "work":{"annictId":1,"title":"タイトル","image":null,"seasonName":null,"seasonYear":null,"media":"TV","episodesCount":12,"officialSiteUrl":null},
+                        "createdAt":"2024-10-02T00:00:00Z",
+                        "state":"WATCHING"
+                    },
+                    "cursor":"cursor1002"
+                },{
+                    "annictId":1003,
+                    "item":{
+                        "__typename":"Status",
+                        "work":{"annictId":1,"title":"タイトル","image":null,"seasonName":null,"seasonYear":null,"media":"TV","episodesCount":12,"officialSiteUrl":null},
+                        "createdAt":"2024-10-03T00:00:00Z",
+                        "state":"WATCHING"
+                    },
+                    "cursor":"cursor1003"
+                }],
+                "pageInfo":{"endCursor":null}
+            }
+        }}}"#,
+        ),
+        (
+            StatusCode::OK,
+            &[],
+            r#"{"data":{"user0":{
+            "annictId":100,
+            "username":"kei519",
+            "name":"kei",
+            "avatarUrl":null,
+            "activities":{
+                "edges":[{"annictId":1001,"item":null,"cursor":"cursor1001"}],
+                "pageInfo":{"endCursor":null}
+            }
+        }}}"#,
+        ),
+        (
+            StatusCode::OK,
+            &[],
+            r#"{"data":{"user0":{
+            "annictId":100,
+            "username":"kei519",
+            "name":"kei",
+            "avatarUrl":null,
+            "activities":{
+                "edges":[{
+                    "annictId":999,
+                    "item":{
+                        "__typename":"Status",
+                        "work":{"annictId":1,"title":"タイトル","image":null,"seasonName":null,"seasonYear":null,"media":"TV","episodesCount":12,"officialSiteUrl":null},
+                        "createdAt":"2024-09-30T00:00:00Z",
+                        "state":"WATCHING"
+                    },
+                    "cursor":"cursor999"
+                },{
+                    "annictId":1000,
+                    "item":{
+                        "__typename":"Status",
+                        "work":{"annictId":1,"title":"タイトル","image":null,"seasonName":null,"seasonYear":null,"media":"TV","episodesCount":12,"officialSiteUrl":null},
+                        "createdAt":"2024-10-01T00:00:00Z",
+                        "state":"WATCHING"
+                    },
+                    "cursor":"cursor1000"
+                }],
+                "pageInfo":{"endCursor":null}
+            }
+        }}}"#,
+        ),
+    ])
+    .await;
+
+    let subscriber = Subscriber {
+        id: 1,
+        user_id: 1,
+        guild_id: 1,
+        annict_name: "kei519".into(),
+        end_cursor: None,
+        access_token: None,
+        annict_id: Some(100),
+        last_activity_id: None,
+    };
+    let since = "2024-10-01T00:00:00Z".parse::<chrono::DateTime<chrono::Utc>>()?;
+    let history = History::Since(since.into());
+    let activities = super::get_past_activities(&client(url)?, &subscriber, &history).await?;
+    assert_eq!(count.load(Ordering::SeqCst), 3);
+    let ids: Vec<_> = activities.iter().map(|activity| activity.id).collect();
+    assert_eq!(ids, [1000, 1002, 1003]);
+
+    Ok(())
+}
+
+#[test]
+fn user_node_id_test() {
+    assert_eq!(query::user_node_id(1), "VXNlci0x");
+    assert_eq!(query::user_node_id(12345), "VXNlci0xMjM0NQ==");
+}
+
+fn oauth(url: String) -> Result<AnnictOAuth> {
+    AnnictOAuth::new("id", "secret", "http://localhost/callback").base_url(url)
+}
+
+#[tokio::test]
+async fn exchange_code_test() -> Result<()> {
+    let (url, count) = stub_server(vec![
+        (
+            StatusCode::OK,
+            &[],
+            r#"{"access_token":"access","token_type":"bearer","scope":"read"}"#,
+        ),
+        (
+            StatusCode::UNAUTHORIZED,
+            &[],
+            r#"{"error":"invalid_grant"}"#,
+        ),
+    ])
+    .await;
+
+    let oauth = oauth(url)?;
+    assert_eq!(oauth.exchange_code("code").await?, "access");
+    let err = oauth.exchange_code("code").await.unwrap_err();
+    assert!(matches!(err, AnnictError::Unauthorized));
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn callback_test() -> Result<()> {
+    // コールバックではトークンの交換まで進まないので、どこにもつながらなくてよい
+    let oauth = oauth("http://127.0.0.1:1".into())?;
+    assert!(
+        AnnictOAuth::new("id", "secret", "http://localhost/callback")
+            .base_url("not a url")
+            .is_err()
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let callback = format!("http://{}{}", listener.local_addr()?, CALLBACK_PATH);
+    let app = oauth.clone().router(
+        client("http://127.0.0.1:1".into())?,
+        Arc::new(MemoryStore::new()),
+    );
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let (authorize_url, receiver) = oauth.start_link(1, 2);
+    let authorize_url = reqwest::Url::parse(&authorize_url)?;
+    let (_, state) = authorize_url
+        .query_pairs()
+        .find(|(key, _)| key == "state")
+        .expect("state が含まれていない");
+
+    // 知らない state は受け付けない
+    let response = reqwest::get(format!("{}?state=unknown&code=code", callback)).await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // 連携を拒否された
+    let response =
+        reqwest::get(format!("{}?state={}&error=access_denied", callback, state)).await?;
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+    assert!(matches!(receiver.await?, Err(AnnictError::Unauthorized)));
+
+    // 同じ state は 2 度使えない
+    let response = reqwest::get(format!("{}?state={}&code=code", callback, state)).await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    Ok(())
+}
@@ -1,116 +1,955 @@
-use chrono::{DateTime, Local, TimeZone};
+use std::{collections::HashMap, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+#[cfg(feature = "sqlite")]
+use diesel::{
+    connection::SimpleConnection,
+    r2d2::{self, CustomizeConnection},
+    SqliteConnection,
+};
 use diesel::{
-    query_dsl::methods::{FilterDsl, SelectDsl},
-    Connection, ExpressionMethods, PgConnection, QueryResult, RunQueryDsl,
+    dsl::exists,
+    expression_methods::EscapeExpressionMethods,
+    r2d2::{ConnectionManager, Pool, R2D2Connection},
+    select, Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, QueryResult,
+    RunQueryDsl, TextExpressionMethods,
 };
+use diesel_migrations::{EmbeddedMigrations, MigrationHarness};
+use tokio::task;
 
 use crate::{
-    discord::NotifyFlag,
-    get_env,
-    models::{Channel, NewSubscriber, Subscriber},
-    schema::*,
+    discord::{DeletedAction, NotifyFlag},
+    get_env, get_env_opt,
+    models::{
+        Channel, DeliveredActivity, Guild, MessageIds, NewNotification, Notification,
+        OutboxActivity, Subscriber,
+    },
+    parse_duration,
+    snapshot::Snapshot,
+    store::{NotificationFilter, Store},
     Result,
 };
 
 #[cfg(test)]
 mod test;
 
-pub fn connect() -> Result<PgConnection> {
-    Ok(PgConnection::establish(&get_env("DATABASE_URL")?)?)
+const MIGRATIONS: EmbeddedMigrations = diesel_migrations::embed_migrations!("./migrations");
+
+#[cfg(feature = "sqlite")]
+const SQLITE_MIGRATIONS: EmbeddedMigrations =
+    diesel_migrations::embed_migrations!("./migrations_sqlite");
+
+/// SQLite を使う場合の `DATABASE_URL` の先頭。続けてファイルのパス (または `:memory:`) を書く。
+/// `:memory:` の場合は 1 つのコネクションを閉じずに使い続け、最大の接続数などの設定は無視する。
+pub const SQLITE_URL_PREFIX: &str = "sqlite://";
+
+/// データベースのコネクションプール。複製しても同じプールを共有する。
+///
+/// どのデータベースを使うかは `DATABASE_URL` で決まる。
+/// SQLite は `sqlite` フィーチャーを有効にした場合に使える。
+#[derive(Debug, Clone)]
+pub enum DbPool {
+    Postgres(Pool<ConnectionManager<PgConnection>>),
+    #[cfg(feature = "sqlite")]
+    Sqlite(Pool<ConnectionManager<SqliteConnection>>),
 }
 
-pub fn insert_or_update_channel(
-    conn: &mut PgConnection,
-    guild_id: u64,
-    channel_id: u64,
-    notify_flag: NotifyFlag,
-) -> QueryResult<Channel> {
-    let new_chan = Channel {
-        channel_id: channel_id as _,
-        guild_id: guild_id as _,
-        notify_flag,
+/// プールの最大の接続数の既定値。
+pub const DEFAULT_POOL_SIZE: u32 = 10;
+
+/// プールからコネクションを借りるまで待つ時間の既定値。
+pub const DEFAULT_POOL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 使われていないコネクションを閉じるまでの時間の既定値。
+pub const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// 環境変数からコネクションプールを作成する。
+///
+/// - `DATABASE_URL`: 接続先 (必須)。`sqlite://` で始まる場合は SQLite のファイルを開く
+/// - `DATABASE_POOL_SIZE`: 最大の接続数
+/// - `DATABASE_POOL_TIMEOUT`: コネクションを借りるまで待つ時間
+/// - `DATABASE_POOL_IDLE_TIMEOUT`: 使われていないコネクションを閉じるまでの時間
+pub fn pool_from_env() -> Result<DbPool> {
+    let size = match get_env_opt("DATABASE_POOL_SIZE")? {
+        Some(size) => size.parse().map_err(|_| {
+            format!(
+                "環境変数 `DATABASE_POOL_SIZE` (\"{}\") の形式が不正です",
+                size
+            )
+        })?,
+        None => DEFAULT_POOL_SIZE,
+    };
+    let timeout = get_duration_env("DATABASE_POOL_TIMEOUT", DEFAULT_POOL_TIMEOUT)?;
+    let idle_timeout = get_duration_env("DATABASE_POOL_IDLE_TIMEOUT", DEFAULT_POOL_IDLE_TIMEOUT)?;
+    tracing::info!(
+        "コネクションプール: 最大接続数 {}, 待ち時間 {} 秒",
+        size,
+        timeout.as_secs()
+    );
+
+    let url = get_env("DATABASE_URL")?;
+    let Some(path) = url.strip_prefix(SQLITE_URL_PREFIX) else {
+        return Ok(DbPool::Postgres(
+            Pool::builder()
+                .max_size(size)
+                .connection_timeout(timeout)
+                .idle_timeout(Some(idle_timeout))
+                .build(ConnectionManager::new(url))?,
+        ));
     };
-    diesel::insert_into(channels::table)
-        .values(new_chan)
-        .on_conflict((channels::guild_id, channels::channel_id))
-        .do_update()
-        .set(channels::notify_flag.eq(notify_flag.bits()))
-        .get_result(conn)
+
+    #[cfg(feature = "sqlite")]
+    {
+        tracing::info!("SQLite のデータベース {} を使います", path);
+        let builder = Pool::builder()
+            .connection_timeout(timeout)
+            .connection_customizer(Box::new(SqlitePragmas));
+        let builder = if path == ":memory:" {
+            // インメモリのデータベースはコネクションごとに別のものになり、閉じると消えるので、
+            // 1 つのコネクションを閉じずに使い続ける
+            builder.max_size(1).idle_timeout(None).max_lifetime(None)
+        } else {
+            builder.max_size(size).idle_timeout(Some(idle_timeout))
+        };
+        Ok(DbPool::Sqlite(builder.build(ConnectionManager::new(path))?))
+    }
+    #[cfg(not(feature = "sqlite"))]
+    {
+        let _ = path;
+        Err("SQLite を使うには `sqlite` フィーチャーを有効にしてビルドしてください".into())
+    }
 }
 
-pub fn remove_channel(
-    conn: &mut PgConnection,
-    guild_id: u64,
-    channel_id: u64,
-) -> QueryResult<bool> {
-    let num_deleted = diesel::delete(channels::table)
-        .filter(channels::channel_id.eq(channel_id as i64))
-        .filter(channels::guild_id.eq(guild_id as i64))
-        .execute(conn)?;
-    Ok(num_deleted >= 1)
+fn get_duration_env(key: &str, default: Duration) -> Result<Duration> {
+    match get_env_opt(key)? {
+        Some(value) => parse_duration(&value)
+            .map_err(|_| format!("環境変数 `{}` (\"{}\") の形式が不正です", key, value).into()),
+        None => Ok(default),
+    }
 }
 
-pub fn get_channels(conn: &mut PgConnection) -> QueryResult<Vec<Channel>> {
-    channels::table.load(conn)
+/// SQLite のコネクションを作るときに設定を行う。
+///
+/// 外部キー制約 (`ON DELETE CASCADE`) を有効にし、複数のコネクションから書き込めるように
+/// WAL モードにして、ロックが解けるまで待つようにする。
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+pub struct SqlitePragmas;
+
+#[cfg(feature = "sqlite")]
+impl CustomizeConnection<SqliteConnection, r2d2::Error> for SqlitePragmas {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> std::result::Result<(), r2d2::Error> {
+        conn.batch_execute(
+            "PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL; PRAGMA busy_timeout = 5000;",
+        )
+        .map_err(r2d2::Error::QueryError)
+    }
 }
 
-pub fn insert_or_update_subscriber(
-    conn: &mut PgConnection,
-    user_id: u64,
-    guild_id: u64,
-    annict_name: impl AsRef<str>,
-    end_cursor: Option<&str>,
-    last_activity_date: Option<DateTime<impl TimeZone>>,
-) -> QueryResult<Subscriber> {
-    let new_subscriber = NewSubscriber {
-        user_id: user_id as _,
-        guild_id: guild_id as _,
-        annict_name: annict_name.as_ref(),
-        end_cursor,
-        last_activity_date: last_activity_date.map(|dt| dt.to_utc()),
+/// コネクションプールの使用状況をログに出力する。
+fn log_pool_state(pool: &DbPool) {
+    let state = match pool {
+        DbPool::Postgres(pool) => pool.state(),
+        #[cfg(feature = "sqlite")]
+        DbPool::Sqlite(pool) => pool.state(),
     };
+    tracing::debug!(
+        "コネクションプール: 接続数 {}, うち未使用 {}",
+        state.connections,
+        state.idle_connections
+    );
+}
 
-    diesel::insert_into(subscribers::table)
-        .values(&new_subscriber)
-        .on_conflict((subscribers::guild_id, subscribers::user_id))
-        .do_update()
-        .set((
-            subscribers::annict_name.eq(new_subscriber.annict_name),
-            subscribers::end_cursor.eq(new_subscriber.end_cursor),
-            subscribers::last_activity_date.eq(new_subscriber.last_activity_date),
-        ))
-        .get_result(conn)
+/// 反映されていないマイグレーションを実行する。
+pub async fn run_migrations(pool: &DbPool) -> Result<()> {
+    match pool {
+        DbPool::Postgres(pool) => run_migrations_with(pool, MIGRATIONS).await,
+        #[cfg(feature = "sqlite")]
+        DbPool::Sqlite(pool) => run_migrations_with(pool, SQLITE_MIGRATIONS).await,
+    }
 }
 
-pub fn update_subscriber_info(
-    conn: &mut PgConnection,
-    id: i32,
-    end_cursor: Option<&str>,
-    last_activity_date: Option<DateTime<impl TimeZone>>,
-) -> QueryResult<Subscriber> {
-    diesel::update(subscribers::table.filter(subscribers::id.eq(id)))
-        .set((
-            subscribers::end_cursor.eq(end_cursor),
-            subscribers::last_activity_date.eq(last_activity_date),
-        ))
-        .get_result(conn)
+async fn run_migrations_with<C>(
+    pool: &Pool<ConnectionManager<C>>,
+    migrations: EmbeddedMigrations,
+) -> Result<()>
+where
+    C: R2D2Connection + MigrationHarness<C::Backend> + 'static,
+{
+    let pool = pool.clone();
+    task::spawn_blocking(move || {
+        pool.get()?.run_pending_migrations(migrations)?;
+        Ok(())
+    })
+    .await?
 }
 
-pub fn get_subscribers_by_guild(
-    conn: &mut PgConnection,
-    guild_id: u64,
-) -> QueryResult<Vec<Subscriber>> {
-    subscribers::table
-        .filter(subscribers::guild_id.eq(guild_id as i64))
-        .load(conn)
+/// プールから借りたコネクション `$conn` で `$body` を実行する。
+///
+/// `$body` はデータベースごとに、そのスキーマとコネクションの型でコンパイルされる。
+macro_rules! run {
+    ($pool:expr, |$conn:ident| $body:expr) => {
+        match $pool {
+            DbPool::Postgres(pool) => {
+                run_blocking(pool, move |$conn: &mut PgConnection| {
+                    use crate::schema::*;
+                    $body
+                })
+                .await
+            }
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite(pool) => {
+                run_blocking(pool, move |$conn: &mut SqliteConnection| {
+                    use crate::sqlite_schema::*;
+                    $body
+                })
+                .await
+            }
+        }
+    };
+}
+
+/// 投稿した通知のメッセージ `$notifications` を、コネクション `$conn` で履歴に記録する。
+///
+/// [run] の `$body` の中で、そのデータベースのスキーマを使って展開する。
+macro_rules! insert_notifications {
+    ($conn:expr, $notifications:expr) => {{
+        let values: Vec<_> = $notifications
+            .iter()
+            .map(|notification: &NewNotification| {
+                (
+                    notifications::subscriber_id.eq(notification.subscriber_id),
+                    notifications::channel_id.eq(notification.channel_id),
+                    notifications::activity_id.eq(notification.activity_id),
+                    notifications::message_id.eq(notification.message_id),
+                    notifications::activity_type.eq(i32::from(notification.activity_type)),
+                    notifications::work_title.eq(&notification.work_title),
+                    notifications::work_url.eq(&notification.work_url),
+                )
+            })
+            .collect();
+        if values.is_empty() {
+            Ok(())
+        } else {
+            diesel::insert_into(notifications::table)
+                .values(values)
+                .execute($conn)
+                .map(|_| ())
+        }
+    }};
+}
+
+/// プールから借りたコネクションで `f` を実行する。
+///
+/// diesel の操作は同期的なので、非同期ランタイムのスレッドを止めないように、
+/// ブロッキング処理用のスレッドで実行する。
+async fn run_blocking<C, R, F>(pool: &Pool<ConnectionManager<C>>, f: F) -> Result<R>
+where
+    C: R2D2Connection + 'static,
+    F: FnOnce(&mut C) -> QueryResult<R> + Send + 'static,
+    R: Send + 'static,
+{
+    let pool = pool.clone();
+    task::spawn_blocking(move || {
+        let mut conn = pool.get()?;
+        Ok(f(&mut conn)?)
+    })
+    .await?
+}
+
+#[async_trait]
+impl Store for DbPool {
+    fn log_state(&self) {
+        log_pool_state(self);
+    }
+
+    async fn insert_or_update_channel(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        notify_flag: NotifyFlag,
+    ) -> Result<Channel> {
+        run!(self, |conn| {
+            conn.transaction(|conn| {
+                // channels はサーバーの設定を参照するので、なければ先に作る
+                diesel::insert_into(guilds::table)
+                    .values(guilds::guild_id.eq(guild_id as i64))
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+                diesel::insert_into(channels::table)
+                    .values((
+                        channels::guild_id.eq(guild_id as i64),
+                        channels::channel_id.eq(channel_id as i64),
+                        channels::notify_flag.eq(notify_flag.bits()),
+                        channels::deleted_action.eq(i32::from(DeletedAction::default())),
+                    ))
+                    .on_conflict((channels::guild_id, channels::channel_id))
+                    .do_update()
+                    .set(channels::notify_flag.eq(notify_flag.bits()))
+                    .get_result(conn)
+            })
+        })
+    }
+
+    async fn update_deleted_action(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        deleted_action: DeletedAction,
+    ) -> Result<Channel> {
+        run!(self, |conn| {
+            diesel::update(
+                channels::table
+                    .filter(channels::guild_id.eq(guild_id as i64))
+                    .filter(channels::channel_id.eq(channel_id as i64)),
+            )
+            .set(channels::deleted_action.eq(i32::from(deleted_action)))
+            .get_result(conn)
+        })
+    }
+
+    async fn remove_channel(&self, guild_id: u64, channel_id: u64) -> Result<bool> {
+        let num_deleted = run!(self, |conn| {
+            diesel::delete(channels::table)
+                .filter(channels::channel_id.eq(channel_id as i64))
+                .filter(channels::guild_id.eq(guild_id as i64))
+                .execute(conn)
+        })?;
+        Ok(num_deleted >= 1)
+    }
+
+    async fn get_channel(&self, guild_id: u64, channel_id: u64) -> Result<Option<Channel>> {
+        run!(self, |conn| {
+            channels::table
+                .filter(channels::guild_id.eq(guild_id as i64))
+                .filter(channels::channel_id.eq(channel_id as i64))
+                .first(conn)
+                .optional()
+        })
+    }
+
+    async fn get_channels(&self) -> Result<Vec<Channel>> {
+        run!(self, |conn| channels::table.load(conn))
+    }
+
+    async fn insert_or_update_subscriber(
+        &self,
+        user_id: u64,
+        guild_id: u64,
+        annict_id: i64,
+        annict_name: &str,
+        end_cursor: Option<&str>,
+        last_activity_id: Option<i64>,
+        access_token: Option<&str>,
+    ) -> Result<Subscriber> {
+        let annict_name = annict_name.to_owned();
+        let end_cursor = end_cursor.map(ToOwned::to_owned);
+        let access_token = access_token.map(ToOwned::to_owned);
+        run!(self, |conn| {
+            let values = (
+                subscribers::annict_id.eq(annict_id),
+                subscribers::annict_name.eq(&annict_name),
+                subscribers::end_cursor.eq(&end_cursor),
+                subscribers::last_activity_id.eq(last_activity_id),
+                subscribers::access_token.eq(&access_token),
+            );
+            conn.transaction(|conn| {
+                // subscribers はサーバーの設定を参照するので、なければ先に作る
+                diesel::insert_into(guilds::table)
+                    .values(guilds::guild_id.eq(guild_id as i64))
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+                diesel::insert_into(subscribers::table)
+                    .values((
+                        subscribers::user_id.eq(user_id as i64),
+                        subscribers::guild_id.eq(guild_id as i64),
+                        values,
+                    ))
+                    .on_conflict((subscribers::guild_id, subscribers::user_id))
+                    .do_update()
+                    .set(values)
+                    .get_result(conn)
+            })
+        })
+    }
+
+    async fn update_access_token(&self, id: i32, access_token: Option<&str>) -> Result<Subscriber> {
+        let access_token = access_token.map(ToOwned::to_owned);
+        run!(self, |conn| {
+            diesel::update(subscribers::table.filter(subscribers::id.eq(id)))
+                .set(subscribers::access_token.eq(access_token))
+                .get_result(conn)
+        })
+    }
+
+    async fn update_annict_user(
+        &self,
+        id: i32,
+        annict_id: i64,
+        annict_name: &str,
+    ) -> Result<Subscriber> {
+        let annict_name = annict_name.to_owned();
+        run!(self, |conn| {
+            diesel::update(subscribers::table.filter(subscribers::id.eq(id)))
+                .set((
+                    subscribers::annict_id.eq(annict_id),
+                    subscribers::annict_name.eq(annict_name),
+                ))
+                .get_result(conn)
+        })
+    }
+
+    async fn get_subscribers(&self) -> Result<Vec<Subscriber>> {
+        run!(self, |conn| subscribers::table.load(conn))
+    }
+
+    async fn get_subscribers_without_annict_id(&self) -> Result<Vec<Subscriber>> {
+        run!(self, |conn| {
+            subscribers::table
+                .filter(subscribers::annict_id.is_null())
+                .load(conn)
+        })
+    }
+
+    async fn get_subscriber(&self, user_id: u64, guild_id: u64) -> Result<Option<Subscriber>> {
+        run!(self, |conn| {
+            subscribers::table
+                .filter(subscribers::user_id.eq(user_id as i64))
+                .filter(subscribers::guild_id.eq(guild_id as i64))
+                .first(conn)
+                .optional()
+        })
+    }
+
+    async fn get_subscribers_by_guild(&self, guild_id: u64) -> Result<Vec<Subscriber>> {
+        run!(self, |conn| {
+            subscribers::table
+                .filter(subscribers::guild_id.eq(guild_id as i64))
+                .load(conn)
+        })
+    }
+
+    async fn is_delivered(
+        &self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+    ) -> Result<bool> {
+        run!(self, |conn| {
+            select(exists(
+                delivered_activities::table
+                    .filter(delivered_activities::subscriber_id.eq(subscriber_id))
+                    .filter(delivered_activities::channel_id.eq(channel_id as i64))
+                    .filter(delivered_activities::activity_id.eq(activity_id)),
+            ))
+            .get_result(conn)
+        })
+    }
+
+    async fn insert_delivered(
+        &self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+        message_ids: &[i64],
+        content: &str,
+        notifications: &[NewNotification],
+    ) -> Result<Option<DeliveredActivity>> {
+        let message_ids = message_ids.to_vec();
+        let content = content.to_owned();
+        let notifications = notifications.to_vec();
+        run!(self, |conn| {
+            conn.transaction(|conn| {
+                let delivered = diesel::insert_into(delivered_activities::table)
+                    .values((
+                        delivered_activities::subscriber_id.eq(subscriber_id),
+                        delivered_activities::channel_id.eq(channel_id as i64),
+                        delivered_activities::activity_id.eq(activity_id),
+                        delivered_activities::message_ids.eq(MessageIds(message_ids)),
+                        delivered_activities::content.eq(content),
+                    ))
+                    .on_conflict_do_nothing()
+                    .get_result(conn)
+                    .optional()?;
+                // 既に記録されていた場合は、履歴も記録されている
+                if delivered.is_some() {
+                    insert_notifications!(conn, &notifications)?;
+                }
+                Ok(delivered)
+            })
+        })
+    }
+
+    async fn insert_digested(
+        &self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+        digest_message_id: i64,
+        content: &str,
+        notifications: &[NewNotification],
+    ) -> Result<Option<DeliveredActivity>> {
+        let content = content.to_owned();
+        let notifications = notifications.to_vec();
+        run!(self, |conn| {
+            conn.transaction(|conn| {
+                let delivered = diesel::insert_into(delivered_activities::table)
+                    .values((
+                        delivered_activities::subscriber_id.eq(subscriber_id),
+                        delivered_activities::channel_id.eq(channel_id as i64),
+                        delivered_activities::activity_id.eq(activity_id),
+                        delivered_activities::content.eq(content),
+                        delivered_activities::digest_message_id.eq(digest_message_id),
+                    ))
+                    .on_conflict_do_nothing()
+                    .get_result(conn)
+                    .optional()?;
+                // 既に記録されていた場合は、履歴も記録されている
+                if delivered.is_some() {
+                    insert_notifications!(conn, &notifications)?;
+                }
+                Ok(delivered)
+            })
+        })
+    }
+
+    async fn get_digested(
+        &self,
+        channel_id: u64,
+        digest_message_id: i64,
+    ) -> Result<Vec<DeliveredActivity>> {
+        run!(self, |conn| {
+            delivered_activities::table
+                .filter(delivered_activities::channel_id.eq(channel_id as i64))
+                .filter(delivered_activities::digest_message_id.eq(digest_message_id))
+                .order(delivered_activities::activity_id)
+                .load(conn)
+        })
+    }
+
+    async fn get_delivered(
+        &self,
+        subscriber_id: i32,
+        activity_id: i64,
+    ) -> Result<Vec<DeliveredActivity>> {
+        run!(self, |conn| {
+            delivered_activities::table
+                .filter(delivered_activities::subscriber_id.eq(subscriber_id))
+                .filter(delivered_activities::activity_id.eq(activity_id))
+                .load(conn)
+        })
+    }
+
+    async fn get_delivered_since(
+        &self,
+        subscriber_id: i32,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DeliveredActivity>> {
+        run!(self, |conn| {
+            delivered_activities::table
+                .filter(delivered_activities::subscriber_id.eq(subscriber_id))
+                .filter(delivered_activities::delivered_at.ge(since))
+                .filter(delivered_activities::deleted.eq(false))
+                .load(conn)
+        })
+    }
+
+    async fn mark_delivered_deleted(
+        &self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+    ) -> Result<DeliveredActivity> {
+        run!(self, |conn| {
+            diesel::update(
+                delivered_activities::table
+                    .filter(delivered_activities::subscriber_id.eq(subscriber_id))
+                    .filter(delivered_activities::channel_id.eq(channel_id as i64))
+                    .filter(delivered_activities::activity_id.eq(activity_id)),
+            )
+            .set(delivered_activities::deleted.eq(true))
+            .get_result(conn)
+        })
+    }
+
+    async fn update_delivered_content(
+        &self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+        content: &str,
+    ) -> Result<DeliveredActivity> {
+        let content = content.to_owned();
+        run!(self, |conn| {
+            diesel::update(
+                delivered_activities::table
+                    .filter(delivered_activities::subscriber_id.eq(subscriber_id))
+                    .filter(delivered_activities::channel_id.eq(channel_id as i64))
+                    .filter(delivered_activities::activity_id.eq(activity_id)),
+            )
+            .set(delivered_activities::content.eq(content))
+            .get_result(conn)
+        })
+    }
+
+    async fn enqueue_activities(
+        &self,
+        activities: &[(i32, Vec<(i64, String)>)],
+        end_cursor: Option<&str>,
+    ) -> Result<()> {
+        let activities = activities.to_vec();
+        let end_cursor = end_cursor.map(ToOwned::to_owned);
+        run!(self, |conn| {
+            conn.transaction(|conn| {
+                for (subscriber_id, activities) in &activities {
+                    let subscriber_id = *subscriber_id;
+                    // SQLite は ON CONFLICT 付きのまとめた挿入ができないので、1 行ずつ加える
+                    for (activity_id, payload) in activities {
+                        diesel::insert_into(outbox::table)
+                            .values((
+                                outbox::subscriber_id.eq(subscriber_id),
+                                outbox::activity_id.eq(activity_id),
+                                outbox::payload.eq(payload),
+                            ))
+                            .on_conflict_do_nothing()
+                            .execute(conn)?;
+                    }
+                    let subscriber =
+                        || subscribers::table.filter(subscribers::id.eq(subscriber_id));
+                    if let Some(last_activity_id) = activities.iter().map(|(id, _)| *id).max() {
+                        diesel::update(subscriber())
+                            .set(subscribers::last_activity_id.eq(last_activity_id))
+                            .execute(conn)?;
+                    }
+                    if let Some(end_cursor) = &end_cursor {
+                        diesel::update(subscriber())
+                            .set(subscribers::end_cursor.eq(end_cursor))
+                            .execute(conn)?;
+                    }
+                }
+                Ok(())
+            })
+        })
+    }
+
+    async fn get_unsent(&self, subscriber_id: i32) -> Result<Vec<OutboxActivity>> {
+        run!(self, |conn| {
+            outbox::table
+                .filter(outbox::subscriber_id.eq(subscriber_id))
+                .filter(outbox::sent_at.is_null())
+                .order(outbox::activity_id)
+                .load(conn)
+        })
+    }
+
+    async fn mark_sent(&self, subscriber_id: i32, activity_ids: &[i64]) -> Result<usize> {
+        let activity_ids = activity_ids.to_vec();
+        let now = Utc::now();
+        run!(self, |conn| {
+            diesel::update(
+                outbox::table
+                    .filter(outbox::subscriber_id.eq(subscriber_id))
+                    .filter(outbox::activity_id.eq_any(activity_ids)),
+            )
+            .set(outbox::sent_at.eq(now))
+            .execute(conn)
+        })
+    }
+
+    async fn prune_outbox(&self, before: DateTime<Utc>) -> Result<usize> {
+        run!(self, |conn| {
+            diesel::delete(outbox::table.filter(outbox::sent_at.lt(before))).execute(conn)
+        })
+    }
+
+    async fn get_guild(&self, guild_id: u64) -> Result<Option<Guild>> {
+        run!(self, |conn| {
+            guilds::table
+                .filter(guilds::guild_id.eq(guild_id as i64))
+                .first(conn)
+                .optional()
+        })
+    }
+
+    async fn get_guilds(&self) -> Result<Vec<Guild>> {
+        run!(self, |conn| guilds::table.load(conn))
+    }
+
+    async fn update_guild(&self, guild: &Guild) -> Result<Guild> {
+        let guild = guild.clone();
+        run!(self, |conn| {
+            let values = (
+                guilds::locale.eq(&guild.locale),
+                guilds::timezone.eq(&guild.timezone),
+                guilds::default_notify_flag.eq(guild.default_notify_flag.bits()),
+                guilds::digest_threshold.eq(guild.digest_threshold),
+                guilds::digest_hour.eq(guild.digest_hour),
+                guilds::link_role_id.eq(guild.link_role_id),
+            );
+            diesel::insert_into(guilds::table)
+                .values((guilds::guild_id.eq(guild.guild_id), values))
+                .on_conflict(guilds::guild_id)
+                .do_update()
+                .set(values)
+                .get_result(conn)
+        })
+    }
+
+    async fn remove_guild(&self, guild_id: u64) -> Result<bool> {
+        let num_deleted = run!(self, |conn| {
+            diesel::delete(guilds::table.filter(guilds::guild_id.eq(guild_id as i64))).execute(conn)
+        })?;
+        Ok(num_deleted >= 1)
+    }
+
+    async fn get_notifications(
+        &self,
+        guild_id: u64,
+        filter: &NotificationFilter,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Notification>> {
+        let filter = filter.clone();
+        run!(self, |conn| {
+            let mut subscriber_ids = subscribers::table
+                .select(subscribers::id)
+                .filter(subscribers::guild_id.eq(guild_id as i64))
+                .into_boxed();
+            if let Some(user_id) = filter.user_id {
+                subscriber_ids = subscriber_ids.filter(subscribers::user_id.eq(user_id as i64));
+            }
+
+            let mut query = notifications::table
+                .filter(notifications::subscriber_id.eq_any(subscriber_ids))
+                .into_boxed();
+            if let Some(title) = &filter.work_title {
+                query = query.filter(
+                    notifications::work_title
+                        .like(format!("%{}%", escape_like(title)))
+                        .escape('\\'),
+                );
+            }
+            if let Some(channel_ids) = &filter.channel_ids {
+                let channel_ids: Vec<_> = channel_ids.iter().map(|id| *id as i64).collect();
+                query = query.filter(notifications::channel_id.eq_any(channel_ids));
+            }
+            if let Some(since) = filter.since {
+                query = query.filter(notifications::notified_at.ge(since));
+            }
+            if let Some(until) = filter.until {
+                query = query.filter(notifications::notified_at.lt(until));
+            }
+            query
+                .order((notifications::notified_at.desc(), notifications::id.desc()))
+                .offset(offset)
+                .limit(limit)
+                .load(conn)
+        })
+    }
+
+    async fn export(&self, guild_id: Option<u64>) -> Result<Snapshot> {
+        let (guilds, channels, subscribers, delivered_activities, outbox, notifications) =
+            run!(self, |conn| {
+                // 読み出している間に書き込まれても食い違わないように、1 つのトランザクションで読み出す
+                conn.transaction(|conn| {
+                    let mut guild_query = guilds::table.into_boxed();
+                    let mut channel_query = channels::table.into_boxed();
+                    let mut subscriber_query = subscribers::table.into_boxed();
+                    if let Some(guild_id) = guild_id {
+                        guild_query = guild_query.filter(guilds::guild_id.eq(guild_id as i64));
+                        channel_query =
+                            channel_query.filter(channels::guild_id.eq(guild_id as i64));
+                        subscriber_query =
+                            subscriber_query.filter(subscribers::guild_id.eq(guild_id as i64));
+                    }
+                    let subscriber_ids = || {
+                        let query = subscribers::table.select(subscribers::id).into_boxed();
+                        match guild_id {
+                            Some(guild_id) => {
+                                query.filter(subscribers::guild_id.eq(guild_id as i64))
+                            }
+                            None => query,
+                        }
+                    };
+
+                    let guilds: Vec<Guild> = guild_query.load(conn)?;
+                    let channels: Vec<Channel> = channel_query.load(conn)?;
+                    let subscribers: Vec<Subscriber> = subscriber_query.load(conn)?;
+                    let delivered_activities: Vec<DeliveredActivity> = delivered_activities::table
+                        .filter(delivered_activities::subscriber_id.eq_any(subscriber_ids()))
+                        .load(conn)?;
+                    let outbox: Vec<OutboxActivity> = outbox::table
+                        .filter(outbox::subscriber_id.eq_any(subscriber_ids()))
+                        .load(conn)?;
+                    let notifications: Vec<Notification> = notifications::table
+                        .filter(notifications::subscriber_id.eq_any(subscriber_ids()))
+                        .load(conn)?;
+                    Ok((
+                        guilds,
+                        channels,
+                        subscribers,
+                        delivered_activities,
+                        outbox,
+                        notifications,
+                    ))
+                })
+            })?;
+        Ok(Snapshot::new(
+            guilds,
+            channels,
+            subscribers,
+            delivered_activities,
+            outbox,
+            notifications,
+        ))
+    }
+
+    async fn import(&self, snapshot: &Snapshot) -> Result<()> {
+        snapshot.validate()?;
+        let snapshot = snapshot.clone();
+        run!(self, |conn| {
+            conn.transaction(|conn| {
+                for guild in &snapshot.guilds {
+                    let values = (
+                        guilds::locale.eq(&guild.locale),
+                        guilds::timezone.eq(&guild.timezone),
+                        guilds::default_notify_flag.eq(guild.default_notify_flag),
+                        guilds::digest_threshold.eq(guild.digest_threshold),
+                        guilds::digest_hour.eq(guild.digest_hour),
+                        guilds::link_role_id.eq(guild.link_role_id),
+                    );
+                    diesel::insert_into(guilds::table)
+                        .values((guilds::guild_id.eq(guild.guild_id), values))
+                        .on_conflict(guilds::guild_id)
+                        .do_update()
+                        .set(values)
+                        .execute(conn)?;
+                }
+
+                for channel in &snapshot.channels {
+                    diesel::insert_into(guilds::table)
+                        .values(guilds::guild_id.eq(channel.guild_id))
+                        .on_conflict_do_nothing()
+                        .execute(conn)?;
+                    let values = (
+                        channels::notify_flag.eq(channel.notify_flag),
+                        channels::deleted_action.eq(channel.deleted_action),
+                    );
+                    diesel::insert_into(channels::table)
+                        .values((
+                            channels::guild_id.eq(channel.guild_id),
+                            channels::channel_id.eq(channel.channel_id),
+                            values,
+                        ))
+                        .on_conflict((channels::guild_id, channels::channel_id))
+                        .do_update()
+                        .set(values)
+                        .execute(conn)?;
+                }
+
+                // 文書の購読者を、このデータベースでの購読者の ID に対応させる
+                let mut ids = HashMap::new();
+                for subscriber in &snapshot.subscribers {
+                    diesel::insert_into(guilds::table)
+                        .values(guilds::guild_id.eq(subscriber.guild_id))
+                        .on_conflict_do_nothing()
+                        .execute(conn)?;
+                    let values = (
+                        subscribers::annict_id.eq(subscriber.annict_id),
+                        subscribers::annict_name.eq(&subscriber.annict_name),
+                        subscribers::end_cursor.eq(&subscriber.end_cursor),
+                        subscribers::last_activity_id.eq(subscriber.last_activity_id),
+                    );
+                    let id: i32 = diesel::insert_into(subscribers::table)
+                        .values((
+                            subscribers::user_id.eq(subscriber.user_id),
+                            subscribers::guild_id.eq(subscriber.guild_id),
+                            values,
+                        ))
+                        .on_conflict((subscribers::guild_id, subscribers::user_id))
+                        .do_update()
+                        .set(values)
+                        .returning(subscribers::id)
+                        .get_result(conn)?;
+                    if let Some(access_token) = &subscriber.access_token {
+                        diesel::update(subscribers::table.filter(subscribers::id.eq(id)))
+                            .set(subscribers::access_token.eq(access_token))
+                            .execute(conn)?;
+                    }
+                    ids.insert((subscriber.guild_id, subscriber.user_id), id);
+                }
+
+                // 検証済みなので、記録の購読者は必ず見つかる
+                // SQLite は ON CONFLICT 付きのまとめた挿入ができないので、1 行ずつ加える
+                for delivered in &snapshot.delivered_activities {
+                    diesel::insert_into(delivered_activities::table)
+                        .values((
+                            delivered_activities::subscriber_id
+                                .eq(ids[&(delivered.guild_id, delivered.user_id)]),
+                            delivered_activities::channel_id.eq(delivered.channel_id),
+                            delivered_activities::activity_id.eq(delivered.activity_id),
+                            delivered_activities::delivered_at.eq(delivered.delivered_at),
+                            delivered_activities::message_ids
+                                .eq(MessageIds(delivered.message_ids.clone())),
+                            delivered_activities::content.eq(&delivered.content),
+                            delivered_activities::deleted.eq(delivered.deleted),
+                            delivered_activities::digest_message_id.eq(delivered.digest_message_id),
+                        ))
+                        .on_conflict_do_nothing()
+                        .execute(conn)?;
+                }
+
+                for activity in &snapshot.outbox {
+                    diesel::insert_into(outbox::table)
+                        .values((
+                            outbox::subscriber_id.eq(ids[&(activity.guild_id, activity.user_id)]),
+                            outbox::activity_id.eq(activity.activity_id),
+                            outbox::payload.eq(&activity.payload),
+                            outbox::created_at.eq(activity.created_at),
+                            outbox::sent_at.eq(activity.sent_at),
+                        ))
+                        .on_conflict_do_nothing()
+                        .execute(conn)?;
+                }
+
+                // 通知の履歴には一意な列がないので、同じメッセージの記録があるか調べてから加える
+                for notification in &snapshot.notifications {
+                    let subscriber_id = ids[&(notification.guild_id, notification.user_id)];
+                    let exists: bool = select(exists(
+                        notifications::table
+                            .filter(notifications::subscriber_id.eq(subscriber_id))
+                            .filter(notifications::channel_id.eq(notification.channel_id))
+                            .filter(notifications::activity_id.eq(notification.activity_id))
+                            .filter(notifications::message_id.eq(notification.message_id)),
+                    ))
+                    .get_result(conn)?;
+                    if exists {
+                        continue;
+                    }
+                    diesel::insert_into(notifications::table)
+                        .values((
+                            notifications::subscriber_id.eq(subscriber_id),
+                            notifications::channel_id.eq(notification.channel_id),
+                            notifications::activity_id.eq(notification.activity_id),
+                            notifications::message_id.eq(notification.message_id),
+                            notifications::activity_type.eq(notification.activity_type),
+                            notifications::work_title.eq(&notification.work_title),
+                            notifications::work_url.eq(&notification.work_url),
+                            notifications::notified_at.eq(notification.notified_at),
+                        ))
+                        .execute(conn)?;
+                }
+
+                Ok(())
+            })
+        })
+    }
 }
 
-pub fn get_last_activity_date(
-    conn: &mut PgConnection,
-    id: i32,
-) -> QueryResult<Option<DateTime<Local>>> {
-    subscribers::table
-        .select(subscribers::last_activity_date)
-        .filter(subscribers::id.eq(id))
-        .first(conn)
+/// `LIKE` のパターン中で `s` がそのまま一致するように、`%` と `_` をエスケープする。
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
 }
@@ -1,86 +1,123 @@
 use std::env;
 
-use chrono::{DateTime, Local};
-use diesel::{Connection, PgConnection, QueryResult};
-
-use crate::{db::get_last_activity_date, discord::NotifyFlag, Result};
-
-use super::{
-    connect, get_channels, get_subscribers_by_guild, insert_or_update_channel,
-    insert_or_update_subscriber,
+#[cfg(feature = "sqlite")]
+use diesel::SqliteConnection;
+use diesel::{
+    r2d2::{self, ConnectionManager, CustomizeConnection, Pool},
+    Connection, PgConnection, RunQueryDsl,
 };
 
-fn test<R>(f: impl FnOnce(&mut PgConnection) -> QueryResult<R>) -> Result<R> {
-    dotenv::dotenv().ok();
-    env::set_var(
-        "DATABASE_URL",
-        env::var("DATABASE_TEST_URL").expect("環境変数 `DATABASE_TEST_URL` を設定してください"),
-    );
-
-    let mut conn = connect()?;
-    Ok(conn.test_transaction(f))
-}
-
-#[test]
-fn channels_test() -> Result<()> {
-    test(|conn| {
-        let channel = insert_or_update_channel(conn, 1, 32, NotifyFlag::default())?;
-        assert_eq!(channel.guild_id, 1);
-        assert_eq!(channel.channel_id, 32);
-        assert_eq!(channel.notify_flag, NotifyFlag::default());
-
-        let channels = get_channels(conn)?;
-        assert_eq!(channels.len(), 1);
-        assert_eq!(channels[0], channel);
+use crate::{
+    store::{
+        test::{
+            annict_user_test, channels_test, delivered_activities_test,
+            delivered_notifications_test, guilds_test, notifications_test, outbox_test,
+            snapshot_test, subscribers_test,
+        },
+        Store,
+    },
+    Result,
+};
 
-        let flag = NotifyFlag::RECORD | NotifyFlag::REVIEW | NotifyFlag::WITH_COMMENT;
-        let channel = insert_or_update_channel(conn, 1, 32, flag)?;
-        assert_eq!(channel.guild_id, 1);
-        assert_eq!(channel.channel_id, 32);
-        assert_eq!(channel.notify_flag, flag);
+use super::DbPool;
+#[cfg(feature = "sqlite")]
+use super::{run_migrations, SqlitePragmas};
 
-        let channels = get_channels(conn)?;
-        assert_eq!(channels.len(), 1);
-        assert_eq!(channels[0], channel);
+/// コネクションを作るときにテスト用のトランザクションを開始する。
+#[derive(Debug)]
+struct TestTransaction;
 
-        Ok(())
-    })
+impl CustomizeConnection<PgConnection, r2d2::Error> for TestTransaction {
+    fn on_acquire(&self, conn: &mut PgConnection) -> std::result::Result<(), r2d2::Error> {
+        conn.begin_test_transaction()
+            .map_err(r2d2::Error::QueryError)
+    }
 }
 
-#[test]
-fn subscribers_test() -> Result<()> {
-    test(|conn| {
-        let subscriber =
-            insert_or_update_subscriber(conn, 1, 1, "kei519", None, None::<DateTime<Local>>)?;
-        assert_eq!(subscriber.user_id, 1);
-        assert_eq!(subscriber.guild_id, 1);
-        assert_eq!(subscriber.annict_name, "kei519");
-        assert!(subscriber.end_cursor.is_none());
-        assert!(subscriber.last_activity_date.is_none());
-
-        let subscribers = get_subscribers_by_guild(conn, subscriber.guild_id as _)?;
-        assert_eq!(subscribers.len(), 1);
-        assert_eq!(subscribers[0], subscriber);
-
-        let datetime = Local::now().format("%Y-%m-%dT%H:%M:%S%:z").to_string();
-        let datetime = DateTime::parse_from_rfc3339(&datetime).unwrap();
-
-        let subscriber =
-            insert_or_update_subscriber(conn, 1, 1, "hoge", Some("fuga"), Some(datetime))?;
-
-        assert_eq!(subscriber.user_id, 1);
-        assert_eq!(subscriber.guild_id, 1);
-        assert_eq!(subscriber.annict_name, "hoge");
-        assert_eq!(subscriber.end_cursor.as_ref().unwrap(), "fuga");
-        assert_eq!(subscriber.last_activity_date.unwrap(), datetime);
+/// テスト用の PostgreSQL のデータベースにつながる、コネクションが 1 つだけのプールを作る。
+/// 変更はすべてそのコネクションのトランザクションの中で行われ、プールを破棄すると取り消される。
+fn postgres_pool() -> Result<DbPool> {
+    dotenv::dotenv().ok();
+    let url =
+        env::var("DATABASE_TEST_URL").expect("環境変数 `DATABASE_TEST_URL` を設定してください");
+
+    Ok(DbPool::Postgres(
+        Pool::builder()
+            .max_size(1)
+            .connection_customizer(Box::new(TestTransaction))
+            .build(ConnectionManager::new(url))?,
+    ))
+}
 
-        let last_activity_date = get_last_activity_date(conn, subscriber.id)?;
-        assert_eq!(last_activity_date.unwrap(), datetime);
+/// メモリ上の SQLite のデータベースにつながる、コネクションが 1 つだけのプールを作る。
+/// データベースはそのコネクションと共に破棄される。
+#[cfg(feature = "sqlite")]
+async fn sqlite_pool() -> Result<DbPool> {
+    let pool = DbPool::Sqlite(
+        Pool::builder()
+            .max_size(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connection_customizer(Box::new(SqlitePragmas))
+            .build(ConnectionManager::<SqliteConnection>::new(":memory:"))?,
+    );
+    run_migrations(&pool).await?;
+    Ok(pool)
+}
 
-        let subscribers = get_subscribers_by_guild(conn, subscriber.guild_id as _)?;
-        assert_eq!(subscribers.len(), 1);
-        assert_eq!(subscribers[0], subscriber);
+/// テストをそれぞれのデータベースで実行する。
+macro_rules! db_tests {
+    ($($name:ident),* $(,)?) => {
+        mod postgres {
+            $(
+                #[tokio::test]
+                async fn $name() -> crate::Result<()> {
+                    super::$name(&super::postgres_pool()?).await
+                }
+            )*
+        }
+
+        #[cfg(feature = "sqlite")]
+        mod sqlite {
+            $(
+                #[tokio::test]
+                async fn $name() -> crate::Result<()> {
+                    super::$name(&super::sqlite_pool().await?).await
+                }
+            )*
+        }
+    };
+}
 
-        Ok(())
-    })
+db_tests!(
+    channels_test,
+    subscribers_test,
+    annict_user_test,
+    delivered_activities_test,
+    delivered_notifications_test,
+    outbox_test,
+    notifications_test,
+    guilds_test,
+    snapshot_test,
+    without_annict_id_test,
+);
+
+async fn without_annict_id_test(pool: &DbPool) -> Result<()> {
+    let subscriber = pool
+        .insert_or_update_subscriber(1, 1, 100, "kei519", None, None, None)
+        .await?;
+    assert!(pool.get_subscribers_without_annict_id().await?.is_empty());
+
+    // ID が入る前からある行
+    let sql = diesel::sql_query("UPDATE subscribers SET annict_id = NULL");
+    match pool {
+        DbPool::Postgres(pool) => sql.execute(&mut pool.get()?)?,
+        #[cfg(feature = "sqlite")]
+        DbPool::Sqlite(pool) => sql.execute(&mut pool.get()?)?,
+    };
+    let subscribers = pool.get_subscribers_without_annict_id().await?;
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0].id, subscriber.id);
+
+    Ok(())
 }
@@ -1,23 +1,62 @@
-use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    future::Future,
+    sync::Arc,
+    time::Duration,
+};
 
 use bitflags::bitflags;
-use regex::Regex;
 use serenity::{
     all::{
-        ChannelId, Command, Context, CreateEmbed, CreateEmbedAuthor, CreateMessage, EventHandler,
-        GatewayIntents, GuildId, Http, HttpError, Interaction, Member, Ready, UserId,
+        ChannelId, Colour, Command, CommandInteraction, Context, CreateEmbed, CreateEmbedAuthor,
+        CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage,
+        CreateMessage, EditMessage, EventHandler, GatewayIntents, Guild, GuildId, Http, HttpError,
+        Interaction, Member, MessageId, Ready, UnavailableGuild, UserId,
     },
     Client,
 };
-use tokio::time;
+use tokio::{sync::Semaphore, task::JoinSet, time};
 
 use crate::{
-    annict::{ActivityItem, RatingState},
-    db, get_env, Result,
+    annict::{AnnictClient, AnnictOAuth},
+    get_env, get_env_opt,
+    models::{NewNotification, Subscriber},
+    parse_duration,
+    source::{
+        Activity, ActivityItem, ActivitySource, History, Rating, RecentActivities, SourceError,
+        StatusState, Work,
+    },
+    store::Store,
+    Result,
 };
 
 mod annict;
+mod backfill;
+mod digest;
+mod export;
+mod history;
 mod notify;
+mod search;
+mod settings;
+
+/// 通知の並行数の既定値。
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// 編集されたアクティビティを確認する間隔の既定値。
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// 編集を確認する最近のアクティビティの数の既定値。
+pub const DEFAULT_REFRESH_COUNT: i32 = 10;
+
+/// 溜まったアクティビティをまとめて通知する件数の閾値の既定値。
+pub const DEFAULT_DIGEST_THRESHOLD: usize = 10;
+
+/// 送信し終えたアクティビティを送信待ちに残しておく期間。
+const OUTBOX_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// 削除されたアクティビティを確認する期間の既定値。
+pub const DEFAULT_DELETION_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
 
 bitflags! {
     /// 通知するアクティビティの種類を表すフラグ。
@@ -59,10 +98,105 @@ impl TryFrom<i32> for NotifyFlag {
     }
 }
 
+/// 取得元でアクティビティが削除されたときの、通知したメッセージの扱い。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeletedAction {
+    /// 何もしない。
+    #[default]
+    Keep = 0,
+
+    /// メッセージを削除する。
+    Delete = 1,
+
+    /// 埋め込みに取り消し線を引く。
+    Strike = 2,
+
+    /// 埋め込みに削除されたことを表示する。
+    Mark = 3,
+}
+
+impl From<DeletedAction> for i32 {
+    fn from(value: DeletedAction) -> Self {
+        value as _
+    }
+}
+
+impl TryFrom<i32> for DeletedAction {
+    type Error = &'static str;
+
+    fn try_from(value: i32) -> std::result::Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Keep),
+            1 => Ok(Self::Delete),
+            2 => Ok(Self::Strike),
+            3 => Ok(Self::Mark),
+            _ => Err("unknown value"),
+        }
+    }
+}
+
+/// 通知したアクティビティの中身の種類。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityType {
+    Record = 0,
+    Review = 1,
+    Status = 2,
+}
+
+impl From<&ActivityItem> for ActivityType {
+    fn from(value: &ActivityItem) -> Self {
+        match value {
+            ActivityItem::Record(_) => Self::Record,
+            ActivityItem::Review(_) => Self::Review,
+            ActivityItem::Status(_) => Self::Status,
+        }
+    }
+}
+
+impl From<ActivityType> for i32 {
+    fn from(value: ActivityType) -> Self {
+        value as _
+    }
+}
+
+impl TryFrom<i32> for ActivityType {
+    type Error = &'static str;
+
+    fn try_from(value: i32) -> std::result::Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Record),
+            1 => Ok(Self::Review),
+            2 => Ok(Self::Status),
+            _ => Err("unknown value"),
+        }
+    }
+}
+
+impl Display for ActivityType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Record => "エピソード記録",
+            Self::Review => "作品記録",
+            Self::Status => "ステータス更新",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 /// Discord の イベントリスナーを開始させ、その [Future] と HTTP クライアント [Http] を返す。
-pub async fn start() -> Result<(impl Future<Output = Result<()>>, Arc<Http>)> {
+pub async fn start(
+    annict: AnnictClient,
+    oauth: AnnictOAuth,
+    source: Arc<dyn ActivitySource>,
+    store: Arc<dyn Store>,
+) -> Result<(impl Future<Output = Result<()>>, Arc<Http>)> {
     let mut client = Client::builder(get_env("DISCORD_TOKEN")?, GatewayIntents::default())
-        .event_handler(Handler)
+        .event_handler(Handler {
+            annict,
+            oauth,
+            source,
+            store,
+        })
         .await?;
 
     let http = client.http.clone();
@@ -71,88 +205,688 @@ pub async fn start() -> Result<(impl Future<Output = Result<()>>, Arc<Http>)> {
     Ok((task, http))
 }
 
-/// 通知に用いる [Http] クライアントを受け取り、通知タスクを開始する。
-pub async fn notify(http: Arc<Http>) -> Result<()> {
+/// 通知に用いる [Http] クライアントとアクティビティの取得元 `source`、保存先 `store` を受け取り、
+/// 通知タスクを開始する。
+///
+/// 購読者の取得と通知は、最大 `NOTIFY_CONCURRENCY` 個のタスクで並行して行う。
+/// 同じ購読者のアクティビティは常に 1 つのタスクで古い順に通知する。
+///
+/// 取得したアクティビティは一旦データベースの送信待ちに加え、Discord に送信し終えてから送信済みにするので、
+/// 途中で停止したり送信に失敗したりしても、次回の更新で改めて通知する。
+/// 停止していた間などに閾値より多く溜まったアクティビティは、1 つの要約にまとめて通知する。
+/// 閾値はサーバーの設定に従い、設定されていなければ `DIGEST_THRESHOLD` 件とする。
+/// サーバーに要約の時刻が設定されている場合は、その時刻になるまで溜めておき、まとめて通知する。
+pub async fn notify(
+    http: Arc<Http>,
+    source: Arc<dyn ActivitySource>,
+    store: Arc<dyn Store>,
+) -> Result<()> {
     let interval = get_interval()?;
     tracing::info!("更新間隔: {} 秒", interval.as_secs());
-    let mut conn = db::connect()?;
+    let concurrency = get_concurrency()?;
+    tracing::info!("並行数: {}", concurrency);
+    let digest_threshold = get_digest_threshold()?;
+    let semaphore = Arc::new(Semaphore::new(concurrency));
     loop {
         tracing::trace!("loop!");
+        store.log_state();
 
+        let guilds: HashMap<_, _> = store
+            .get_guilds()
+            .await?
+            .into_iter()
+            .map(|guild| (GuildId::new(guild.guild_id as _), guild))
+            .collect();
+        let now = chrono::Utc::now();
         let mut channels = HashMap::new();
-        for chan in db::get_channels(&mut conn)? {
+        for chan in store.get_channels().await? {
             let guild_id = GuildId::new(chan.guild_id as _);
-            if !channels.contains_key(&guild_id) {
-                channels.insert(guild_id, vec![]);
+            channels
+                .entry(guild_id)
+                .or_insert_with(Vec::new)
+                .push((ChannelId::new(chan.channel_id as _), chan.notify_flag));
+        }
+
+        // 通知対象の (購読者, メンバー, 通知先チャンネル) を並行して集める
+        let mut tasks = JoinSet::new();
+        for (guild_id, channels_and_flags) in channels {
+            let channels_and_flags = Arc::new(channels_and_flags);
+            let guild = guilds.get(&guild_id);
+            let delivery = match guild.and_then(|guild| guild.is_digest_hour(now)) {
+                Some(true) => Delivery::Digest,
+                Some(false) => Delivery::Hold,
+                None => Delivery::Threshold(
+                    guild
+                        .and_then(|guild| guild.digest_threshold)
+                        .map_or(digest_threshold, |threshold| threshold as _),
+                ),
+            };
+            for subscriber in store.get_subscribers_by_guild(guild_id.get()).await? {
+                let http = http.clone();
+                let semaphore = semaphore.clone();
+                let channels_and_flags = channels_and_flags.clone();
+                tasks.spawn(async move {
+                    let _permit = semaphore.acquire_owned().await.expect("セマフォは閉じない");
+                    let member = get_member(&http, &subscriber).await?;
+                    Result::Ok(
+                        member.map(|member| (subscriber, member, channels_and_flags, delivery)),
+                    )
+                });
+            }
+        }
+        let mut targets = vec![];
+        // 一部の購読者で失敗しても、残りの購読者には通知する
+        while let Some(target) = tasks.join_next().await {
+            match target {
+                Ok(Ok(target)) => targets.extend(target),
+                Ok(Err(e)) => tracing::warn!("通知先のメンバーを取得できませんでした: {}", e),
+                Err(e) => tracing::error!("通知先を集めるタスクが異常終了しました: {}", e),
             }
-            channels.get_mut(&guild_id).and_then(|map| {
-                map.push((ChannelId::new(chan.channel_id as _), chan.notify_flag));
-                Some(())
+        }
+
+        // 複数のサーバーで購読されているアカウントは 1 回だけ取得するようにまとめる
+        let mut accounts: HashMap<_, Vec<_>> = HashMap::new();
+        for target in targets {
+            accounts
+                .entry(account_key(&target.0))
+                .or_default()
+                .push(target);
+        }
+        let mut accounts: Vec<_> = accounts.into_values().collect();
+
+        // 複数のアカウントのアクティビティをまとめて取得し、まとまりごとに並行して通知する
+        let mut tasks = JoinSet::new();
+        while !accounts.is_empty() {
+            let batch: Vec<_> = accounts
+                .drain(..source.batch_size().min(accounts.len()))
+                .collect();
+            let http = http.clone();
+            let source = source.clone();
+            let store = store.clone();
+            let semaphore = semaphore.clone();
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await.expect("セマフォは閉じない");
+                notify_batch(&http, source.as_ref(), store.as_ref(), batch).await
             });
         }
+        // 一部のまとまりで失敗しても、残りのまとまりの通知は続ける
+        while let Some(result) = tasks.join_next().await {
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => tracing::warn!("アクティビティを通知できませんでした: {}", e),
+                Err(e) => tracing::error!("通知するタスクが異常終了しました: {}", e),
+            }
+        }
 
-        'chan_loop: for (guild_id, channels_and_flags) in channels {
-            for subscriber in db::get_subscribers_by_guild(&mut conn, guild_id.get())? {
-                let member = match http
-                    .get_member(
-                        GuildId::new(subscriber.guild_id as _),
-                        UserId::new(subscriber.user_id as _),
-                    )
+        store
+            .prune_outbox(chrono::Utc::now() - OUTBOX_RETENTION)
+            .await?;
+
+        time::sleep(interval).await;
+    }
+}
+
+/// 最近のアクティビティを定期的に取得し直し、通知した後に編集や削除されていたら、
+/// 通知したメッセージも更新する。
+///
+/// 間隔は環境変数 `REFRESH_INTERVAL` で、取得し直すアクティビティの数は `REFRESH_COUNT` で設定する。
+/// 削除は、通知してから `DELETION_WINDOW` 以内のアクティビティについて確認する。
+pub async fn refresh(
+    http: Arc<Http>,
+    source: Arc<dyn ActivitySource>,
+    store: Arc<dyn Store>,
+) -> Result<()> {
+    let interval = match get_env_opt("REFRESH_INTERVAL")? {
+        Some(interval) => parse_duration(&interval).map_err(|_| {
+            format!(
+                "環境変数 `REFRESH_INTERVAL` (\"{}\") の形式が不正です",
+                interval
+            )
+        })?,
+        None => DEFAULT_REFRESH_INTERVAL,
+    };
+    let count = match get_env_opt("REFRESH_COUNT")? {
+        Some(count) => count
+            .parse()
+            .map_err(|_| format!("環境変数 `REFRESH_COUNT` (\"{}\") の形式が不正です", count))?,
+        None => DEFAULT_REFRESH_COUNT,
+    };
+    let window = match get_env_opt("DELETION_WINDOW")? {
+        Some(window) => parse_duration(&window).map_err(|_| {
+            format!(
+                "環境変数 `DELETION_WINDOW` (\"{}\") の形式が不正です",
+                window
+            )
+        })?,
+        None => DEFAULT_DELETION_WINDOW,
+    };
+    tracing::info!("編集の確認間隔: {} 秒", interval.as_secs());
+    loop {
+        time::sleep(interval).await;
+
+        // 失敗しても次回の確認で再び試みる
+        if let Err(e) = refresh_once(&http, source.as_ref(), store.as_ref(), count, window).await {
+            tracing::warn!("編集の確認に失敗しました: {}", e);
+        }
+    }
+}
+
+/// 最近 `count` 件のアクティビティを 1 回取得し直し、通知したメッセージを更新する。
+/// 一部の購読者で失敗しても、ログに残して残りの購読者の確認を続ける。
+async fn refresh_once(
+    http: &Http,
+    source: &dyn ActivitySource,
+    store: &dyn Store,
+    count: i32,
+    window: Duration,
+) -> Result<()> {
+    let channels: HashMap<_, _> = store
+        .get_channels()
+        .await?
+        .into_iter()
+        .map(|chan| (chan.channel_id, (chan.notify_flag, chan.deleted_action)))
+        .collect();
+    let since = chrono::Utc::now() - window;
+
+    let mut accounts: HashMap<_, Vec<_>> = HashMap::new();
+    for subscriber in store.get_subscribers().await? {
+        accounts
+            .entry(account_key(&subscriber))
+            .or_default()
+            .push(subscriber);
+    }
+
+    for subscribers in accounts.values() {
+        let recent = match source.recent_activities(&subscribers[0], count).await {
+            Ok(recent) => recent,
+            Err(e) => {
+                // 次回の確認で再び取得を試みる
+                tracing::warn!("{}", e);
+                continue;
+            }
+        };
+        // 取得した数が足りない場合は、それより前のアクティビティが無い
+        let oldest = if recent.len() < count as usize {
+            i64::MIN
+        } else {
+            recent.min_id().unwrap_or(i64::MIN)
+        };
+        for subscriber in subscribers {
+            let result = async {
+                refresh_messages(
+                    http,
+                    source,
+                    store,
+                    &channels,
+                    subscriber,
+                    &recent.activities,
+                )
+                .await?;
+                refresh_deleted(
+                    http, source, store, &channels, subscriber, &recent, oldest, since,
+                )
+                .await
+            }
+            .await;
+            if let Err(e) = result {
+                tracing::warn!(
+                    "購読者 {} の通知したメッセージを更新できませんでした: {}",
+                    subscriber.annict_name,
+                    e,
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 購読者 `subscriber` の `activities` を通知したメッセージのうち、内容が変わったものを編集する。
+async fn refresh_messages(
+    http: &Http,
+    source: &dyn ActivitySource,
+    store: &dyn Store,
+    channels: &HashMap<i64, (NotifyFlag, DeletedAction)>,
+    subscriber: &Subscriber,
+    activities: &[Activity],
+) -> Result<()> {
+    let mut member = None;
+    for activity in activities {
+        let delivered = store.get_delivered(subscriber.id, activity.id).await?;
+        if delivered.is_empty() {
+            continue;
+        }
+
+        // 通知したことがある場合だけメンバーを取得する
+        let member = match &member {
+            Some(member) => member,
+            None => match get_member(http, subscriber).await? {
+                Some(found) => member.insert(found),
+                None => return Ok(()),
+            },
+        };
+        let embeds = activity_embeds(
+            member,
+            &source.user_url(&subscriber.annict_name),
+            activity.clone(),
+        );
+
+        for delivered in delivered {
+            // 通知設定が解除されたチャンネルは更新しない
+            let Some((flag, _)) = channels.get(&delivered.channel_id) else {
+                continue;
+            };
+            let embeds = channel_embeds(&embeds, *flag);
+            let content = serde_json::to_string(&embeds)?;
+            if delivered.content.as_deref() == Some(content.as_str())
+                // 通知設定が変わるなどして、メッセージと埋め込みが対応しない場合は更新しない
+                || delivered.message_ids.len() != embeds.len()
+            {
+                continue;
+            }
+
+            let channel_id = ChannelId::new(delivered.channel_id as _);
+            let mut edited = true;
+            for (message_id, embed) in delivered.message_ids.iter().zip(embeds) {
+                let msg = EditMessage::new().embed(embed);
+                if let Err(e) = channel_id
+                    .edit_message(http, MessageId::new(*message_id as _), msg)
                     .await
                 {
-                    Ok(mem) => mem,
-                    Err(serenity::Error::Http(HttpError::UnsuccessfulRequest(e))) => {
-                        match e.error.message.to_ascii_lowercase().as_str() {
-                            "unknown member" => {
-                                tracing::info!(
-                                    "サーバー (ID = {}) にユーザー (ID = {}) が所属していません",
-                                    subscriber.guild_id,
-                                    subscriber.user_id,
-                                );
-                                continue;
-                            }
-                            "unknown user" => {
-                                tracing::info!(
-                                    "ユーザー (ID = {}) が見つかりませんでした",
-                                    subscriber.user_id,
-                                );
-                                continue;
-                            }
-                            "unknown guild" => {
-                                tracing::info!(
-                                    "サーバー (ID = {}) が見つかりませんでした",
-                                    subscriber.guild_id,
-                                );
-                                continue 'chan_loop;
-                            }
-                            _ => {
-                                return Err(serenity::Error::Http(HttpError::UnsuccessfulRequest(
-                                    e,
-                                ))
-                                .into())
-                            }
-                        }
-                    }
-                    Err(e) => return Err(e.into()),
-                };
-                for activity in crate::annict::get_new_activities(&subscriber).await? {
-                    notify_activity(
-                        &http,
-                        &channels_and_flags,
-                        &member,
-                        &subscriber.annict_name,
-                        activity,
+                    // 次回の確認で再び更新を試みる
+                    tracing::warn!("{}", e);
+                    edited = false;
+                    break;
+                }
+            }
+            if edited {
+                store
+                    .update_delivered_content(
+                        subscriber.id,
+                        channel_id.get(),
+                        activity.id,
+                        &content,
                     )
-                    .await;
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 購読者 `subscriber` について `since` 以降に通知したアクティビティのうち、
+/// 取得し直した `recent` に含まれないものを削除されたとみなし、チャンネルの設定に従ってメッセージを更新する。
+/// 中身を取得できなかったアクティビティも `recent` に含まれるものとして扱う。
+///
+/// `recent` より前のアクティビティは確認できないため、ID が `oldest` 未満のものは対象にしない。
+#[allow(clippy::too_many_arguments)]
+async fn refresh_deleted(
+    http: &Http,
+    source: &dyn ActivitySource,
+    store: &dyn Store,
+    channels: &HashMap<i64, (NotifyFlag, DeletedAction)>,
+    subscriber: &Subscriber,
+    recent: &RecentActivities,
+    oldest: i64,
+    since: chrono::DateTime<chrono::Utc>,
+) -> Result<()> {
+    for delivered in store.get_delivered_since(subscriber.id, since).await? {
+        if delivered.activity_id < oldest || recent.contains(delivered.activity_id) {
+            continue;
+        }
+        // 通知設定が解除されたチャンネルは更新しない
+        let Some((_, action)) = channels.get(&delivered.channel_id) else {
+            continue;
+        };
+
+        let channel_id = ChannelId::new(delivered.channel_id as _);
+        let mut updated = true;
+        for message_id in &delivered.message_ids {
+            let message_id = MessageId::new(*message_id as _);
+            if let Err(e) =
+                update_deleted_message(http, source, channel_id, message_id, *action).await
+            {
+                // 次回の確認で再び更新を試みる
+                tracing::warn!("{}", e);
+                updated = false;
+                break;
+            }
+        }
+        if updated {
+            store
+                .mark_delivered_deleted(subscriber.id, channel_id.get(), delivered.activity_id)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// `source` で削除されたアクティビティを通知したメッセージを、`action` に従って更新する。
+async fn update_deleted_message(
+    http: &Http,
+    source: &dyn ActivitySource,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    action: DeletedAction,
+) -> Result<()> {
+    let edit = |embed: CreateEmbed| EditMessage::new().embed(embed);
+    match action {
+        DeletedAction::Keep => {}
+        DeletedAction::Delete => channel_id.delete_message(http, message_id).await?,
+        DeletedAction::Strike => {
+            let message = channel_id.message(http, message_id).await?;
+            let Some(mut embed) = message.embeds.into_iter().next() else {
+                return Ok(());
+            };
+            let strike = |text: &mut String| {
+                if !text.is_empty() {
+                    *text = format!("~~{}~~", text);
                 }
+            };
+            if let Some(title) = &mut embed.title {
+                strike(title);
+            }
+            if let Some(description) = &mut embed.description {
+                strike(description);
             }
+            for field in &mut embed.fields {
+                strike(&mut field.value);
+            }
+            channel_id
+                .edit_message(http, message_id, edit(embed.into()))
+                .await?;
+        }
+        DeletedAction::Mark => {
+            let message = channel_id.message(http, message_id).await?;
+            let Some(embed) = message.embeds.into_iter().next() else {
+                return Ok(());
+            };
+            let footer = match &embed.footer {
+                Some(footer) if !footer.text.is_empty() => {
+                    format!("{} · {} で削除されました", footer.text, source.name())
+                }
+                _ => format!("{} で削除されました", source.name()),
+            };
+            let embed = CreateEmbed::from(embed)
+                .footer(CreateEmbedFooter::new(footer))
+                .colour(Colour::DARK_GREY);
+            channel_id
+                .edit_message(http, message_id, edit(embed))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// 同じアカウントの購読者をまとめるためのキー。
+fn account_key(subscriber: &Subscriber) -> (Option<i64>, Option<String>) {
+    match subscriber.annict_id {
+        Some(annict_id) => (Some(annict_id), None),
+        None => (None, Some(subscriber.annict_name.to_lowercase())),
+    }
+}
+
+/// 通知の対象となる購読者と、その Discord のメンバー、通知先のチャンネル、送信待ちのアクティビティの通知の仕方。
+type Target = (
+    Subscriber,
+    Member,
+    Arc<Vec<(ChannelId, NotifyFlag)>>,
+    Delivery,
+);
+
+/// 送信待ちのアクティビティの通知の仕方。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Delivery {
+    /// 溜まったアクティビティが閾値より多い場合は要約にまとめ、そうでなければ 1 件ずつ通知する。
+    /// 閾値が 0 の場合はまとめない。
+    Threshold(usize),
+
+    /// 要約の時刻になったので、溜まったアクティビティを要約にまとめて通知する。
+    Digest,
+
+    /// 要約の時刻になるまで通知しない。
+    Hold,
+}
+
+impl Delivery {
+    /// 溜まった `count` 件のアクティビティを要約にまとめるか。
+    fn is_digest(self, count: usize) -> bool {
+        match self {
+            Delivery::Threshold(threshold) => threshold > 0 && count > threshold,
+            // 1 件だけの場合はまとめずに通知する
+            Delivery::Digest => count > 1,
+            Delivery::Hold => false,
         }
+    }
+}
 
-        time::sleep(interval).await;
+/// 購読者 `subscriber` のサーバーのメンバーを取得する。
+/// メンバーやサーバーが見つからない場合は `None` を返す。
+async fn get_member(http: &Http, subscriber: &Subscriber) -> Result<Option<Member>> {
+    match http
+        .get_member(
+            GuildId::new(subscriber.guild_id as _),
+            UserId::new(subscriber.user_id as _),
+        )
+        .await
+    {
+        Ok(mem) => Ok(Some(mem)),
+        Err(serenity::Error::Http(HttpError::UnsuccessfulRequest(e))) => {
+            match e.error.message.to_ascii_lowercase().as_str() {
+                "unknown member" => {
+                    tracing::info!(
+                        "サーバー (ID = {}) にユーザー (ID = {}) が所属していません",
+                        subscriber.guild_id,
+                        subscriber.user_id,
+                    );
+                    Ok(None)
+                }
+                "unknown user" => {
+                    tracing::info!(
+                        "ユーザー (ID = {}) が見つかりませんでした",
+                        subscriber.user_id,
+                    );
+                    Ok(None)
+                }
+                "unknown guild" => {
+                    tracing::info!(
+                        "サーバー (ID = {}) が見つかりませんでした",
+                        subscriber.guild_id,
+                    );
+                    Ok(None)
+                }
+                _ => Err(serenity::Error::Http(HttpError::UnsuccessfulRequest(e)).into()),
+            }
+        }
+        Err(e) => Err(e.into()),
     }
 }
 
-pub struct Handler;
+/// `accounts` のアカウントのアクティビティを `source` からまとめて取得して送信待ちに加え、
+/// 購読者ごとに送信待ちのアクティビティを古い順に通知する。
+/// `accounts` はそれぞれ同じアカウントの購読者をまとめたもので、
+/// その数は [ActivitySource::batch_size] 以下にしておくこと。
+///
+/// 送信待ちのアクティビティは、購読者ごとの [Delivery] に従って要約にまとめたり、溜めておいたりする。
+async fn notify_batch(
+    http: &Http,
+    source: &dyn ActivitySource,
+    store: &dyn Store,
+    accounts: Vec<Vec<Target>>,
+) -> Result<()> {
+    let subscribers: Vec<Vec<_>> = accounts
+        .iter()
+        .map(|targets| targets.iter().map(|(subscriber, ..)| subscriber).collect())
+        .collect();
+    let results: Vec<_> = match source.new_activities(&subscribers).await {
+        Ok(results) => results.into_iter().map(Some).collect(),
+        Err(e @ SourceError::Transient(_)) => {
+            // 次回の更新で再び取得を試みるが、送信待ちのものは通知する
+            tracing::warn!("{}", e);
+            accounts.iter().map(|_| None).collect()
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    for (targets, result) in accounts.iter().zip(results) {
+        match result {
+            // 取得したアクティビティを、それぞれのサーバーの購読者について送信待ちに加える
+            // 取得したところも同時に進めるので、途中で失敗しても取りこぼさない
+            Some(Ok(fetched)) => {
+                let mut activities = vec![];
+                for (subscriber, ..) in targets {
+                    let mut new_activities = vec![];
+                    for activity in fetched
+                        .activities
+                        .iter()
+                        .filter(|activity| subscriber.is_new_activity(activity.id))
+                    {
+                        new_activities.push((activity.id, serde_json::to_string(activity)?));
+                    }
+                    activities.push((subscriber.id, new_activities));
+                }
+                store
+                    .enqueue_activities(&activities, fetched.end_cursor.as_deref())
+                    .await?;
+            }
+            Some(Err(SourceError::NotFound)) => {
+                tracing::info!(
+                    "アカウント {} が見つかりませんでした",
+                    targets[0].0.annict_name,
+                );
+            }
+            Some(Err(e @ SourceError::Transient(_))) => {
+                // 次回の更新で再び取得を試みる
+                tracing::warn!("{}", e);
+            }
+            Some(Err(e)) => {
+                // ほかのアカウントの通知は続ける
+                tracing::warn!(
+                    "アカウント {} のアクティビティを取得できませんでした: {}",
+                    targets[0].0.annict_name,
+                    e
+                );
+            }
+            None => {}
+        }
+
+        // 前回までに送信し損ねたものも含めて、送信待ちのアクティビティを通知する
+        for (subscriber, member, channels_and_flags, delivery) in targets {
+            // 要約の時刻になるまでは送信待ちのまま溜めておく
+            if *delivery == Delivery::Hold {
+                continue;
+            }
+            let unsent = store.get_unsent(subscriber.id).await?;
+            let mut activities = vec![];
+            for row in &unsent {
+                match serde_json::from_str::<Activity>(&row.payload) {
+                    Ok(activity) => activities.push(activity),
+                    Err(e) => {
+                        // 読み込めないものは何度試しても読み込めないので、送信済みにして取り除く
+                        tracing::warn!(
+                            "購読者 (ID = {}) の送信待ちのアクティビティ (ID = {}) を読み込めません: {}",
+                            subscriber.id,
+                            row.activity_id,
+                            e
+                        );
+                        store.mark_sent(subscriber.id, &[row.activity_id]).await?;
+                    }
+                }
+            }
+
+            if delivery.is_digest(activities.len()) {
+                let activities: Vec<_> = activities.iter().collect();
+                if notify_digest(
+                    http,
+                    source,
+                    store,
+                    channels_and_flags,
+                    subscriber,
+                    member,
+                    &activities,
+                )
+                .await?
+                {
+                    let ids: Vec<_> = activities.iter().map(|activity| activity.id).collect();
+                    store.mark_sent(subscriber.id, &ids).await?;
+                }
+                continue;
+            }
+
+            for activity in activities {
+                let activity_id = activity.id;
+                if !notify_activity(
+                    http,
+                    source,
+                    store,
+                    channels_and_flags,
+                    subscriber,
+                    member,
+                    activity,
+                )
+                .await?
+                {
+                    // 古い順に通知するため、送信できなかったもの以降は次回にまとめて再び通知する
+                    break;
+                }
+                // Discord に送信し終えてから記録するので、送信できなかったものは次回に再び通知する
+                store.mark_sent(subscriber.id, &[activity_id]).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 購読者 `subscriber` の過去のアクティビティのうち `history` の範囲のものを `source` から取得し、
+/// チャンネル `channel_id` に通知設定 `flag` に従って古い順に通知する。
+/// 既に通知したアクティビティは通知しない。
+///
+/// 新しく通知を記録したアクティビティの数を返す。
+pub async fn backfill(
+    http: &Http,
+    source: &dyn ActivitySource,
+    store: &dyn Store,
+    subscriber: &Subscriber,
+    channel_id: ChannelId,
+    flag: NotifyFlag,
+    history: &History,
+) -> Result<usize> {
+    let member = get_member(http, subscriber)
+        .await?
+        .ok_or("購読者がサーバーのメンバーではありません")?;
+    let activities = source.past_activities(subscriber, history).await?;
+
+    let mut count = 0;
+    for activity in activities {
+        if store
+            .is_delivered(subscriber.id, channel_id.get(), activity.id)
+            .await?
+        {
+            continue;
+        }
+        if !notify_activity(
+            http,
+            source,
+            store,
+            &[(channel_id, flag)],
+            subscriber,
+            &member,
+            activity,
+        )
+        .await?
+        {
+            return Err("Discord への送信に失敗しました".into());
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+pub struct Handler {
+    annict: AnnictClient,
+    oauth: AnnictOAuth,
+    source: Arc<dyn ActivitySource>,
+    store: Arc<dyn Store>,
+}
 
 #[serenity::async_trait]
 impl EventHandler for Handler {
@@ -160,8 +894,19 @@ impl EventHandler for Handler {
         tracing::info!("Discord に {} として接続", ready.user.name);
 
         // スラッシュコマンドの設定
-        match Command::set_global_commands(&ctx.http, vec![notify::register(), annict::register()])
-            .await
+        match Command::set_global_commands(
+            &ctx.http,
+            vec![
+                notify::register(self.source.name()),
+                annict::register(),
+                search::register(),
+                backfill::register(),
+                history::register(),
+                settings::register(),
+                export::register(),
+            ],
+        )
+        .await
         {
             Ok(commands) => {
                 for command in commands {
@@ -172,15 +917,67 @@ impl EventHandler for Handler {
         }
     }
 
+    async fn guild_delete(
+        &self,
+        _ctx: Context,
+        incomplete: UnavailableGuild,
+        _full: Option<Guild>,
+    ) {
+        // 障害で一時的に使えなくなっただけの場合は何もしない
+        if incomplete.unavailable {
+            return;
+        }
+        // サーバーから外されたので、そのサーバーの設定や購読者をすべて削除する
+        tracing::info!("サーバー {} から外されました", incomplete.id);
+        if let Err(e) = self.store.remove_guild(incomplete.id.get()).await {
+            tracing::warn!("{}", e);
+        }
+    }
+
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
         tracing::trace!("interaction {:?} が作成されました", interaction);
-        let Interaction::Command(interaction) = interaction else {
-            return;
+        let interaction = match interaction {
+            Interaction::Command(interaction) => interaction,
+            // 要約のメッセージのボタンはいつ押されるか分からないので、ここで受け付ける
+            Interaction::Component(component)
+                if digest::is_digest_component(&component.data.custom_id) =>
+            {
+                if let Err(e) =
+                    digest::handle_component(&ctx, &component, self.store.as_ref()).await
+                {
+                    tracing::warn!("{}", e);
+                }
+                return;
+            }
+            _ => return,
         };
 
         if let Err(e) = match interaction.data.name.as_str() {
-            notify::NAME => notify::handle(&ctx, &interaction).await,
-            annict::NAME => annict::handle(&ctx, &interaction).await,
+            notify::NAME => {
+                notify::handle(
+                    &ctx,
+                    &interaction,
+                    self.source.as_ref(),
+                    self.store.as_ref(),
+                )
+                .await
+            }
+            annict::NAME => {
+                annict::handle(&ctx, &interaction, &self.oauth, self.store.as_ref()).await
+            }
+            search::NAME => search::handle(&ctx, &interaction, &self.annict).await,
+            backfill::NAME => {
+                backfill::handle(
+                    &ctx,
+                    &interaction,
+                    self.source.as_ref(),
+                    self.store.as_ref(),
+                )
+                .await
+            }
+            history::NAME => history::handle(&ctx, &interaction, self.store.as_ref()).await,
+            settings::NAME => settings::handle(&ctx, &interaction, self.store.as_ref()).await,
+            export::NAME => export::handle(&ctx, &interaction, self.store.as_ref()).await,
             cmd_name => Err(format!("不明なコマンド `{}` を受信", cmd_name).into()),
         } {
             tracing::warn!("{}", e);
@@ -188,74 +985,311 @@ impl EventHandler for Handler {
     }
 }
 
+/// 溜まったアクティビティをまとめて通知する件数の閾値を環境変数 `DIGEST_THRESHOLD` から取得する。
+/// 設定されていない場合は [DEFAULT_DIGEST_THRESHOLD] を返す。
+fn get_digest_threshold() -> Result<usize> {
+    let Some(threshold) = get_env_opt("DIGEST_THRESHOLD")? else {
+        return Ok(DEFAULT_DIGEST_THRESHOLD);
+    };
+    threshold.parse().map_err(|_| {
+        format!(
+            "環境変数 `DIGEST_THRESHOLD` (\"{}\") の形式が不正です",
+            threshold
+        )
+        .into()
+    })
+}
+
+/// 通知の並行数を環境変数 `NOTIFY_CONCURRENCY` から取得する。
+/// 設定されていない場合は [DEFAULT_CONCURRENCY] を返す。
+fn get_concurrency() -> Result<usize> {
+    let Some(concurrency) = get_env_opt("NOTIFY_CONCURRENCY")? else {
+        return Ok(DEFAULT_CONCURRENCY);
+    };
+    match concurrency.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "環境変数 `NOTIFY_CONCURRENCY` (\"{}\") の形式が不正です",
+            concurrency
+        )
+        .into()),
+    }
+}
+
 fn get_interval() -> Result<Duration> {
     let duration = get_env("NOTIFICATION_INTERVAL")?;
-    let regex = Regex::new(r"^\s*(\d+)\s*((?i)s|sec|m|min|h|hour)\s*$")?;
-    let (_, [num, unit]) = regex
-        .captures(&duration)
-        .ok_or_else(|| {
-            format!(
-                "環境変数 `NOTIFICATION_INTERVAL` (\"{}\") の形式が不正です",
-                duration
-            )
-        })?
-        .extract();
-
-    let num: u64 = num.parse()?;
-    Ok(match unit.to_ascii_lowercase().as_str() {
-        "s" | "sec" => Duration::from_secs(num),
-        "m" | "min" => Duration::from_secs(num * 60),
-        "h" | "hour" => Duration::from_secs(num * 60 * 60),
-        _ => unreachable!("正規表現の不正"),
+    parse_duration(&duration).map_err(|_| {
+        format!(
+            "環境変数 `NOTIFICATION_INTERVAL` (\"{}\") の形式が不正です",
+            duration
+        )
+        .into()
     })
 }
 
+/// 購読者 `subscriber` のアクティビティ `activity` を、通知設定に合うチャンネルに通知する。
+/// 通知済みのチャンネルには通知しない。
+/// 投稿したメッセージは通知の履歴に記録する。
+///
+/// すべてのチャンネルについて通知し終えた場合は `true` を返す。
 async fn notify_activity(
     http: &Http,
-    channels_and_flags: &Vec<(ChannelId, NotifyFlag)>,
+    source: &dyn ActivitySource,
+    store: &dyn Store,
+    channels_and_flags: &[(ChannelId, NotifyFlag)],
+    subscriber: &Subscriber,
     member: &Member,
-    username: &str,
-    activity: ActivityItem,
-) {
-    let mut author = CreateEmbedAuthor::new(member.display_name())
-        .url(format!("https://annict.com/@{}", username));
-    // Member.avator_url() はサーバー限定のアバター画像であることもある
-    if let Some(url) = member.avatar_url().or_else(|| member.user.avatar_url()) {
-        author = author.icon_url(url);
+    activity: Activity,
+) -> Result<bool> {
+    let activity_id = activity.id;
+    let items: Vec<_> = activity
+        .items
+        .iter()
+        .map(|item| {
+            (
+                item_flag(item),
+                ActivityType::from(item),
+                item.work().clone(),
+            )
+        })
+        .collect();
+    let embeds = activity_embeds(member, &source.user_url(&subscriber.annict_name), activity);
+
+    let mut all_delivered = true;
+    'chan_loop: for (channel_id, flag) in channels_and_flags {
+        if store
+            .is_delivered(subscriber.id, channel_id.get(), activity_id)
+            .await?
+        {
+            continue;
+        }
+        let embeds = channel_embeds(&embeds, *flag);
+        let mut message_ids = vec![];
+        for embed in &embeds {
+            let msg = CreateMessage::new().add_embed(embed.clone());
+            match channel_id.send_message(http, msg).await {
+                Ok(message) => message_ids.push(message.id.get() as i64),
+                Err(e) => {
+                    // 次回の更新で再び通知を試みる
+                    tracing::warn!("{}", e);
+                    all_delivered = false;
+                    continue 'chan_loop;
+                }
+            }
+        }
+        // メッセージは channel_embeds と同じ順に、通知設定に合う中身ごとに投稿している
+        let notifications: Vec<_> = items
+            .iter()
+            .filter(|(item_flag, ..)| flag.contains(*item_flag))
+            .zip(&message_ids)
+            .map(|((_, activity_type, work), message_id)| NewNotification {
+                subscriber_id: subscriber.id,
+                channel_id: channel_id.get() as _,
+                activity_id,
+                message_id: *message_id,
+                activity_type: *activity_type,
+                work_title: work.title.clone(),
+                work_url: work.url.clone(),
+            })
+            .collect();
+        store
+            .insert_delivered(
+                subscriber.id,
+                channel_id.get(),
+                activity_id,
+                &message_ids,
+                &serde_json::to_string(&embeds)?,
+                &notifications,
+            )
+            .await?;
     }
-    let author = author;
 
-    let mut embed = CreateEmbed::new().author(author);
+    Ok(all_delivered)
+}
 
-    let mut activity_flag = NotifyFlag::empty();
-    match activity {
-        ActivityItem::MultipleRecord(records) => {
-            for edge in records.records.edges {
-                // NOTE: ここ理解する
-                Box::pin(notify_activity(
-                    http,
-                    channels_and_flags,
-                    member,
-                    username,
-                    ActivityItem::Record(edge.node),
-                ))
-                .await;
+/// 購読者 `subscriber` の溜まったアクティビティ `activities` を、チャンネルごとに 1 つの要約にまとめて通知する。
+/// 通知済みのアクティビティは含めない。
+/// それぞれのアクティビティの埋め込みは、要約の「すべて表示」で見られるように記録しておく。
+///
+/// すべてのチャンネルについて通知し終えた場合は `true` を返す。
+async fn notify_digest(
+    http: &Http,
+    source: &dyn ActivitySource,
+    store: &dyn Store,
+    channels_and_flags: &[(ChannelId, NotifyFlag)],
+    subscriber: &Subscriber,
+    member: &Member,
+    activities: &[&Activity],
+) -> Result<bool> {
+    let user_url = source.user_url(&subscriber.annict_name);
+
+    let mut all_delivered = true;
+    for (channel_id, flag) in channels_and_flags {
+        let mut pending = vec![];
+        for activity in activities {
+            if !store
+                .is_delivered(subscriber.id, channel_id.get(), activity.id)
+                .await?
+            {
+                pending.push(*activity);
             }
-            return;
         }
+        if pending.is_empty() {
+            continue;
+        }
+
+        let items: Vec<_> = pending
+            .iter()
+            .flat_map(|activity| &activity.items)
+            .filter(|item| flag.contains(item_flag(item)))
+            .collect();
+        let digest_message_id = if items.is_empty() {
+            None
+        } else {
+            let embed = digest::summary_embed(embed_author(member, &user_url), &items);
+            let msg = CreateMessage::new()
+                .add_embed(embed)
+                .components(vec![digest::show_all_button()]);
+            match channel_id.send_message(http, msg).await {
+                Ok(message) => Some(message.id.get() as i64),
+                Err(e) => {
+                    // 次回の更新で再び通知を試みる
+                    tracing::warn!("{}", e);
+                    all_delivered = false;
+                    continue;
+                }
+            }
+        };
+
+        for activity in pending {
+            let embeds = activity_embeds(member, &user_url, activity.clone());
+            let content = serde_json::to_string(&channel_embeds(&embeds, *flag))?;
+            match digest_message_id {
+                Some(message_id) => {
+                    // 履歴では、要約に含めたそれぞれの中身から要約のメッセージを辿れるようにする
+                    let notifications: Vec<_> = activity
+                        .items
+                        .iter()
+                        .filter(|item| flag.contains(item_flag(item)))
+                        .map(|item| {
+                            let work = item.work();
+                            NewNotification {
+                                subscriber_id: subscriber.id,
+                                channel_id: channel_id.get() as _,
+                                activity_id: activity.id,
+                                message_id,
+                                activity_type: ActivityType::from(item),
+                                work_title: work.title.clone(),
+                                work_url: work.url.clone(),
+                            }
+                        })
+                        .collect();
+                    store
+                        .insert_digested(
+                            subscriber.id,
+                            channel_id.get(),
+                            activity.id,
+                            message_id,
+                            &content,
+                            &notifications,
+                        )
+                        .await?
+                }
+                // 通知設定に合うものが無い場合は、通知したことだけを記録する
+                None => {
+                    store
+                        .insert_delivered(
+                            subscriber.id,
+                            channel_id.get(),
+                            activity.id,
+                            &[],
+                            &content,
+                            &[],
+                        )
+                        .await?
+                }
+            };
+        }
+    }
+
+    Ok(all_delivered)
+}
+
+/// `embeds` のうち、通知設定 `flag` のチャンネルに通知するものを返す。
+fn channel_embeds(embeds: &[(CreateEmbed, NotifyFlag)], flag: NotifyFlag) -> Vec<CreateEmbed> {
+    embeds
+        .iter()
+        .filter(|(_, activity_flag)| flag.contains(*activity_flag))
+        .map(|(embed, _)| embed.clone())
+        .collect()
+}
+
+/// アクティビティ `activity` を、埋め込みとその種類のフラグの列にする。
+/// `user_url` は投稿者のページの URL。
+fn activity_embeds(
+    member: &Member,
+    user_url: &str,
+    activity: Activity,
+) -> Vec<(CreateEmbed, NotifyFlag)> {
+    let author = embed_author(member, user_url);
+    activity
+        .items
+        .into_iter()
+        .map(|item| {
+            let flag = item_flag(&item);
+            (
+                item_embed(CreateEmbed::new().author(author.clone()), item),
+                flag,
+            )
+        })
+        .collect()
+}
+
+/// メンバー `member` を、ページの URL が `user_url` の投稿者として埋め込みに表示する。
+fn embed_author(member: &Member, user_url: &str) -> CreateEmbedAuthor {
+    let author = CreateEmbedAuthor::new(member.display_name()).url(user_url);
+    // Member.avator_url() はサーバー限定のアバター画像であることもある
+    match member.avatar_url().or_else(|| member.user.avatar_url()) {
+        Some(url) => author.icon_url(url),
+        None => author,
+    }
+}
+
+/// アクティビティの中身 `item` の種類のフラグ。
+fn item_flag(item: &ActivityItem) -> NotifyFlag {
+    let (kind, has_comment) = match item {
+        ActivityItem::Record(record) => (
+            NotifyFlag::RECORD,
+            record
+                .comment
+                .as_ref()
+                .is_some_and(|comment| !comment.is_empty()),
+        ),
+        ActivityItem::Review(review) => (NotifyFlag::REVIEW, !review.body.is_empty()),
+        ActivityItem::Status(_) => return NotifyFlag::STATUS,
+    };
+    if has_comment {
+        kind | NotifyFlag::WITH_COMMENT
+    } else {
+        kind | NotifyFlag::WITHOUT_COMMENT
+    }
+}
+
+/// アクティビティの中身 `item` を `embed` に設定する。
+fn item_embed(embed: CreateEmbed, item: ActivityItem) -> CreateEmbed {
+    let mut embed = embed;
+    match item {
         ActivityItem::Record(record) => {
-            activity_flag |= NotifyFlag::RECORD;
+            let episode_url = record.episode.url;
+            embed = with_work_info(embed, &record.work, record.progress);
 
             // 『**タイトル**』
-            let mut desc = format!("『**{}**』", record.work.title);
+            let mut desc = format!("『[**{}**]({})』", record.work.title, record.work.url);
 
             // 『**タイトル**』
             // 第n話
-            let has_number = if let Some(num_text) = record.episode.number_text {
-                desc = format!("{}\n{}", desc, num_text);
-                true
-            } else if let Some(num) = record.episode.number {
-                desc = format!("{}\n第{}話", desc, num);
+            let has_number = if let Some(number) = record.episode.number {
+                desc = format!("{}\n[{}]({})", desc, number, episode_url);
                 true
             } else {
                 false
@@ -266,51 +1300,32 @@ async fn notify_activity(
                 if has_number {
                     desc = format!("{}「{}」", desc, title);
                 } else {
-                    desc = format!("{}\n「{}」", desc, title);
+                    desc = format!("{}\n[「{}」]({})", desc, title, episode_url);
                 }
             }
 
-            if let Some(rating) = record.rating_state {
-                embed = embed.colour(rating.to_colour());
-            } else {
-                embed = embed.color(RatingState::Average.to_colour());
-            }
+            embed = embed.colour(rating_colour(record.rating.unwrap_or(Rating::Average)));
 
-            if let Some(comment) = record.comment {
-                if !comment.is_empty() {
-                    desc = format!("{}\n{}", desc, comment);
-                    activity_flag |= NotifyFlag::WITH_COMMENT;
-                } else {
-                    activity_flag |= NotifyFlag::WITHOUT_COMMENT;
-                }
-            } else {
-                activity_flag |= NotifyFlag::WITHOUT_COMMENT;
+            if let Some(comment) = record.comment.filter(|comment| !comment.is_empty()) {
+                desc = format!("{}\n{}", desc, comment);
             }
 
             embed = embed.description(desc);
         }
         ActivityItem::Review(review) => {
-            activity_flag |= NotifyFlag::REVIEW;
+            embed = with_work_info(embed, &review.work, None);
+            embed = embed.field(
+                "タイトル",
+                format!("[{}]({})", review.work.title, review.work.url),
+                false,
+            );
 
-            embed = embed.field("タイトル", review.work.title, false);
-
-            if let Some(rating) = review.rating_overall_state {
+            if let Some(rating) = review.rating {
                 embed = embed.field("全体", rating.to_string(), true);
-                embed = embed.colour(rating.to_colour());
-            } else {
-                embed = embed.colour(RatingState::Average.to_colour());
-            }
-            if let Some(rating) = review.rating_animation_state {
-                embed = embed.field("映像", rating.to_string(), true);
-            }
-            if let Some(rating) = review.rating_character_state {
-                embed = embed.field("キャラクター", rating.to_string(), true);
             }
-            if let Some(rating) = review.rating_story_state {
-                embed = embed.field("ストーリー", rating.to_string(), true);
-            }
-            if let Some(rating) = review.rating_music_state {
-                embed = embed.field("音楽", rating.to_string(), true);
+            embed = embed.colour(rating_colour(review.rating.unwrap_or(Rating::Average)));
+            for (name, rating) in review.ratings {
+                embed = embed.field(name, rating.to_string(), true);
             }
 
             if !review.body.is_empty() {
@@ -319,28 +1334,81 @@ async fn notify_activity(
                     review.body.chars().take(1024).collect::<String>(),
                     false,
                 );
-                activity_flag |= NotifyFlag::WITH_COMMENT;
-            } else {
-                activity_flag |= NotifyFlag::WITHOUT_COMMENT;
             }
         }
         ActivityItem::Status(status) => {
-            activity_flag |= NotifyFlag::STATUS;
+            embed = with_work_info(embed, &status.work, None);
 
-            // 『**タイトル**』
+            // 『**タイトル**』 (公式サイト)
             // 見た/見たい/一時中断/...
-            embed = embed.description(format!("『**{}**』\n{}", status.work.title, status.state));
-            embed = embed.colour(status.state.to_colour());
+            let mut desc = format!("『[**{}**]({})』", status.work.title, status.work.url);
+            if let Some(url) = &status.work.official_site_url {
+                desc = format!("{} ([公式サイト]({}))", desc, url);
+            }
+            embed = embed.description(format!("{}\n{}", desc, status.label));
+            embed = embed.colour(status_colour(status.state));
         }
     }
-    let embed = embed;
 
-    let msg = CreateMessage::new().add_embed(embed);
-    for channel_id in channels_and_flags
-        .iter()
-        .filter(|(_, flag)| flag.contains(activity_flag))
-        .map(|(chan, _)| chan)
-    {
-        channel_id.send_message(http, msg.clone()).await.unwrap();
+    embed
+}
+
+/// 評価 `rating` を表す色。
+fn rating_colour(rating: Rating) -> Colour {
+    match rating {
+        Rating::Average => Colour::new(0xff6d00),
+        Rating::Bad => Colour::new(0x757575),
+        Rating::Good => Colour::new(0x00c853),
+        Rating::Great => Colour::new(0x00b0ff),
+    }
+}
+
+/// 状況 `state` を表す色。
+fn status_colour(state: StatusState) -> Colour {
+    match state {
+        StatusState::None => Colour::new(0x4dd4ac),
+        StatusState::Paused => Colour::new(0xdc2626),
+        StatusState::Dropped => Colour::new(0x52525b),
+        StatusState::Planning => Colour::new(0xea580c),
+        StatusState::Completed => Colour::new(0x16a34a),
+        StatusState::Current => Colour::new(0x0284c7),
+    }
+}
+
+/// 作品の画像をサムネイルに、放送時期・メディア・視聴の進み具合 `progress` をフッターに設定する。
+/// コマンド `interaction` に、使った本人にだけ見えるエラーのメッセージ `msg` で応答する。
+pub(super) async fn error_response(
+    ctx: &Context,
+    interaction: &CommandInteraction,
+    msg: impl Into<String>,
+) -> Result<()> {
+    interaction
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .ephemeral(true)
+                    .content(msg),
+            ),
+        )
+        .await
+        .map_err(|e| e.into())
+}
+
+fn with_work_info(embed: CreateEmbed, work: &Work, progress: Option<String>) -> CreateEmbed {
+    let mut embed = embed;
+    if let Some(url) = &work.image_url {
+        embed = embed.thumbnail(url);
+    }
+
+    // 2024年秋 · TV · 5/12 話
+    let footer: Vec<_> = [work.season.clone(), work.media.clone(), progress]
+        .into_iter()
+        .flatten()
+        .collect();
+    // 空のフッターは Discord に拒否されるので、何もなければ付けない
+    if footer.is_empty() {
+        return embed;
     }
+    embed.footer(CreateEmbedFooter::new(footer.join(" · ")))
 }
@@ -1,60 +1,104 @@
 use serenity::all::{
-    CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption,
-    CreateInteractionResponse, CreateInteractionResponseMessage,
+    CommandInteraction, Context, CreateCommand, CreateInteractionResponse,
+    CreateInteractionResponseFollowup, CreateInteractionResponseMessage, CreateMessage,
+    Mentionable, RoleId,
 };
+use tokio::time;
 
-use crate::{annict, Result};
+use crate::{
+    annict::{AnnictOAuth, LINK_TTL},
+    store::Store,
+    Result,
+};
 
 pub(super) const NAME: &str = "annict";
 
 pub(super) fn register() -> CreateCommand {
-    let option = CreateCommandOption::new(
-        CommandOptionType::String,
-        "ユーザー名",
-        "連携する Annict アカウントのユーザー名",
-    )
-    .required(true);
-    CreateCommand::new(NAME)
-        .description("Annict アカウントとの連携を行います")
-        .add_option(option)
+    CreateCommand::new(NAME).description("Annict アカウントとの連携を行います")
 }
 
-pub(super) async fn handle(ctx: &Context, interaction: &CommandInteraction) -> Result<()> {
-    let response = CreateInteractionResponseMessage::new();
-
-    let response = if let Some(guild) = &interaction.guild_id {
-        // サーバー内の場合
-        let username = interaction
-            .data
-            .options
-            .first()
-            // 引数は必須になっているから、この unwrap は必ず成功する
-            .unwrap()
-            .value
-            .as_str()
-            // 引数の値は文字列であることが決まっているため、この unwrap は必ず成功する
-            .unwrap();
-
-        if annict::register_user(username, interaction.user.id.get(), guild.get()).await? {
-            // TODO: 既に登録されている場合に変更してもよいか確認する
-            response.content(format!(
-                // プレビューさせないために < > で囲う
-                "ユーザー [{0}](<https://annict.com/@{0}>) と連携しました",
-                username,
-            ))
-        } else {
-            response
-                .content(format!("ユーザー {} は存在しません", username))
-                .ephemeral(true)
-        }
-    } else {
+pub(super) async fn handle(
+    ctx: &Context,
+    interaction: &CommandInteraction,
+    oauth: &AnnictOAuth,
+    store: &dyn Store,
+) -> Result<()> {
+    let response = CreateInteractionResponseMessage::new().ephemeral(true);
+
+    let Some(guild) = &interaction.guild_id else {
         // DM の場合
-        response.content("この操作はサーバー内で行ってください")
+        let response = response.content("この操作はサーバー内で行ってください");
+        interaction
+            .create_response(&ctx.http, CreateInteractionResponse::Message(response))
+            .await?;
+        return Ok(());
     };
 
+    // サーバー内の場合
+    // 連携できるロールが決められているときは、そのロールかサーバーの管理権限を持っている必要がある
+    let link_role = store
+        .get_guild(guild.get())
+        .await?
+        .and_then(|guild| guild.link_role_id)
+        .map(|role| RoleId::new(role as _));
+    if let Some(role) = link_role {
+        let allowed = interaction.member.as_ref().is_some_and(|member| {
+            member.roles.contains(&role)
+                || member
+                    .permissions
+                    .is_some_and(|permissions| permissions.manage_guild())
+        });
+        if !allowed {
+            let response = response.content(format!(
+                "このサーバーで Annict アカウントと連携できるのは {} のメンバーだけです",
+                role.mention()
+            ));
+            interaction
+                .create_response(&ctx.http, CreateInteractionResponse::Message(response))
+                .await?;
+            return Ok(());
+        }
+    }
+
+    // 本人であることを確かめるため、Annict にログインして連携を許可してもらう
+    let (url, receiver) = oauth.start_link(interaction.user.id.get(), guild.get());
+    let link_message = format!(
+        // プレビューさせないために < > で囲う
+        "[こちら](<{}>) から Annict にログインし、連携を許可してください\n\
+            リンクの有効期限は {} 分です",
+        url,
+        LINK_TTL.as_secs() / 60,
+    );
+    let dm = CreateMessage::new().content(&link_message);
+    let response = match interaction.user.direct_message(&ctx.http, dm).await {
+        Ok(_) => response.content("DM に連携用のリンクを送信しました"),
+        // DM を受け付けていない場合は、本人にだけ見えるようにここで送る
+        Err(_) => response.content(link_message),
+    };
     interaction
         .create_response(&ctx.http, CreateInteractionResponse::Message(response))
         .await?;
 
+    let followup = CreateInteractionResponseFollowup::new();
+    let followup = match time::timeout(LINK_TTL, receiver).await {
+        // TODO: 既に登録されている場合に変更してもよいか確認する
+        Ok(Ok(Ok(username))) => followup.content(format!(
+            // プレビューさせないために < > で囲う
+            "ユーザー [{0}](<https://annict.com/@{0}>) と連携しました",
+            username,
+        )),
+        Ok(Ok(Err(e))) => {
+            tracing::info!("{}", e);
+            followup
+                .content("Annict アカウントとの連携に失敗しました")
+                .ephemeral(true)
+        }
+        // 期限切れ
+        Ok(Err(_)) | Err(_) => followup
+            .content("連携用のリンクの有効期限が切れました")
+            .ephemeral(true),
+    };
+    interaction.create_followup(&ctx.http, followup).await?;
+
     Ok(())
 }
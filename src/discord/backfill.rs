@@ -0,0 +1,132 @@
+use serenity::all::{
+    ChannelId, CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption,
+    EditInteractionResponse, Mentionable, Permissions,
+};
+
+use crate::{
+    source::{ActivitySource, History},
+    store::Store,
+    Result,
+};
+
+use super::error_response;
+
+pub(super) const NAME: &str = "backfill";
+
+const MEMBER_OPTION: &str = "メンバー";
+const HISTORY_OPTION: &str = "範囲";
+const CHANNEL_OPTION: &str = "チャンネル";
+
+pub(super) fn register() -> CreateCommand {
+    let member_option = CreateCommandOption::new(
+        CommandOptionType::User,
+        MEMBER_OPTION,
+        "過去のアクティビティを通知するメンバー",
+    )
+    .required(true);
+    let history_option = CreateCommandOption::new(
+        CommandOptionType::String,
+        HISTORY_OPTION,
+        "遡る件数 (例: 20) か、この日以降を遡る日付 (例: 2024-10-01)",
+    )
+    .required(true);
+    let channel_option = CreateCommandOption::new(
+        CommandOptionType::Channel,
+        CHANNEL_OPTION,
+        "通知するチャンネル",
+    );
+    CreateCommand::new(NAME)
+        .description("メンバーの過去のアクティビティを遡ってチャンネルに通知します")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .add_option(member_option)
+        .add_option(history_option)
+        .add_option(channel_option)
+}
+
+pub(super) async fn handle(
+    ctx: &Context,
+    interaction: &CommandInteraction,
+    source: &dyn ActivitySource,
+    store: &dyn Store,
+) -> Result<()> {
+    let Some(guild) = &interaction.guild_id else {
+        // DM の場合
+        return error_response(ctx, interaction, "この操作はサーバー内で行ってください").await;
+    };
+
+    let options = &interaction.data.options;
+    let option = |name| options.iter().find(|opt| opt.name == name);
+    // 必須のユーザーの引数なので、この unwrap は必ず成功する
+    let user = option(MEMBER_OPTION)
+        .and_then(|opt| opt.value.as_user_id())
+        .unwrap();
+    // 必須の文字列の引数なので、この unwrap は必ず成功する
+    let history = option(HISTORY_OPTION)
+        .and_then(|opt| opt.value.as_str())
+        .unwrap();
+    // チャンネルが指定されなかった場合は、現在のチャンネルに通知する
+    let channel = option(CHANNEL_OPTION)
+        .and_then(|opt| opt.value.as_channel_id())
+        .unwrap_or(interaction.channel_id);
+
+    let history: History = match history.parse() {
+        Ok(history) => history,
+        Err(e) => return error_response(ctx, interaction, e).await,
+    };
+
+    let Some(subscriber) = store.get_subscriber(user.get(), guild.get()).await? else {
+        return error_response(
+            ctx,
+            interaction,
+            format!("{} は Annict アカウントと連携していません", user.mention()),
+        )
+        .await;
+    };
+    // 通知設定に従って通知するので、通知設定のあるチャンネルに限る
+    let Some(channel) = store.get_channel(guild.get(), channel.get()).await? else {
+        return error_response(
+            ctx,
+            interaction,
+            format!(
+                "{} は通知設定されていません\n先に /notify で通知設定を行ってください",
+                channel.mention()
+            ),
+        )
+        .await;
+    };
+    let channel_id = ChannelId::new(channel.channel_id as _);
+
+    // 遡るのに時間がかかることがあるので、先に応答しておく
+    interaction.defer_ephemeral(&ctx.http).await?;
+
+    let content = match super::backfill(
+        &ctx.http,
+        source,
+        store,
+        &subscriber,
+        channel_id,
+        channel.notify_flag,
+        &history,
+    )
+    .await
+    {
+        Ok(count) => format!(
+            "{} の過去のアクティビティ {} 件のうち、通知設定に合うものを {} に通知しました",
+            user.mention(),
+            count,
+            channel_id.mention(),
+        ),
+        Err(e) => {
+            tracing::warn!("{}", e);
+            format!(
+                "{} の過去のアクティビティの通知に失敗しました",
+                user.mention()
+            )
+        }
+    };
+    interaction
+        .edit_response(&ctx.http, EditInteractionResponse::new().content(content))
+        .await?;
+
+    Ok(())
+}
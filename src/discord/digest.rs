@@ -0,0 +1,213 @@
+use serenity::all::{
+    ButtonStyle, ComponentInteraction, Context, CreateActionRow, CreateButton, CreateEmbed,
+    CreateEmbedAuthor, CreateInteractionResponse, CreateInteractionResponseMessage, Embed,
+};
+
+use crate::{
+    source::{ActivityItem, Rating},
+    store::Store,
+    Result,
+};
+
+use super::rating_colour;
+
+#[cfg(test)]
+mod test;
+
+/// 要約のメッセージの「すべて表示」のボタン。
+pub(super) const SHOW_ALL_ID: &str = "digest_all";
+
+/// まとめたアクティビティのページ送りのボタン。`digest_page:<要約のメッセージ ID>:<ページ>` の形にする。
+const PAGE_ID_PREFIX: &str = "digest_page:";
+
+/// 1 ページに表示する埋め込みの数。
+const PER_PAGE: usize = 5;
+
+/// 埋め込みに付けられるフィールドの最大数。
+const MAX_FIELDS: usize = 25;
+
+/// `custom_id` が要約のメッセージのボタンか返す。
+pub(super) fn is_digest_component(custom_id: &str) -> bool {
+    custom_id == SHOW_ALL_ID || custom_id.starts_with(PAGE_ID_PREFIX)
+}
+
+/// 要約のメッセージに付けるボタン。
+pub(super) fn show_all_button() -> CreateActionRow {
+    CreateActionRow::Buttons(vec![CreateButton::new(SHOW_ALL_ID)
+        .label("すべて表示")
+        .style(ButtonStyle::Secondary)])
+}
+
+/// 作品ごとのアクティビティの要約。
+struct WorkSummary<'a> {
+    title: &'a str,
+    url: &'a str,
+    records: usize,
+    first_episode: Option<&'a str>,
+    last_episode: Option<&'a str>,
+    reviews: usize,
+    status: Option<&'a str>,
+    best_rating: Option<Rating>,
+}
+
+impl WorkSummary<'_> {
+    fn to_field_value(&self) -> String {
+        let mut lines = vec![];
+        if self.records > 0 {
+            let episodes = match (self.first_episode, self.last_episode) {
+                (Some(first), Some(last)) if first != last => format!(" ({}〜{})", first, last),
+                (Some(episode), _) | (_, Some(episode)) => format!(" ({})", episode),
+                (None, None) => String::new(),
+            };
+            lines.push(format!("エピソード記録 {} 件{}", self.records, episodes));
+        }
+        if self.reviews > 0 {
+            lines.push(format!("作品記録 {} 件", self.reviews));
+        }
+        if let Some(status) = self.status {
+            lines.push(format!("ステータス: {}", status));
+        }
+        if let Some(rating) = self.best_rating {
+            lines.push(format!("最高評価: {}", rating));
+        }
+        lines.push(format!("[作品ページ]({})", self.url));
+        lines.join("\n")
+    }
+}
+
+/// 古い順のアクティビティの中身 `items` をまとめた、作品ごとの要約の埋め込みを作る。
+pub(super) fn summary_embed(author: CreateEmbedAuthor, items: &[&ActivityItem]) -> CreateEmbed {
+    let mut works: Vec<WorkSummary> = vec![];
+    for item in items {
+        let work = item.work();
+        // 最初に出てきた順に並べる
+        let summary = match works.iter().position(|summary| summary.url == work.url) {
+            Some(i) => &mut works[i],
+            None => {
+                works.push(WorkSummary {
+                    title: &work.title,
+                    url: &work.url,
+                    records: 0,
+                    first_episode: None,
+                    last_episode: None,
+                    reviews: 0,
+                    status: None,
+                    best_rating: None,
+                });
+                works.last_mut().unwrap()
+            }
+        };
+
+        let rating = match item {
+            ActivityItem::Record(record) => {
+                summary.records += 1;
+                if let Some(number) = &record.episode.number {
+                    summary.first_episode.get_or_insert(number);
+                    summary.last_episode = Some(number);
+                }
+                record.rating
+            }
+            ActivityItem::Review(review) => {
+                summary.reviews += 1;
+                review.rating
+            }
+            ActivityItem::Status(status) => {
+                summary.status = Some(&status.label);
+                None
+            }
+        };
+        summary.best_rating = summary.best_rating.max(rating);
+    }
+
+    let best_rating = works.iter().filter_map(|work| work.best_rating).max();
+    let mut embed = CreateEmbed::new()
+        .author(author)
+        .description(format!(
+            "{} 件のアクティビティをまとめて表示しています",
+            items.len()
+        ))
+        .colour(rating_colour(best_rating.unwrap_or(Rating::Average)));
+
+    // フィールドの数に収まらない場合は、最後のフィールドを残りの作品の数にする
+    let shown = if works.len() > MAX_FIELDS {
+        MAX_FIELDS - 1
+    } else {
+        works.len()
+    };
+    for work in &works[..shown] {
+        let title: String = work.title.chars().take(256).collect();
+        embed = embed.field(title, work.to_field_value(), false);
+    }
+    if shown < works.len() {
+        embed = embed.field(
+            "その他",
+            format!("ほか {} 作品", works.len() - shown),
+            false,
+        );
+    }
+    embed
+}
+
+/// 要約のメッセージのボタンが押されたときに、まとめたアクティビティを本人にだけ 1 ページずつ表示する。
+pub(super) async fn handle_component(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    store: &dyn Store,
+) -> Result<()> {
+    let custom_id = component.data.custom_id.as_str();
+    let (digest_message_id, page, update) = match custom_id.strip_prefix(PAGE_ID_PREFIX) {
+        Some(rest) => {
+            let (message_id, page) = rest
+                .split_once(':')
+                .and_then(|(id, page)| Some((id.parse().ok()?, page.parse().ok()?)))
+                .ok_or_else(|| format!("不正なボタン `{}`", custom_id))?;
+            (message_id, page, true)
+        }
+        None => (component.message.id.get() as i64, 0, false),
+    };
+
+    let mut embeds = vec![];
+    for delivered in store
+        .get_digested(component.channel_id.get(), digest_message_id)
+        .await?
+    {
+        let Some(content) = &delivered.content else {
+            continue;
+        };
+        let digested: Vec<Embed> = serde_json::from_str(content)?;
+        embeds.extend(digested.into_iter().map(CreateEmbed::from));
+    }
+
+    let pages = embeds.len().div_ceil(PER_PAGE).max(1);
+    let page = page.min(pages - 1);
+    let response = CreateInteractionResponseMessage::new()
+        .content(format!("{}/{} ページ", page + 1, pages))
+        .embeds(
+            embeds
+                .into_iter()
+                .skip(page * PER_PAGE)
+                .take(PER_PAGE)
+                .collect(),
+        )
+        .components(vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(page_id(digest_message_id, page.saturating_sub(1)))
+                .label("前へ")
+                .disabled(page == 0),
+            CreateButton::new(page_id(digest_message_id, page + 1))
+                .label("次へ")
+                .disabled(page + 1 >= pages),
+        ])])
+        .ephemeral(true);
+    let response = if update {
+        CreateInteractionResponse::UpdateMessage(response)
+    } else {
+        CreateInteractionResponse::Message(response)
+    };
+    component.create_response(&ctx.http, response).await?;
+
+    Ok(())
+}
+
+fn page_id(digest_message_id: i64, page: usize) -> String {
+    format!("{}{}:{}", PAGE_ID_PREFIX, digest_message_id, page)
+}
@@ -0,0 +1,101 @@
+use chrono::Local;
+use serenity::all::{CreateEmbed, CreateEmbedAuthor, Embed};
+
+use crate::source::{ActivityItem, Episode, Rating, Record, Status, StatusState, Work};
+
+use super::summary_embed;
+
+fn work(id: i64) -> Work {
+    Work {
+        title: format!("作品{}", id),
+        url: format!("https://annict.com/works/{}", id),
+        image_url: None,
+        season: None,
+        media: None,
+        official_site_url: None,
+    }
+}
+
+fn record(work_id: i64, number: i64, rating: Option<Rating>) -> ActivityItem {
+    ActivityItem::Record(Record {
+        work: work(work_id),
+        episode: Episode {
+            url: format!("https://annict.com/works/{}/episodes/{}", work_id, number),
+            number: Some(format!("第{}話", number)),
+            title: None,
+        },
+        progress: None,
+        rating,
+        comment: None,
+    })
+}
+
+#[test]
+fn summary_embed_test() -> crate::Result<()> {
+    let items = [
+        record(1, 1, Some(Rating::Good)),
+        record(2, 5, None),
+        record(1, 2, Some(Rating::Great)),
+        record(1, 3, Some(Rating::Bad)),
+        ActivityItem::Status(Status {
+            work: work(2),
+            state: StatusState::Completed,
+            label: "見た".into(),
+        }),
+    ];
+    let items: Vec<_> = items.iter().collect();
+    let embed = summary_embed(CreateEmbedAuthor::new("kei"), &items);
+    let embed: Embed = serde_json::from_value(serde_json::to_value(&embed)?)?;
+
+    assert_eq!(
+        embed.description.as_deref(),
+        Some("5 件のアクティビティをまとめて表示しています")
+    );
+    // 作品ごとに、最初に出てきた順に並ぶ
+    assert_eq!(embed.fields.len(), 2);
+    assert_eq!(embed.fields[0].name, "作品1");
+    assert_eq!(
+        embed.fields[0].value,
+        "エピソード記録 3 件 (第1話〜第3話)\n最高評価: とても良い\n[作品ページ](https://annict.com/works/1)"
+    );
+    assert_eq!(embed.fields[1].name, "作品2");
+    assert_eq!(
+        embed.fields[1].value,
+        "エピソード記録 1 件 (第5話)\nステータス: 見た\n[作品ページ](https://annict.com/works/2)"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn summary_embed_too_many_works_test() -> crate::Result<()> {
+    let items: Vec<_> = (0..30).map(|id| record(id, 1, None)).collect();
+    let items: Vec<_> = items.iter().collect();
+    let embed = summary_embed(CreateEmbedAuthor::new("kei"), &items);
+    let embed: Embed = serde_json::from_value(serde_json::to_value(&embed)?)?;
+
+    assert_eq!(embed.fields.len(), 25);
+    assert_eq!(embed.fields[24].value, "ほか 6 作品");
+
+    Ok(())
+}
+
+#[test]
+fn embed_round_trip_test() -> crate::Result<()> {
+    // 記録した埋め込みは「すべて表示」で Embed として読み戻す
+    let embed = CreateEmbed::new()
+        .author(CreateEmbedAuthor::new("kei").url("https://annict.com/@kei519"))
+        .description("説明")
+        .thumbnail("https://example.com/image.png")
+        .field("名前", "値", true)
+        .timestamp(Local::now());
+    let content = serde_json::to_string(&vec![embed.clone()])?;
+    let embeds: Vec<Embed> = serde_json::from_str(&content)?;
+    assert_eq!(embeds.len(), 1);
+    assert_eq!(
+        serde_json::to_value(CreateEmbed::from(embeds[0].clone()))?,
+        serde_json::to_value(embed)?
+    );
+
+    Ok(())
+}
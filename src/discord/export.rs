@@ -0,0 +1,51 @@
+use serenity::all::{
+    CommandInteraction, Context, CreateAttachment, CreateCommand,
+    CreateInteractionResponseFollowup, Permissions,
+};
+
+use crate::{store::Store, Result};
+
+use super::error_response;
+
+pub(super) const NAME: &str = "export";
+
+pub(super) fn register() -> CreateCommand {
+    CreateCommand::new(NAME)
+        .description("このサーバーの通知設定・購読者・通知の記録を JSON で書き出します")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+}
+
+pub(super) async fn handle(
+    ctx: &Context,
+    interaction: &CommandInteraction,
+    store: &dyn Store,
+) -> Result<()> {
+    let Some(guild) = &interaction.guild_id else {
+        // DM の場合
+        return error_response(ctx, interaction, "この操作はサーバー内で行ってください").await;
+    };
+
+    // 書き出しには時間がかかることがあるので、先に応答しておく
+    interaction.defer_ephemeral(&ctx.http).await?;
+
+    let mut snapshot = store.export(Some(guild.get())).await?;
+    // 購読者のアクセストークンはサーバーの管理者にも渡さない
+    snapshot.remove_access_tokens();
+    let json = serde_json::to_vec_pretty(&snapshot)?;
+
+    let followup = CreateInteractionResponseFollowup::new()
+        .content(format!(
+            "チャンネル {} 件・購読者 {} 件・通知の履歴 {} 件を書き出しました",
+            snapshot.channels.len(),
+            snapshot.subscribers.len(),
+            snapshot.notifications.len(),
+        ))
+        .add_file(CreateAttachment::bytes(
+            json,
+            format!("annict-notify-{}.json", guild),
+        ))
+        .ephemeral(true);
+    interaction.create_followup(&ctx.http, followup).await?;
+
+    Ok(())
+}
@@ -0,0 +1,312 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use serenity::all::{
+    ChannelId, CommandInteraction, CommandOptionType, Context, CreateActionRow, CreateButton,
+    CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponse,
+    CreateInteractionResponseMessage, EditInteractionResponse, GuildId, Member, Mentionable,
+    MessageId, UserId,
+};
+
+use crate::{
+    models::{Guild, Notification},
+    store::{NotificationFilter, Store},
+    Result,
+};
+
+use super::error_response;
+
+pub(super) const NAME: &str = "history";
+
+const MEMBER_OPTION: &str = "メンバー";
+const WORK_OPTION: &str = "作品";
+const SINCE_OPTION: &str = "開始日";
+const UNTIL_OPTION: &str = "終了日";
+
+/// 1 ページに表示する通知の数。
+const PER_PAGE: i64 = 10;
+
+/// ボタンの操作を受け付ける時間。
+const TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+const PREV_ID: &str = "history_prev";
+const NEXT_ID: &str = "history_next";
+
+pub(super) fn register() -> CreateCommand {
+    let member_option = CreateCommandOption::new(
+        CommandOptionType::User,
+        MEMBER_OPTION,
+        "このメンバーのアクティビティの通知に絞り込む",
+    );
+    let work_option = CreateCommandOption::new(
+        CommandOptionType::String,
+        WORK_OPTION,
+        "タイトルにこの文字列を含む作品の通知に絞り込む",
+    );
+    let since_option = CreateCommandOption::new(
+        CommandOptionType::String,
+        SINCE_OPTION,
+        "この日以降の通知に絞り込む (例: 2024-10-01)",
+    );
+    let until_option = CreateCommandOption::new(
+        CommandOptionType::String,
+        UNTIL_OPTION,
+        "この日までの通知に絞り込む (例: 2024-10-31)",
+    );
+    CreateCommand::new(NAME)
+        .description("通知したアクティビティの履歴を表示します")
+        .add_option(member_option)
+        .add_option(work_option)
+        .add_option(since_option)
+        .add_option(until_option)
+}
+
+pub(super) async fn handle(
+    ctx: &Context,
+    interaction: &CommandInteraction,
+    store: &dyn Store,
+) -> Result<()> {
+    let Some(guild) = interaction.guild_id else {
+        // DM の場合
+        return error_response(ctx, interaction, "この操作はサーバー内で行ってください").await;
+    };
+
+    // 日付はサーバーのタイムゾーンで解釈する
+    let offset = store
+        .get_guild(guild.get())
+        .await?
+        .unwrap_or_else(|| Guild::new(guild.get()))
+        .utc_offset();
+
+    let options = &interaction.data.options;
+    let option = |name| options.iter().find(|opt| opt.name == name);
+    let user = option(MEMBER_OPTION).and_then(|opt| opt.value.as_user_id());
+    let work_title = option(WORK_OPTION)
+        .and_then(|opt| opt.value.as_str())
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty());
+    let date = |name| {
+        option(name)
+            .and_then(|opt| opt.value.as_str())
+            .map(|s| parse_date(s, offset))
+    };
+    let since = match date(SINCE_OPTION).transpose() {
+        Ok(since) => since,
+        Err(e) => return error_response(ctx, interaction, e).await,
+    };
+    // 終了日はその日の終わりまでを含める
+    let until = match date(UNTIL_OPTION).transpose() {
+        Ok(until) => until.map(|until| until + chrono::Duration::days(1)),
+        Err(e) => return error_response(ctx, interaction, e).await,
+    };
+    if let (Some(since), Some(until)) = (since, until) {
+        if since >= until {
+            return error_response(ctx, interaction, "開始日は終了日以前にしてください").await;
+        }
+    }
+
+    // 履歴は使った本人にだけ表示する
+    interaction.defer_ephemeral(&ctx.http).await?;
+
+    // 使った本人が見られないチャンネルへの通知は表示しない
+    let Some(member) = &interaction.member else {
+        // サーバー内なので必ずある
+        return Err("コマンドを使ったメンバーの情報がありません".into());
+    };
+    let filter = NotificationFilter {
+        user_id: user.map(|user| user.get()),
+        work_title,
+        channel_ids: Some(viewable_channels(ctx, guild, member).await?),
+        since,
+        until,
+    };
+
+    let users: HashMap<_, _> = store
+        .get_subscribers_by_guild(guild.get())
+        .await?
+        .into_iter()
+        .map(|subscriber| (subscriber.id, UserId::new(subscriber.user_id as _)))
+        .collect();
+
+    let mut page_number = 1;
+    let (mut notifications, mut has_next) = get_page(store, guild, &filter, page_number).await?;
+    if notifications.is_empty() {
+        interaction
+            .edit_response(
+                &ctx.http,
+                EditInteractionResponse::new().content("条件に合う通知はありません"),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let message = interaction
+        .edit_response(
+            &ctx.http,
+            EditInteractionResponse::new()
+                .embed(render(guild, &users, page_number, &notifications))
+                .components(buttons(page_number, has_next, false)),
+        )
+        .await?;
+
+    while let Some(component) = message
+        .await_component_interaction(&ctx.shard)
+        .author_id(interaction.user.id)
+        .timeout(TIMEOUT)
+        .await
+    {
+        let number = match component.data.custom_id.as_str() {
+            PREV_ID => page_number - 1,
+            NEXT_ID => page_number + 1,
+            // 知らないボタンは無視する
+            _ => continue,
+        };
+
+        let (new_notifications, new_has_next) = get_page(store, guild, &filter, number).await?;
+        if new_notifications.is_empty() {
+            // 前のページは必ずあるので、次のページを表示している間に履歴が減った場合にだけ来る
+            let response = CreateInteractionResponseMessage::new()
+                .content("ページを取得できませんでした")
+                .ephemeral(true);
+            component
+                .create_response(&ctx.http, CreateInteractionResponse::Message(response))
+                .await?;
+            continue;
+        }
+        page_number = number;
+        notifications = new_notifications;
+        has_next = new_has_next;
+
+        let response = CreateInteractionResponseMessage::new()
+            .embed(render(guild, &users, page_number, &notifications))
+            .components(buttons(page_number, has_next, false));
+        component
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::UpdateMessage(response),
+            )
+            .await?;
+    }
+
+    // 時間切れになったらボタンを無効にする
+    interaction
+        .edit_response(
+            &ctx.http,
+            EditInteractionResponse::new().components(buttons(page_number, has_next, true)),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// サーバー `guild` のチャンネルとスレッドのうち、メンバー `member` が見られるものの ID を返す。
+/// スレッドは親のチャンネルを見られる場合に見られるものとする。
+async fn viewable_channels(ctx: &Context, guild: GuildId, member: &Member) -> Result<Vec<u64>> {
+    let partial_guild = guild.to_partial_guild(&ctx.http).await?;
+    let channels = guild.channels(&ctx.http).await?;
+    let mut viewable: Vec<_> = channels
+        .values()
+        .filter(|channel| {
+            partial_guild
+                .user_permissions_in(channel, member)
+                .view_channel()
+        })
+        .map(|channel| channel.id)
+        .collect();
+
+    let threads = guild.get_active_threads(&ctx.http).await?.threads;
+    let viewable_threads: Vec<_> = threads
+        .iter()
+        .filter(|thread| {
+            thread
+                .parent_id
+                .is_some_and(|parent| viewable.contains(&parent))
+        })
+        .map(|thread| thread.id)
+        .collect();
+    viewable.extend(viewable_threads);
+
+    Ok(viewable.into_iter().map(|id| id.get()).collect())
+}
+
+/// `YYYY-MM-DD` 形式の日付を、UTC からのオフセットが `offset` のタイムゾーンでのその日の始まりの日時として解釈する。
+fn parse_date(s: &str, offset: FixedOffset) -> std::result::Result<DateTime<Utc>, String> {
+    let s = s.trim();
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| {
+            date.and_hms_opt(0, 0, 0)?
+                .and_local_timezone(offset)
+                .single()
+        })
+        .map(|date| date.to_utc())
+        .ok_or_else(|| format!("日付 (\"{}\") は YYYY-MM-DD 形式にしてください", s))
+}
+
+/// サーバー `guild` の `filter` に合う通知の履歴の `page_number` ページ目と、次のページがあるかを返す。
+async fn get_page(
+    store: &dyn Store,
+    guild: GuildId,
+    filter: &NotificationFilter,
+    page_number: i64,
+) -> Result<(Vec<Notification>, bool)> {
+    // 次のページがあるか調べるために 1 件多く取得する
+    let mut notifications = store
+        .get_notifications(
+            guild.get(),
+            filter,
+            (page_number - 1) * PER_PAGE,
+            PER_PAGE + 1,
+        )
+        .await?;
+    let has_next = notifications.len() as i64 > PER_PAGE;
+    notifications.truncate(PER_PAGE as _);
+    Ok((notifications, has_next))
+}
+
+/// 通知の履歴のページ `notifications` を、1 行に 1 件の埋め込みにする。
+/// `users` は購読者の ID から Discord のユーザーを引くためのもの。
+fn render(
+    guild: GuildId,
+    users: &HashMap<i32, UserId>,
+    page_number: i64,
+    notifications: &[Notification],
+) -> CreateEmbed {
+    let lines: Vec<_> = notifications
+        .iter()
+        .map(|notification| {
+            let user = users
+                .get(&notification.subscriber_id)
+                .map(|user| format!("{} ", user.mention()))
+                .unwrap_or_default();
+            let title: String = notification.work_title.chars().take(100).collect();
+            let link = MessageId::new(notification.message_id as _)
+                .link(ChannelId::new(notification.channel_id as _), Some(guild));
+            format!(
+                "<t:{}:f> {}{}『[{}]({})』 ([メッセージ]({}))",
+                notification.notified_at.timestamp(),
+                user,
+                notification.activity_type,
+                title,
+                notification.work_url,
+                link,
+            )
+        })
+        .collect();
+    CreateEmbed::new()
+        .title(format!("通知の履歴 ({} ページ目)", page_number))
+        .description(lines.join("\n"))
+}
+
+/// `page_number` ページ目のページ送りのボタン。
+/// `disabled` が `true` の場合は、前後のページの有無に関わらず無効にする。
+fn buttons(page_number: i64, has_next: bool, disabled: bool) -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(PREV_ID)
+            .label("前へ")
+            .disabled(disabled || page_number <= 1),
+        CreateButton::new(NEXT_ID)
+            .label("次へ")
+            .disabled(disabled || !has_next),
+    ])]
+}
@@ -5,25 +5,47 @@ use serenity::all::{
     CreateSelectMenuKind, CreateSelectMenuOption, Mentionable, Permissions,
 };
 
-use crate::{db, Result};
+use crate::{source::ActivitySource, store::Store, Result};
 
-use super::NotifyFlag;
+use super::{error_response, DeletedAction, NotifyFlag};
 
 pub(super) const NAME: &str = "notify";
 
-pub(super) fn register() -> CreateCommand {
-    let option = CreateCommandOption::new(
+const CHANNEL_OPTION: &str = "チャンネル";
+const DELETED_OPTION: &str = "削除時";
+
+/// `source_name` はアクティビティの取得元の名前。
+pub(super) fn register(source_name: &str) -> CreateCommand {
+    let channel_option = CreateCommandOption::new(
         CommandOptionType::Channel,
-        "チャンネル",
+        CHANNEL_OPTION,
         "通知を行うチャンネル",
     );
+    let deleted_option = CreateCommandOption::new(
+        CommandOptionType::Integer,
+        DELETED_OPTION,
+        format!(
+            "{} でアクティビティが削除されたときの、通知したメッセージの扱い",
+            source_name
+        ),
+    )
+    .add_int_choice("何もしない", DeletedAction::Keep as _)
+    .add_int_choice("削除する", DeletedAction::Delete as _)
+    .add_int_choice("取り消し線を引く", DeletedAction::Strike as _)
+    .add_int_choice("削除されたと表示する", DeletedAction::Mark as _);
     CreateCommand::new(NAME)
         .description("通知を行うチャンネルを登録します")
         .default_member_permissions(Permissions::MANAGE_GUILD)
-        .add_option(option)
+        .add_option(channel_option)
+        .add_option(deleted_option)
 }
 
-pub(super) async fn handle(ctx: &Context, interaction: &CommandInteraction) -> Result<()> {
+pub(super) async fn handle(
+    ctx: &Context,
+    interaction: &CommandInteraction,
+    source: &dyn ActivitySource,
+    store: &dyn Store,
+) -> Result<()> {
     let Some(guild) = &interaction.guild_id else {
         // DM の場合
         return error_response(ctx, interaction, "この操作はサーバー内で行ってください").await;
@@ -32,12 +54,19 @@ pub(super) async fn handle(ctx: &Context, interaction: &CommandInteraction) -> R
     let channel = interaction
         .data
         .options
-        .first()
-        // このコマンドが引数を受け付けた場合、その値はチャンネルであることが決まっているため、
-        // この unwrap は必ず成功する
+        .iter()
+        .find(|opt| opt.name == CHANNEL_OPTION)
+        // この引数の値はチャンネルであることが決まっているため、この unwrap は必ず成功する
         .map(|opt| opt.value.as_channel_id().unwrap())
         // チャンネルが指定されなかった場合は、現在のチャンネルで設定する
         .unwrap_or_else(|| interaction.channel_id);
+    let deleted_action = interaction
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == DELETED_OPTION)
+        // 選択肢から選ばれるので、この unwrap は必ず成功する
+        .map(|opt| DeletedAction::try_from(opt.value.as_i64().unwrap() as i32).unwrap());
 
     // チャンネルがテキストチャンネルに類するか確認
     // サーバー内のチャンネルであることは分かっているので、unwrap は成功
@@ -64,18 +93,16 @@ pub(super) async fn handle(ctx: &Context, interaction: &CommandInteraction) -> R
     interaction.defer_ephemeral(&ctx.http).await?;
 
     // 通知するアクティビティの種類を選ばせる
-    let options = vec![
-        CreateSelectMenuOption::new("感想あり", "with_comment"),
-        CreateSelectMenuOption::new("エピソード記録", "record"),
-        CreateSelectMenuOption::new("作品記録", "review"),
-        CreateSelectMenuOption::new("感想なし", "without_comment"),
-        CreateSelectMenuOption::new("ステータス更新", "status"),
-    ];
-    let num_options = options.len() as _;
-    let select_menu = CreateSelectMenu::new("", CreateSelectMenuKind::String { options })
-        .placeholder("通知するアクティビティの種類")
-        .min_values(0)
-        .max_values(num_options);
+    // 通知設定済みのチャンネルはその設定を、新しいチャンネルはサーバーの既定の設定を選んだ状態にしておく
+    let current_flag = match store.get_channel(guild.get(), channel.get()).await? {
+        Some(channel) => channel.notify_flag,
+        None => store
+            .get_guild(guild.get())
+            .await?
+            .map(|guild| guild.default_notify_flag)
+            .unwrap_or_default(),
+    };
+    let select_menu = flag_select_menu(current_flag);
 
     let response = CreateInteractionResponseFollowup::new()
         .content(
@@ -102,8 +129,7 @@ pub(super) async fn handle(ctx: &Context, interaction: &CommandInteraction) -> R
     if selected_flags.is_empty() {
         let response = CreateInteractionResponseMessage::new();
 
-        let mut conn = db::connect()?;
-        let response = if db::remove_channel(&mut conn, guild.get(), channel.get())? {
+        let response = if store.remove_channel(guild.get(), channel.get()).await? {
             // TODO: 解除しても良いか確認
             response.content(format!("{} の通知設定を解除しました", channel.mention()))
         } else {
@@ -120,6 +146,79 @@ pub(super) async fn handle(ctx: &Context, interaction: &CommandInteraction) -> R
         return Ok(());
     }
 
+    let notify_flag = parse_selected_flags(selected_flags);
+    let updated = store
+        .insert_or_update_channel(guild.get(), channel.get(), notify_flag)
+        .await?;
+    // 指定されなかった場合は、これまでの設定のままにする
+    let deleted_action = match deleted_action {
+        Some(deleted_action) => {
+            store
+                .update_deleted_action(guild.get(), channel.get(), deleted_action)
+                .await?
+                .deleted_action
+        }
+        None => updated.deleted_action,
+    };
+
+    // TODO: チャンネルの変更が伴う場合は、確認を行う
+    let deleted_text = match deleted_action {
+        DeletedAction::Keep => String::new(),
+        DeletedAction::Delete => format!(
+            "\n{} で削除されたアクティビティの通知は削除します",
+            source.name()
+        ),
+        DeletedAction::Strike => format!(
+            "\n{} で削除されたアクティビティの通知には取り消し線を引きます",
+            source.name()
+        ),
+        DeletedAction::Mark => format!(
+            "\n{} で削除されたアクティビティの通知にはその旨を表示します",
+            source.name()
+        ),
+    };
+    let response = CreateInteractionResponseMessage::new().content(format!(
+        "{} で {} のアクティビティを通知します{}",
+        channel.mention(),
+        flags_text(notify_flag),
+        deleted_text,
+    ));
+
+    component
+        .create_response(&ctx.http, CreateInteractionResponse::Message(response))
+        .await?;
+
+    Ok(())
+}
+
+/// 通知するアクティビティの種類を選ぶセレクトメニュー。`selected` の種類を選んだ状態にしておく。
+pub(super) fn flag_select_menu(selected: NotifyFlag) -> CreateSelectMenu {
+    // 感想の有無はどちらか一方だけのときに選ぶ
+    let comment_only = selected.contains(NotifyFlag::WITH_COMMENT)
+        && !selected.contains(NotifyFlag::WITHOUT_COMMENT);
+    let without_comment_only = selected.contains(NotifyFlag::WITHOUT_COMMENT)
+        && !selected.contains(NotifyFlag::WITH_COMMENT);
+    let options = vec![
+        CreateSelectMenuOption::new("感想あり", "with_comment").default_selection(comment_only),
+        CreateSelectMenuOption::new("エピソード記録", "record")
+            .default_selection(selected.contains(NotifyFlag::RECORD)),
+        CreateSelectMenuOption::new("作品記録", "review")
+            .default_selection(selected.contains(NotifyFlag::REVIEW)),
+        CreateSelectMenuOption::new("感想なし", "without_comment")
+            .default_selection(without_comment_only),
+        CreateSelectMenuOption::new("ステータス更新", "status")
+            .default_selection(selected.contains(NotifyFlag::STATUS)),
+    ];
+    let num_options = options.len() as _;
+    CreateSelectMenu::new("", CreateSelectMenuKind::String { options })
+        .placeholder("通知するアクティビティの種類")
+        .min_values(0)
+        .max_values(num_options)
+}
+
+/// [flag_select_menu] で選ばれた `selected_flags` を、通知するアクティビティの種類にする。
+/// 知らない選択肢は無視する。
+pub(super) fn parse_selected_flags(selected_flags: &[String]) -> NotifyFlag {
     let mut notify_flag = NotifyFlag::empty();
     for selected in selected_flags {
         match selected.as_str() {
@@ -128,7 +227,7 @@ pub(super) async fn handle(ctx: &Context, interaction: &CommandInteraction) -> R
             "review" => notify_flag |= NotifyFlag::REVIEW,
             "without_comment" => notify_flag |= NotifyFlag::WITHOUT_COMMENT,
             "status" => notify_flag |= NotifyFlag::STATUS,
-            s => unreachable!("unknown select menu option {}", s),
+            s => tracing::warn!("不明な通知の種類 {} を無視します", s),
         }
     }
 
@@ -161,10 +260,12 @@ pub(super) async fn handle(ctx: &Context, interaction: &CommandInteraction) -> R
         }
     }
 
-    let mut conn = db::connect()?;
-    db::insert_or_update_channel(&mut conn, guild.get(), channel.get(), notify_flag)?;
+    notify_flag
+}
 
-    let flags_text = if notify_flag.is_all() {
+/// 通知するアクティビティの種類 `notify_flag` を説明する文字列。
+pub(super) fn flags_text(notify_flag: NotifyFlag) -> String {
+    if notify_flag.is_all() {
         "全て".into()
     } else {
         let about_comment =
@@ -190,36 +291,5 @@ pub(super) async fn handle(ctx: &Context, interaction: &CommandInteraction) -> R
         }
 
         flags_strs.join("・")
-    };
-
-    // TODO: チャンネルの変更が伴う場合は、確認を行う
-    let response = CreateInteractionResponseMessage::new().content(format!(
-        "{} で {} のアクティビティを通知します",
-        channel.mention(),
-        flags_text,
-    ));
-
-    component
-        .create_response(&ctx.http, CreateInteractionResponse::Message(response))
-        .await?;
-
-    Ok(())
-}
-
-async fn error_response(
-    ctx: &Context,
-    interaction: &CommandInteraction,
-    msg: impl Into<String>,
-) -> Result<()> {
-    interaction
-        .create_response(
-            &ctx.http,
-            CreateInteractionResponse::Message(
-                CreateInteractionResponseMessage::new()
-                    .ephemeral(true)
-                    .content(msg),
-            ),
-        )
-        .await
-        .map_err(|e| e.into())
+    }
 }
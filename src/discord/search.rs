@@ -0,0 +1,208 @@
+use std::time::Duration;
+
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateActionRow, CreateButton, CreateCommand,
+    CreateCommandOption, CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage,
+    EditInteractionResponse,
+};
+
+use crate::{
+    annict::{self, AnnictClient, PageCursor, WorkPage},
+    Result,
+};
+
+use super::with_work_info;
+
+#[cfg(test)]
+mod test;
+
+pub(super) const NAME: &str = "search";
+
+/// 1 ページに表示する作品の数。
+const PER_PAGE: i64 = 5;
+
+/// ボタンの操作を受け付ける時間。
+const TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+const PREV_ID: &str = "search_prev";
+const NEXT_ID: &str = "search_next";
+
+pub(super) fn register() -> CreateCommand {
+    let option = CreateCommandOption::new(
+        CommandOptionType::String,
+        "タイトル",
+        "検索する作品のタイトル",
+    )
+    .required(true);
+    CreateCommand::new(NAME)
+        .description("Annict で作品を検索します")
+        .add_option(option)
+}
+
+pub(super) async fn handle(
+    ctx: &Context,
+    interaction: &CommandInteraction,
+    client: &AnnictClient,
+) -> Result<()> {
+    let title = interaction
+        .data
+        .options
+        .first()
+        // 必須の文字列の引数なので、この unwrap は必ず成功する
+        .map(|opt| opt.value.as_str().unwrap())
+        .unwrap_or_default()
+        .to_string();
+
+    // 検索に時間がかかることがあるので、先に応答しておく
+    interaction.defer(&ctx.http).await?;
+
+    let mut page_number = 1;
+    let mut page_cursor = PageCursor::First;
+    let mut page = match annict::search_works(client, &title, &PageCursor::First, PER_PAGE).await {
+        Ok(page) => page,
+        Err(e) => {
+            tracing::warn!("{}", e);
+            interaction
+                .edit_response(
+                    &ctx.http,
+                    EditInteractionResponse::new().content("検索に失敗しました"),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+    if page.works.is_empty() {
+        interaction
+            .edit_response(
+                &ctx.http,
+                EditInteractionResponse::new()
+                    .content(format!("「{}」に一致する作品は見つかりませんでした", title)),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let (content, embeds) = render(&title, page_number, &page);
+    let message = interaction
+        .edit_response(
+            &ctx.http,
+            EditInteractionResponse::new()
+                .content(content)
+                .embeds(embeds)
+                .components(buttons(page_number, &page_cursor, &page, false)),
+        )
+        .await?;
+
+    // ページ送りのボタンはコマンドを使った本人だけが操作できる
+    while let Some(component) = message
+        .await_component_interaction(&ctx.shard)
+        .author_id(interaction.user.id)
+        .timeout(TIMEOUT)
+        .await
+    {
+        let (cursor, number) = match component.data.custom_id.as_str() {
+            PREV_ID => (
+                page.page_info.start_cursor.clone().map(PageCursor::Before),
+                page_number - 1,
+            ),
+            NEXT_ID => (
+                page.page_info.end_cursor.clone().map(PageCursor::After),
+                page_number + 1,
+            ),
+            // 知らないボタンは無視する
+            _ => continue,
+        };
+        // カーソルが無い場合はボタンが無効になっているので来ない
+        let Some(cursor) = cursor else {
+            continue;
+        };
+
+        match annict::search_works(client, &title, &cursor, PER_PAGE).await {
+            Ok(new_page) if !new_page.works.is_empty() => {
+                page = new_page;
+                page_number = number;
+                page_cursor = cursor;
+                let (content, embeds) = render(&title, page_number, &page);
+                let response = CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .embeds(embeds)
+                    .components(buttons(page_number, &page_cursor, &page, false));
+                component
+                    .create_response(
+                        &ctx.http,
+                        CreateInteractionResponse::UpdateMessage(response),
+                    )
+                    .await?;
+            }
+            result => {
+                if let Err(e) = result {
+                    tracing::warn!("{}", e);
+                }
+                let response = CreateInteractionResponseMessage::new()
+                    .content("ページを取得できませんでした")
+                    .ephemeral(true);
+                component
+                    .create_response(&ctx.http, CreateInteractionResponse::Message(response))
+                    .await?;
+            }
+        }
+    }
+
+    // 時間切れになったらボタンを無効にする
+    interaction
+        .edit_response(
+            &ctx.http,
+            EditInteractionResponse::new().components(buttons(
+                page_number,
+                &page_cursor,
+                &page,
+                true,
+            )),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// 検索結果のページ `page` を、本文と作品ごとの埋め込みにする。
+fn render(title: &str, page_number: usize, page: &WorkPage) -> (String, Vec<CreateEmbed>) {
+    let content = format!("「{}」の検索結果 ({} ページ目)", title, page_number);
+    let embeds = page
+        .works
+        .iter()
+        .map(|work| {
+            let embed = CreateEmbed::new().title(&work.title).url(work.url());
+            let embed = if work.episodes_count > 0 {
+                embed.description(format!("全 {} 話", work.episodes_count))
+            } else {
+                embed
+            };
+            with_work_info(embed, &work.clone().into(), None)
+        })
+        .collect();
+    (content, embeds)
+}
+
+/// `cursor` で取得した `page_number` ページ目の `page` のページ送りのボタン。
+/// `disabled` が `true` の場合は、前後のページの有無に関わらず無効にする。
+fn buttons(
+    page_number: usize,
+    cursor: &PageCursor,
+    page: &WorkPage,
+    disabled: bool,
+) -> Vec<CreateActionRow> {
+    // 前のページを取得した場合、has_next_page は常に false になるが、
+    // 取得する前に表示していた次のページがある
+    let has_next_page = match cursor {
+        PageCursor::Before(_) => true,
+        PageCursor::First | PageCursor::After(_) => page.page_info.has_next_page,
+    };
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(PREV_ID)
+            .label("前へ")
+            .disabled(disabled || page_number <= 1),
+        CreateButton::new(NEXT_ID)
+            .label("次へ")
+            .disabled(disabled || !has_next_page),
+    ])]
+}
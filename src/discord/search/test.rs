@@ -0,0 +1,55 @@
+use serde_json::Value;
+
+use crate::annict::{PageCursor, PageInfo, WorkPage};
+
+use super::{buttons, NEXT_ID, PREV_ID};
+
+fn page(has_next_page: bool, has_previous_page: bool) -> WorkPage {
+    WorkPage {
+        works: vec![],
+        page_info: PageInfo {
+            has_next_page,
+            has_previous_page,
+            start_cursor: Some("start".into()),
+            end_cursor: Some("end".into()),
+        },
+    }
+}
+
+/// `page_number` ページ目の `page` のボタンについて、前へと次へが無効になっているかを返す。
+fn disabled(cursor: &PageCursor, page_number: usize, page: &WorkPage) -> (bool, bool) {
+    let rows = serde_json::to_value(buttons(page_number, cursor, page, false)).unwrap();
+    let find = |id: &str| {
+        rows[0]["components"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|button| button["custom_id"] == id)
+            .map(|button| button["disabled"] == Value::Bool(true))
+            .unwrap()
+    };
+    (find(PREV_ID), find(NEXT_ID))
+}
+
+#[test]
+fn buttons_test() {
+    // 最初のページ
+    assert_eq!(
+        disabled(&PageCursor::First, 1, &page(true, false)),
+        (true, false)
+    );
+    // 最後のページ
+    assert_eq!(
+        disabled(&PageCursor::After("end".into()), 2, &page(false, true)),
+        (false, true)
+    );
+    // 前へで戻ったページは、has_next_page が false でも次のページがある
+    assert_eq!(
+        disabled(&PageCursor::Before("start".into()), 1, &page(false, true)),
+        (true, false)
+    );
+    assert_eq!(
+        disabled(&PageCursor::Before("start".into()), 2, &page(false, true)),
+        (false, false)
+    );
+}
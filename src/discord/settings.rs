@@ -0,0 +1,248 @@
+use chrono::FixedOffset;
+use serenity::all::{
+    CommandInteraction, CommandOptionType, ComponentInteractionDataKind, Context, CreateCommand,
+    CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseFollowup,
+    CreateInteractionResponseMessage, Mentionable, Permissions, RoleId,
+};
+
+use crate::{models::Guild, store::Store, Result};
+
+use super::{
+    error_response,
+    notify::{flag_select_menu, flags_text, parse_selected_flags},
+};
+
+pub(super) const NAME: &str = "settings";
+
+const LOCALE_OPTION: &str = "言語";
+const TIMEZONE_OPTION: &str = "タイムゾーン";
+const NOTIFY_FLAG_OPTION: &str = "既定の通知の種類";
+const DIGEST_OPTION: &str = "要約の件数";
+const DIGEST_HOUR_OPTION: &str = "要約の時刻";
+const DIGEST_NOW_OPTION: &str = "すぐに通知する";
+const LINK_ROLE_OPTION: &str = "連携できるロール";
+const LINK_ANYONE_OPTION: &str = "全員が連携できる";
+
+/// 対応している言語の名前とコード。
+const LOCALES: &[(&str, &str)] = &[("日本語", "ja")];
+
+pub(super) fn register() -> CreateCommand {
+    let mut locale_option = CreateCommandOption::new(
+        CommandOptionType::String,
+        LOCALE_OPTION,
+        "通知やコマンドの応答の言語",
+    );
+    for (name, code) in LOCALES {
+        locale_option = locale_option.add_string_choice(*name, *code);
+    }
+    let timezone_option = CreateCommandOption::new(
+        CommandOptionType::String,
+        TIMEZONE_OPTION,
+        "日付を解釈するタイムゾーンの UTC からのオフセット (例: +09:00)",
+    );
+    let notify_flag_option = CreateCommandOption::new(
+        CommandOptionType::Boolean,
+        NOTIFY_FLAG_OPTION,
+        "新しく通知設定するチャンネルで最初に選ばれている通知の種類を選び直す",
+    );
+    let digest_option = CreateCommandOption::new(
+        CommandOptionType::Integer,
+        DIGEST_OPTION,
+        "溜まったアクティビティがこの件数より多いときに要約にまとめる (0 でまとめない)",
+    )
+    .min_int_value(0);
+    let digest_hour_option = CreateCommandOption::new(
+        CommandOptionType::Integer,
+        DIGEST_HOUR_OPTION,
+        "アクティビティを溜めておき、毎日この時刻 (0〜23 時) に要約にまとめて通知する",
+    )
+    .min_int_value(0)
+    .max_int_value(23);
+    let digest_now_option = CreateCommandOption::new(
+        CommandOptionType::Boolean,
+        DIGEST_NOW_OPTION,
+        "要約の時刻を決めずに、アクティビティをすぐに通知する",
+    );
+    let link_role_option = CreateCommandOption::new(
+        CommandOptionType::Role,
+        LINK_ROLE_OPTION,
+        "Annict アカウントとの連携をこのロールのメンバーに限る",
+    );
+    let link_anyone_option = CreateCommandOption::new(
+        CommandOptionType::Boolean,
+        LINK_ANYONE_OPTION,
+        "Annict アカウントとの連携をロールで制限しない",
+    );
+    CreateCommand::new(NAME)
+        .description("サーバーの設定を表示・変更します")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .add_option(locale_option)
+        .add_option(timezone_option)
+        .add_option(notify_flag_option)
+        .add_option(digest_option)
+        .add_option(digest_hour_option)
+        .add_option(digest_now_option)
+        .add_option(link_role_option)
+        .add_option(link_anyone_option)
+}
+
+pub(super) async fn handle(
+    ctx: &Context,
+    interaction: &CommandInteraction,
+    store: &dyn Store,
+) -> Result<()> {
+    let Some(guild_id) = interaction.guild_id else {
+        // DM の場合
+        return error_response(ctx, interaction, "この操作はサーバー内で行ってください").await;
+    };
+    let mut guild = store
+        .get_guild(guild_id.get())
+        .await?
+        .unwrap_or_else(|| Guild::new(guild_id.get()));
+
+    let options = &interaction.data.options;
+    let option = |name| options.iter().find(|opt| opt.name == name);
+    if let Some(locale) = option(LOCALE_OPTION).and_then(|opt| opt.value.as_str()) {
+        guild.locale = locale.into();
+    }
+    if let Some(timezone) = option(TIMEZONE_OPTION).and_then(|opt| opt.value.as_str()) {
+        let timezone = timezone.trim();
+        if timezone.parse::<FixedOffset>().is_err() {
+            return error_response(
+                ctx,
+                interaction,
+                format!(
+                    "タイムゾーン (\"{}\") は +09:00 のような UTC からのオフセットにしてください",
+                    timezone
+                ),
+            )
+            .await;
+        }
+        guild.timezone = timezone.into();
+    }
+    if let Some(threshold) = option(DIGEST_OPTION).and_then(|opt| opt.value.as_i64()) {
+        guild.digest_threshold = Some(threshold.try_into().unwrap_or(i32::MAX));
+    }
+    let digest_hour = option(DIGEST_HOUR_OPTION).and_then(|opt| opt.value.as_i64());
+    let digest_now = option(DIGEST_NOW_OPTION).and_then(|opt| opt.value.as_bool());
+    match (digest_hour, digest_now) {
+        (Some(_), Some(true)) => {
+            return error_response(
+                ctx,
+                interaction,
+                format!(
+                    "{} と {} は同時に指定できません",
+                    DIGEST_HOUR_OPTION, DIGEST_NOW_OPTION
+                ),
+            )
+            .await;
+        }
+        // 0〜23 の範囲に制限しているので、変換は必ず成功する
+        (Some(hour), _) => guild.digest_hour = Some(hour as _),
+        (None, Some(true)) => guild.digest_hour = None,
+        (None, _) => {}
+    }
+    let link_role = option(LINK_ROLE_OPTION).and_then(|opt| opt.value.as_role_id());
+    let link_anyone = option(LINK_ANYONE_OPTION).and_then(|opt| opt.value.as_bool());
+    match (link_role, link_anyone) {
+        (Some(_), Some(true)) => {
+            return error_response(
+                ctx,
+                interaction,
+                format!(
+                    "{} と {} は同時に指定できません",
+                    LINK_ROLE_OPTION, LINK_ANYONE_OPTION
+                ),
+            )
+            .await;
+        }
+        (Some(role), _) => guild.link_role_id = Some(role.get() as _),
+        (None, Some(true)) => guild.link_role_id = None,
+        (None, _) => {}
+    }
+
+    let choose_flag = option(NOTIFY_FLAG_OPTION)
+        .and_then(|opt| opt.value.as_bool())
+        .unwrap_or(false);
+    if !choose_flag {
+        // 何も指定されなかった場合は、今の設定を本人にだけ表示する
+        let response = if options.is_empty() {
+            CreateInteractionResponseMessage::new()
+                .content(settings_text(&guild)?)
+                .ephemeral(true)
+        } else {
+            let guild = store.update_guild(&guild).await?;
+            CreateInteractionResponseMessage::new().content(format!(
+                "サーバーの設定を変更しました\n{}",
+                settings_text(&guild)?
+            ))
+        };
+        interaction
+            .create_response(&ctx.http, CreateInteractionResponse::Message(response))
+            .await?;
+        return Ok(());
+    }
+
+    // 既定の通知の種類は /notify と同じセレクトメニューで選ばせる
+    interaction.defer_ephemeral(&ctx.http).await?;
+    let response = CreateInteractionResponseFollowup::new()
+        .content("新しく通知設定するチャンネルで最初に選ばれている通知の種類を選択してください")
+        .select_menu(flag_select_menu(guild.default_notify_flag).min_values(1));
+    let message = interaction.create_followup(&ctx.http, response).await?;
+
+    let Some(component) = message.await_component_interaction(&ctx.shard).await else {
+        return Ok(());
+    };
+    let ComponentInteractionDataKind::StringSelect { values } = &component.data.kind else {
+        // セレクトメニューの作り方的にここには来ないが、来た場合は何も変更しない
+        return Ok(());
+    };
+    guild.default_notify_flag = parse_selected_flags(values);
+    let guild = store.update_guild(&guild).await?;
+
+    let response = CreateInteractionResponseMessage::new().content(format!(
+        "サーバーの設定を変更しました\n{}",
+        settings_text(&guild)?
+    ));
+    component
+        .create_response(&ctx.http, CreateInteractionResponse::Message(response))
+        .await?;
+
+    Ok(())
+}
+
+/// サーバーの設定 `guild` を説明する文字列。
+fn settings_text(guild: &Guild) -> Result<String> {
+    let locale = LOCALES
+        .iter()
+        .find(|(_, code)| *code == guild.locale)
+        .map_or(guild.locale.as_str(), |(name, _)| name);
+    let digest = match guild.digest_threshold {
+        Some(0) => "まとめない".to_string(),
+        Some(threshold) => format!("{} 件より多いとき", threshold),
+        None => match super::get_digest_threshold()? {
+            0 => "まとめない (既定)".to_string(),
+            threshold => format!("{} 件より多いとき (既定)", threshold),
+        },
+    };
+    let digest_hour = match guild.digest_hour {
+        Some(hour) => format!("毎日 {} 時にまとめて通知する", hour),
+        None => "すぐに通知する".to_string(),
+    };
+    let link = match guild.link_role_id {
+        Some(role) => format!("{} のメンバー", RoleId::new(role as _).mention()),
+        None => "全員".to_string(),
+    };
+    Ok(format!(
+        "{}: {}\n{}: UTC{}\n新しいチャンネルで通知する種類: {}\n要約にまとめる: {}\n{}: {}\nAnnict アカウントと連携できる: {}",
+        LOCALE_OPTION,
+        locale,
+        TIMEZONE_OPTION,
+        guild.utc_offset(),
+        flags_text(guild.default_notify_flag),
+        digest,
+        DIGEST_HOUR_OPTION,
+        digest_hour,
+        link,
+    ))
+}
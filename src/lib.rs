@@ -1,30 +1,163 @@
 use std::{
     env::{self, VarError},
     error::Error,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    sync::Arc,
+    time::Duration,
 };
 
-use diesel::{Connection, PgConnection};
-use diesel_migrations::{EmbeddedMigrations, MigrationHarness};
+use annict::{AnnictClient, AnnictOAuth, AnnictSource};
+use regex::Regex;
+use serenity::all::{ChannelId, Http};
+use snapshot::Snapshot;
+use source::{ActivitySource, History};
+use store::Store;
 
 pub mod annict;
 pub mod db;
 pub mod discord;
 pub mod models;
 mod schema;
+pub mod snapshot;
+pub mod source;
+#[cfg(feature = "sqlite")]
+mod sqlite_schema;
+pub mod store;
 
 pub type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
 
-const MIGRATIONS: EmbeddedMigrations = diesel_migrations::embed_migrations!("./migrations");
-
 pub async fn main() -> Result<()> {
-    // 反映されていないマイグレーションの実行
-    let mut conn = PgConnection::establish(&get_env("DATABASE_URL")?)?;
-    conn.run_pending_migrations(MIGRATIONS)?;
+    let pool = db::pool_from_env()?;
+    db::run_migrations(&pool).await?;
+    let store: Arc<dyn Store> = Arc::new(pool);
+
+    let annict = AnnictClient::from_env()?;
+    annict::backfill_annict_ids(&annict, store.as_ref()).await?;
+    let oauth = AnnictOAuth::from_env()?;
+    let callback_addr = get_env_opt("OAUTH_CALLBACK_ADDR")?
+        .unwrap_or_else(|| "0.0.0.0:8080".into())
+        .parse()
+        .map_err(|_| "環境変数 `OAUTH_CALLBACK_ADDR` の形式が不正です")?;
+
+    let source: Arc<dyn ActivitySource> =
+        Arc::new(AnnictSource::new(annict.clone(), store.clone()));
+    let (discord_monitor_task, http) =
+        discord::start(annict.clone(), oauth.clone(), source.clone(), store.clone()).await?;
+    let notify_task = discord::notify(http.clone(), source.clone(), store.clone());
+    let refresh_task = discord::refresh(http, source, store.clone());
+    let callback_task = oauth.serve(annict, store, callback_addr);
+
+    tokio::try_join!(
+        discord_monitor_task,
+        notify_task,
+        refresh_task,
+        callback_task
+    )?;
+
+    Ok(())
+}
+
+/// コマンドライン引数 `args` (`<サーバー ID> <ユーザー ID> <チャンネル ID> <範囲>`) で指定した購読者の
+/// 過去のアクティビティを遡って、通知設定のあるチャンネルに通知する。
+/// 範囲は件数 (`20` など) か、その日以降を遡る日付 (`2024-10-01` など) で指定する。
+pub async fn backfill(args: &[String]) -> Result<()> {
+    const USAGE: &str =
+        "使い方: backfill <サーバー ID> <ユーザー ID> <チャンネル ID> <件数|YYYY-MM-DD>";
+    let [guild_id, user_id, channel_id, history] = args else {
+        return Err(USAGE.into());
+    };
+    let guild_id: u64 = guild_id.parse().map_err(|_| USAGE)?;
+    let user_id: u64 = user_id.parse().map_err(|_| USAGE)?;
+    let channel_id: u64 = channel_id.parse().map_err(|_| USAGE)?;
+    let history: History = history.parse()?;
 
-    let (discord_monitor_task, http) = discord::start().await?;
-    let notify_task = discord::notify(http);
+    let pool = db::pool_from_env()?;
+    db::run_migrations(&pool).await?;
+    let store: Arc<dyn Store> = Arc::new(pool);
+    let subscriber = store
+        .get_subscriber(user_id, guild_id)
+        .await?
+        .ok_or_else(|| {
+            format!(
+                "ユーザー (ID = {}) は Annict アカウントと連携していません",
+                user_id
+            )
+        })?;
+    let channel = store
+        .get_channel(guild_id, channel_id)
+        .await?
+        .ok_or_else(|| format!("チャンネル (ID = {}) は通知設定されていません", channel_id))?;
 
-    tokio::try_join!(discord_monitor_task, notify_task)?;
+    let source = AnnictSource::new(AnnictClient::from_env()?, store.clone());
+    let http = Http::new(&get_env("DISCORD_TOKEN")?);
+    let count = discord::backfill(
+        &http,
+        &source,
+        store.as_ref(),
+        &subscriber,
+        ChannelId::new(channel_id),
+        channel.notify_flag,
+        &history,
+    )
+    .await?;
+    tracing::info!("{} 件のアクティビティを新しく通知しました", count);
+
+    Ok(())
+}
+
+/// コマンドライン引数 `args` (`<ファイル> [<サーバー ID>]`) で指定したファイルに、
+/// ボットの状態を JSON で書き出す。サーバー ID を指定した場合は、そのサーバーに関するものだけを書き出す。
+pub async fn export(args: &[String]) -> Result<()> {
+    const USAGE: &str = "使い方: export <ファイル> [<サーバー ID>]";
+    let (path, guild_id) = match args {
+        [path] => (path, None),
+        [path, guild_id] => (path, Some(guild_id.parse().map_err(|_| USAGE)?)),
+        _ => return Err(USAGE.into()),
+    };
+
+    let pool = db::pool_from_env()?;
+    db::run_migrations(&pool).await?;
+    let snapshot = pool.export(guild_id).await?;
+
+    let file =
+        File::create(path).map_err(|e| format!("ファイル {} を作成できません: {}", path, e))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, &snapshot)?;
+    writer.flush()?;
+    tracing::info!(
+        "{} にサーバー {} 件・チャンネル {} 件・購読者 {} 件を書き出しました",
+        path,
+        snapshot.guilds.len(),
+        snapshot.channels.len(),
+        snapshot.subscribers.len(),
+    );
+
+    Ok(())
+}
+
+/// コマンドライン引数 `args` (`<ファイル>`) で指定した、[export] で書き出したファイルを読み込む。
+/// 同じファイルを何度読み込んでも結果は変わらない。
+pub async fn import(args: &[String]) -> Result<()> {
+    const USAGE: &str = "使い方: import <ファイル>";
+    let [path] = args else {
+        return Err(USAGE.into());
+    };
+
+    let file = File::open(path).map_err(|e| format!("ファイル {} を開けません: {}", path, e))?;
+    let snapshot: Snapshot = serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("ファイル {} の形式が不正です: {}", path, e))?;
+
+    let pool = db::pool_from_env()?;
+    db::run_migrations(&pool).await?;
+    pool.import(&snapshot).await?;
+    tracing::info!(
+        "{} からサーバー {} 件・チャンネル {} 件・購読者 {} 件を読み込みました",
+        path,
+        snapshot.guilds.len(),
+        snapshot.channels.len(),
+        snapshot.subscribers.len(),
+    );
 
     Ok(())
 }
@@ -39,3 +172,34 @@ pub fn get_env(key: impl AsRef<str>) -> Result<String> {
         other => Ok(other?),
     }
 }
+
+/// 環境変数 `key` を取り出す。
+/// 存在しなかった場合は `None` を返す。
+pub fn get_env_opt(key: impl AsRef<str>) -> Result<Option<String>> {
+    match env::var(key.as_ref()) {
+        Err(VarError::NotPresent) => Ok(None),
+        other => Ok(Some(other?)),
+    }
+}
+
+/// `"500ms"`, `"30s"`, `"5 min"`, `"1h"` のような文字列を [Duration] に変換する。
+/// 長さが 0 の場合はエラーを返す。
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let regex = Regex::new(r"^\s*(\d+)\s*((?i)ms|msec|s|sec|m|min|h|hour)\s*$")?;
+    let (_, [num, unit]) = regex
+        .captures(s)
+        .ok_or_else(|| format!("時間の形式 (\"{}\") が不正です", s))?
+        .extract();
+
+    let num: u64 = num.parse()?;
+    if num == 0 {
+        return Err(format!("時間 (\"{}\") が 0 です", s).into());
+    }
+    Ok(match unit.to_ascii_lowercase().as_str() {
+        "ms" | "msec" => Duration::from_millis(num),
+        "s" | "sec" => Duration::from_secs(num),
+        "m" | "min" => Duration::from_secs(num * 60),
+        "h" | "hour" => Duration::from_secs(num * 60 * 60),
+        _ => unreachable!("正規表現の不正"),
+    })
+}
@@ -19,7 +19,15 @@ async fn main() -> ExitCode {
         .with(level_filter)
         .init();
 
-    if let Err(e) = annict_notify::main().await {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        None => annict_notify::main().await,
+        Some("backfill") => annict_notify::backfill(&args[1..]).await,
+        Some("export") => annict_notify::export(&args[1..]).await,
+        Some("import") => annict_notify::import(&args[1..]).await,
+        Some(command) => Err(format!("不明なサブコマンド `{}` です", command).into()),
+    };
+    if let Err(e) = result {
         tracing::error!("{}", e);
         1.into()
     } else {
@@ -1,37 +1,209 @@
-use chrono::{DateTime, Utc};
-use diesel::prelude::{Insertable, Queryable};
+use chrono::{DateTime, FixedOffset, Timelike, Utc};
+use custom_debug::Debug;
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    prelude::Queryable,
+    serialize::{self, Output, ToSql},
+    sql_types::{Array, BigInt},
+};
 
-use crate::discord::NotifyFlag;
+use crate::discord::{ActivityType, DeletedAction, NotifyFlag};
 
-use super::schema::*;
-
-#[derive(Debug, Queryable, Insertable, PartialEq, Eq)]
-#[diesel(table_name = channels)]
+#[derive(Debug, Clone, Queryable, PartialEq, Eq)]
 pub struct Channel {
     pub guild_id: i64,
     pub channel_id: i64,
 
-    #[diesel(serialize_as = i32)]
     #[diesel(deserialize_as = i32)]
     pub notify_flag: NotifyFlag,
+
+    #[diesel(deserialize_as = i32)]
+    pub deleted_action: DeletedAction,
+}
+
+/// サーバーごとの設定。
+#[derive(Debug, Clone, Queryable, PartialEq, Eq)]
+pub struct Guild {
+    pub guild_id: i64,
+    pub locale: String,
+    /// `+09:00` のような UTC からのオフセット。
+    pub timezone: String,
+    /// 新しく通知設定するチャンネルで最初に選ばれている通知の種類。
+    #[diesel(deserialize_as = i32)]
+    pub default_notify_flag: NotifyFlag,
+    /// 溜まったアクティビティをまとめて通知する件数の閾値。
+    /// `None` の場合は環境変数 `DIGEST_THRESHOLD` に従い、0 の場合はまとめない。
+    pub digest_threshold: Option<i32>,
+    /// 溜まったアクティビティを要約にまとめて通知する時刻 (サーバーのタイムゾーンで 0〜23 時)。
+    /// 設定されている場合は、毎日この時刻になるまで通知せずに溜めておく。
+    /// `None` の場合はすぐに通知する。
+    pub digest_hour: Option<i32>,
+    /// Annict アカウントと連携できるロール。`None` の場合は誰でも連携できる。
+    pub link_role_id: Option<i64>,
+}
+
+impl Guild {
+    /// 言語の既定値。現在は日本語のみに対応している。
+    pub const DEFAULT_LOCALE: &'static str = "ja";
+
+    /// タイムゾーンの既定値。
+    pub const DEFAULT_TIMEZONE: &'static str = "+09:00";
+
+    /// 設定を変更していないサーバー `guild_id` の設定。
+    pub fn new(guild_id: u64) -> Self {
+        Self {
+            guild_id: guild_id as _,
+            locale: Self::DEFAULT_LOCALE.into(),
+            timezone: Self::DEFAULT_TIMEZONE.into(),
+            default_notify_flag: NotifyFlag::default(),
+            digest_threshold: None,
+            digest_hour: None,
+            link_role_id: None,
+        }
+    }
+
+    /// タイムゾーンの UTC からのオフセット。
+    /// 保存されているタイムゾーンが不正な場合は、ログに残して既定値を返す。
+    pub fn utc_offset(&self) -> FixedOffset {
+        self.timezone.parse().unwrap_or_else(|_| {
+            tracing::warn!(
+                "サーバー (ID = {}) のタイムゾーン (\"{}\") が不正なので {} とみなします",
+                self.guild_id,
+                self.timezone,
+                Self::DEFAULT_TIMEZONE,
+            );
+            // 定数なので失敗しない
+            Self::DEFAULT_TIMEZONE.parse().unwrap()
+        })
+    }
+
+    /// 要約にまとめて通知する時刻が設定されている場合に、`now` がその時刻 (サーバーのタイムゾーンで
+    /// `digest_hour` 時台) か返す。設定されていない場合は `None` を返す。
+    pub fn is_digest_hour(&self, now: DateTime<Utc>) -> Option<bool> {
+        let hour = self.digest_hour?;
+        Some(now.with_timezone(&self.utc_offset()).hour() as i32 == hour)
+    }
 }
 
-#[derive(Debug, Queryable, PartialEq, Eq)]
+#[derive(Debug, Clone, Queryable, PartialEq, Eq)]
 pub struct Subscriber {
     pub id: i32,
     pub user_id: i64,
     pub guild_id: i64,
     pub annict_name: String,
     pub end_cursor: Option<String>,
-    pub last_activity_date: Option<DateTime<Utc>>,
+    #[debug(skip)]
+    pub access_token: Option<String>,
+    pub annict_id: Option<i64>,
+    pub last_activity_id: Option<i64>,
 }
 
-#[derive(Debug, Insertable)]
-#[diesel(table_name = subscribers)]
-pub struct NewSubscriber<'a, 'b> {
-    pub user_id: i64,
-    pub guild_id: i64,
-    pub annict_name: &'a str,
-    pub end_cursor: Option<&'b str>,
-    pub last_activity_date: Option<DateTime<Utc>>,
+impl Subscriber {
+    /// Annict のアクティビティの ID `activity_id` が、まだ送信待ちに加えていないものか返す。
+    pub fn is_new_activity(&self, activity_id: i64) -> bool {
+        self.last_activity_id.is_none_or(|id| activity_id > id)
+    }
+}
+
+#[derive(Debug, Clone, Queryable, PartialEq, Eq)]
+pub struct DeliveredActivity {
+    pub subscriber_id: i32,
+    pub channel_id: i64,
+    pub activity_id: i64,
+    pub delivered_at: DateTime<Utc>,
+    #[diesel(deserialize_as = MessageIds)]
+    pub message_ids: Vec<i64>,
+    pub content: Option<String>,
+    pub deleted: bool,
+    pub digest_message_id: Option<i64>,
+}
+
+/// 送信待ちのアクティビティ。`payload` は取得したアクティビティの JSON。
+#[derive(Debug, Clone, Queryable, PartialEq, Eq)]
+pub struct OutboxActivity {
+    pub subscriber_id: i32,
+    pub activity_id: i64,
+    pub payload: String,
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+/// Discord に投稿した通知のメッセージ。
+#[derive(Debug, Clone, Queryable, PartialEq, Eq)]
+pub struct Notification {
+    pub id: i32,
+    pub subscriber_id: i32,
+    pub channel_id: i64,
+    pub activity_id: i64,
+    pub message_id: i64,
+    #[diesel(deserialize_as = i32)]
+    pub activity_type: ActivityType,
+    pub work_title: String,
+    pub work_url: String,
+    pub notified_at: DateTime<Utc>,
+}
+
+/// 記録する通知のメッセージ。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewNotification {
+    pub subscriber_id: i32,
+    pub channel_id: i64,
+    pub activity_id: i64,
+    pub message_id: i64,
+    pub activity_type: ActivityType,
+    pub work_title: String,
+    pub work_url: String,
+}
+
+/// 通知したメッセージの ID の列。
+/// PostgreSQL では配列として、SQLite では JSON の配列の文字列として保存する。
+#[derive(Debug, Clone, PartialEq, Eq, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Array<BigInt>)]
+#[cfg_attr(feature = "sqlite", diesel(sql_type = diesel::sql_types::Text))]
+pub struct MessageIds(pub Vec<i64>);
+
+impl From<MessageIds> for Vec<i64> {
+    fn from(value: MessageIds) -> Self {
+        value.0
+    }
+}
+
+impl ToSql<Array<BigInt>, Pg> for MessageIds {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <Vec<i64> as ToSql<Array<BigInt>, Pg>>::to_sql(&self.0, out)
+    }
+}
+
+impl FromSql<Array<BigInt>, Pg> for MessageIds {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        <Vec<i64> as FromSql<Array<BigInt>, Pg>>::from_sql(bytes).map(Self)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use diesel::{
+        deserialize::{self, FromSql},
+        serialize::{self, IsNull, Output, ToSql},
+        sql_types::Text,
+        sqlite::{Sqlite, SqliteValue},
+    };
+
+    use super::MessageIds;
+
+    impl ToSql<Text, Sqlite> for MessageIds {
+        fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+            out.set_value(serde_json::to_string(&self.0)?);
+            Ok(IsNull::No)
+        }
+    }
+
+    impl FromSql<Text, Sqlite> for MessageIds {
+        fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+            let text = <String as FromSql<Text, Sqlite>>::from_sql(value)?;
+            Ok(Self(serde_json::from_str(&text)?))
+        }
+    }
 }
@@ -5,6 +5,56 @@ diesel::table! {
         guild_id -> Int8,
         channel_id -> Int8,
         notify_flag -> Int4,
+        deleted_action -> Int4,
+    }
+}
+
+diesel::table! {
+    delivered_activities (subscriber_id, channel_id, activity_id) {
+        subscriber_id -> Int4,
+        channel_id -> Int8,
+        activity_id -> Int8,
+        delivered_at -> Timestamptz,
+        message_ids -> Array<Int8>,
+        content -> Nullable<Text>,
+        deleted -> Bool,
+        digest_message_id -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    guilds (guild_id) {
+        guild_id -> Int8,
+        locale -> Text,
+        timezone -> Text,
+        default_notify_flag -> Int4,
+        digest_threshold -> Nullable<Int4>,
+        digest_hour -> Nullable<Int4>,
+        link_role_id -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    notifications (id) {
+        id -> Int4,
+        subscriber_id -> Int4,
+        channel_id -> Int8,
+        activity_id -> Int8,
+        message_id -> Int8,
+        activity_type -> Int4,
+        work_title -> Text,
+        work_url -> Text,
+        notified_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    outbox (subscriber_id, activity_id) {
+        subscriber_id -> Int4,
+        activity_id -> Int8,
+        payload -> Text,
+        created_at -> Timestamptz,
+        sent_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -15,11 +65,23 @@ diesel::table! {
         guild_id -> Int8,
         annict_name -> Text,
         end_cursor -> Nullable<Text>,
-        last_activity_date -> Nullable<Timestamptz>,
+        access_token -> Nullable<Text>,
+        annict_id -> Nullable<Int8>,
+        last_activity_id -> Nullable<Int8>,
     }
 }
 
+diesel::joinable!(channels -> guilds (guild_id));
+diesel::joinable!(delivered_activities -> subscribers (subscriber_id));
+diesel::joinable!(notifications -> subscribers (subscriber_id));
+diesel::joinable!(outbox -> subscribers (subscriber_id));
+diesel::joinable!(subscribers -> guilds (guild_id));
+
 diesel::allow_tables_to_appear_in_same_query!(
     channels,
+    delivered_activities,
+    guilds,
+    notifications,
+    outbox,
     subscribers,
 );
@@ -0,0 +1,345 @@
+//! ボットの状態 (通知設定・購読者・通知の記録) を書き出した JSON の文書。
+//!
+//! ホストを移すときなどに、[crate::store::Store::export] で書き出したものを
+//! [crate::store::Store::import] で読み込む。購読者は保存先ごとに振られる ID ではなく、
+//! サーバー ID とユーザー ID の組で参照するので、別の保存先にもそのまま読み込める。
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    discord::{ActivityType, DeletedAction, NotifyFlag},
+    models::{Channel, DeliveredActivity, Guild, Notification, OutboxActivity, Subscriber},
+    Result,
+};
+
+/// 文書の形式のバージョン。形式を変えたときに上げる。
+pub const VERSION: u32 = 1;
+
+/// ボットの状態を書き出した文書。
+///
+/// 通知の種類などの列挙値はデータベースと同じ整数で表す。
+/// 後から加えた表は `#[serde(default)]` にして、古い文書も読み込めるようにする。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    #[serde(default)]
+    pub guilds: Vec<GuildRecord>,
+    #[serde(default)]
+    pub channels: Vec<ChannelRecord>,
+    #[serde(default)]
+    pub subscribers: Vec<SubscriberRecord>,
+    #[serde(default)]
+    pub delivered_activities: Vec<DeliveredRecord>,
+    #[serde(default)]
+    pub outbox: Vec<OutboxRecord>,
+    #[serde(default)]
+    pub notifications: Vec<NotificationRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuildRecord {
+    pub guild_id: i64,
+    pub locale: String,
+    pub timezone: String,
+    pub default_notify_flag: i32,
+    pub digest_threshold: Option<i32>,
+    #[serde(default)]
+    pub digest_hour: Option<i32>,
+    pub link_role_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelRecord {
+    pub guild_id: i64,
+    pub channel_id: i64,
+    pub notify_flag: i32,
+    pub deleted_action: i32,
+}
+
+/// 購読者の記録。
+///
+/// 送信待ちに加え終えたところは `last_activity_id` で表す。
+/// これはマイグレーション `2026-10-18-000002_add_delivered_activities` で削除した
+/// `last_activity_date` に代わるもので、古い文書に `last_activity_date` が残っていても読み込むときに無視する。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubscriberRecord {
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub annict_id: Option<i64>,
+    pub annict_name: String,
+    pub end_cursor: Option<String>,
+    /// 読み込むときに `None` の場合は、保存先のアクセストークンを変えない。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    /// 送信待ちに加え終えたアクティビティの ID。
+    pub last_activity_id: Option<i64>,
+}
+
+/// 通知したアクティビティの記録。購読者はサーバー ID `guild_id` とユーザー ID `user_id` で表す。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeliveredRecord {
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub channel_id: i64,
+    pub activity_id: i64,
+    pub delivered_at: DateTime<Utc>,
+    pub message_ids: Vec<i64>,
+    pub content: Option<String>,
+    pub deleted: bool,
+    pub digest_message_id: Option<i64>,
+}
+
+/// 送信待ちのアクティビティ。購読者はサーバー ID `guild_id` とユーザー ID `user_id` で表す。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutboxRecord {
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub activity_id: i64,
+    pub payload: String,
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+/// 通知の履歴。購読者はサーバー ID `guild_id` とユーザー ID `user_id` で表す。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationRecord {
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub channel_id: i64,
+    pub activity_id: i64,
+    pub message_id: i64,
+    pub activity_type: i32,
+    pub work_title: String,
+    pub work_url: String,
+    pub notified_at: DateTime<Utc>,
+}
+
+impl Snapshot {
+    /// 保存先から読み出した行から文書を作る。
+    /// 購読者の記録は購読者の ID を `subscribers` から引いて置き換え、見つからないものは含めない。
+    /// 同じ状態からはいつも同じ文書になるように、それぞれの表は主キーの順に並べる。
+    pub fn new(
+        guilds: Vec<Guild>,
+        channels: Vec<Channel>,
+        subscribers: Vec<Subscriber>,
+        delivered_activities: Vec<DeliveredActivity>,
+        outbox: Vec<OutboxActivity>,
+        notifications: Vec<Notification>,
+    ) -> Self {
+        let keys: HashMap<_, _> = subscribers
+            .iter()
+            .map(|subscriber| (subscriber.id, (subscriber.guild_id, subscriber.user_id)))
+            .collect();
+
+        let mut guilds: Vec<_> = guilds
+            .into_iter()
+            .map(|guild| GuildRecord {
+                guild_id: guild.guild_id,
+                locale: guild.locale,
+                timezone: guild.timezone,
+                default_notify_flag: guild.default_notify_flag.into(),
+                digest_threshold: guild.digest_threshold,
+                digest_hour: guild.digest_hour,
+                link_role_id: guild.link_role_id,
+            })
+            .collect();
+        guilds.sort_by_key(|guild| guild.guild_id);
+
+        let mut channels: Vec<_> = channels
+            .into_iter()
+            .map(|channel| ChannelRecord {
+                guild_id: channel.guild_id,
+                channel_id: channel.channel_id,
+                notify_flag: channel.notify_flag.into(),
+                deleted_action: channel.deleted_action.into(),
+            })
+            .collect();
+        channels.sort_by_key(|channel| (channel.guild_id, channel.channel_id));
+
+        let mut delivered_activities: Vec<_> = delivered_activities
+            .into_iter()
+            .filter_map(|delivered| {
+                let (guild_id, user_id) = *keys.get(&delivered.subscriber_id)?;
+                Some(DeliveredRecord {
+                    guild_id,
+                    user_id,
+                    channel_id: delivered.channel_id,
+                    activity_id: delivered.activity_id,
+                    delivered_at: delivered.delivered_at,
+                    message_ids: delivered.message_ids,
+                    content: delivered.content,
+                    deleted: delivered.deleted,
+                    digest_message_id: delivered.digest_message_id,
+                })
+            })
+            .collect();
+        delivered_activities.sort_by_key(|delivered| {
+            (
+                delivered.guild_id,
+                delivered.user_id,
+                delivered.channel_id,
+                delivered.activity_id,
+            )
+        });
+
+        let mut outbox: Vec<_> = outbox
+            .into_iter()
+            .filter_map(|activity| {
+                let (guild_id, user_id) = *keys.get(&activity.subscriber_id)?;
+                Some(OutboxRecord {
+                    guild_id,
+                    user_id,
+                    activity_id: activity.activity_id,
+                    payload: activity.payload,
+                    created_at: activity.created_at,
+                    sent_at: activity.sent_at,
+                })
+            })
+            .collect();
+        outbox.sort_by_key(|activity| (activity.guild_id, activity.user_id, activity.activity_id));
+
+        let mut notifications: Vec<_> = notifications
+            .into_iter()
+            .filter_map(|notification| {
+                let (guild_id, user_id) = *keys.get(&notification.subscriber_id)?;
+                Some(NotificationRecord {
+                    guild_id,
+                    user_id,
+                    channel_id: notification.channel_id,
+                    activity_id: notification.activity_id,
+                    message_id: notification.message_id,
+                    activity_type: notification.activity_type.into(),
+                    work_title: notification.work_title,
+                    work_url: notification.work_url,
+                    notified_at: notification.notified_at,
+                })
+            })
+            .collect();
+        notifications.sort_by_key(|notification| {
+            (
+                notification.guild_id,
+                notification.user_id,
+                notification.notified_at,
+                notification.channel_id,
+                notification.activity_id,
+                notification.message_id,
+            )
+        });
+
+        let mut subscribers: Vec<_> = subscribers
+            .into_iter()
+            .map(|subscriber| SubscriberRecord {
+                guild_id: subscriber.guild_id,
+                user_id: subscriber.user_id,
+                annict_id: subscriber.annict_id,
+                annict_name: subscriber.annict_name,
+                end_cursor: subscriber.end_cursor,
+                access_token: subscriber.access_token,
+                last_activity_id: subscriber.last_activity_id,
+            })
+            .collect();
+        subscribers.sort_by_key(|subscriber| (subscriber.guild_id, subscriber.user_id));
+
+        Self {
+            version: VERSION,
+            exported_at: Utc::now(),
+            guilds,
+            channels,
+            subscribers,
+            delivered_activities,
+            outbox,
+            notifications,
+        }
+    }
+
+    /// 購読者のアクセストークンを取り除く。サーバーの管理者に渡す場合などに使う。
+    pub fn remove_access_tokens(&mut self) {
+        for subscriber in &mut self.subscribers {
+            subscriber.access_token = None;
+        }
+    }
+
+    /// 読み込める文書か確かめる。
+    ///
+    /// バージョンが対応しているか、列挙値が正しいか、購読者の記録の購読者が文書に含まれているかを確かめる。
+    /// 保存先は読み込む前にこれを呼び、読み込み途中で失敗しないようにする。
+    pub fn validate(&self) -> Result<()> {
+        if self.version > VERSION {
+            return Err(format!(
+                "文書のバージョン {} には対応していません (対応しているのは {} まで)",
+                self.version, VERSION
+            )
+            .into());
+        }
+
+        for guild in &self.guilds {
+            NotifyFlag::try_from(guild.default_notify_flag).map_err(|_| {
+                format!(
+                    "サーバー (ID = {}) の既定の通知の種類 ({}) が不正です",
+                    guild.guild_id, guild.default_notify_flag
+                )
+            })?;
+            if guild
+                .digest_hour
+                .is_some_and(|hour| !(0..24).contains(&hour))
+            {
+                return Err(format!(
+                    "サーバー (ID = {}) の要約の時刻 ({:?}) が不正です",
+                    guild.guild_id, guild.digest_hour
+                )
+                .into());
+            }
+        }
+        for channel in &self.channels {
+            NotifyFlag::try_from(channel.notify_flag).map_err(|_| {
+                format!(
+                    "チャンネル (ID = {}) の通知の種類 ({}) が不正です",
+                    channel.channel_id, channel.notify_flag
+                )
+            })?;
+            DeletedAction::try_from(channel.deleted_action).map_err(|_| {
+                format!(
+                    "チャンネル (ID = {}) の削除時の扱い ({}) が不正です",
+                    channel.channel_id, channel.deleted_action
+                )
+            })?;
+        }
+
+        let keys: HashSet<_> = self
+            .subscribers
+            .iter()
+            .map(|subscriber| (subscriber.guild_id, subscriber.user_id))
+            .collect();
+        let check_subscriber = |guild_id, user_id| {
+            if keys.contains(&(guild_id, user_id)) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "サーバー (ID = {}) のユーザー (ID = {}) の購読者が文書に含まれていません",
+                    guild_id, user_id
+                ))
+            }
+        };
+        for delivered in &self.delivered_activities {
+            check_subscriber(delivered.guild_id, delivered.user_id)?;
+        }
+        for activity in &self.outbox {
+            check_subscriber(activity.guild_id, activity.user_id)?;
+        }
+        for notification in &self.notifications {
+            check_subscriber(notification.guild_id, notification.user_id)?;
+            ActivityType::try_from(notification.activity_type).map_err(|_| {
+                format!(
+                    "通知の履歴 (メッセージ ID = {}) のアクティビティの種類 ({}) が不正です",
+                    notification.message_id, notification.activity_type
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+//! アクティビティの取得元。
+//!
+//! 取得元ごとに [ActivitySource] を実装し、取得したアクティビティを共通の [Activity] にして返す。
+//! 通知などの処理は取得元を区別せずにこれを扱う。
+
+use async_trait::async_trait;
+
+use crate::models::Subscriber;
+
+pub use error::*;
+pub use models::*;
+
+mod error;
+mod models;
+#[cfg(test)]
+mod test;
+
+/// アクティビティの取得元。
+#[async_trait]
+pub trait ActivitySource: Send + Sync {
+    /// 取得元のサービスの名前。メッセージの中で取得元を示すのに使う。
+    fn name(&self) -> &str;
+
+    /// 1 回の [ActivitySource::new_activities] でまとめて取得するアカウントの数。
+    fn batch_size(&self) -> usize {
+        1
+    }
+
+    /// ユーザー名 `username` のユーザーのページの URL。
+    fn user_url(&self, username: &str) -> String;
+
+    /// `accounts` のアカウントそれぞれについて、新しいアクティビティを古い順に取得する。
+    /// `accounts` はそれぞれ同じアカウントの購読者 (サーバーごとに 1 人) をまとめたもので、
+    /// その数は [ActivitySource::batch_size] 以下にしておくこと。
+    ///
+    /// 購読者ごとに送信待ちに加え終えたところが違う場合は、最も遅れている購読者に合わせて返すので、
+    /// それぞれの購読者について [Subscriber::is_new_activity] で絞り込んでから通知すること。
+    ///
+    /// 取得したアクティビティは、次回の取得を始める位置と一緒に
+    /// [crate::store::Store::enqueue_activities] で送信待ちに加えること。
+    /// 加えられるまでは、同じアクティビティを再び返す。
+    ///
+    /// 結果は `accounts` と同じ順番で返す。
+    /// まとめた取得自体が失敗した場合は全体としてエラーを返す。
+    async fn new_activities(
+        &self,
+        accounts: &[Vec<&Subscriber>],
+    ) -> SourceResult<Vec<SourceResult<NewActivities>>>;
+
+    /// 購読者 `subscriber` のアカウントの最近 `count` 件のアクティビティを古い順に返す。
+    /// 中身を取得できなかったアクティビティは ID だけを返す。
+    /// 購読者の情報は更新しない。
+    async fn recent_activities(
+        &self,
+        subscriber: &Subscriber,
+        count: i32,
+    ) -> SourceResult<RecentActivities>;
+
+    /// 購読者 `subscriber` のアカウントの過去のアクティビティのうち、`history` の範囲のものを古い順に返す。
+    /// 購読者の情報は更新しない。
+    async fn past_activities(
+        &self,
+        subscriber: &Subscriber,
+        history: &History,
+    ) -> SourceResult<Vec<Activity>>;
+}
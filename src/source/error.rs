@@ -0,0 +1,37 @@
+use std::{
+    error,
+    fmt::{self, Display, Formatter},
+};
+
+pub type SourceResult<T> = std::result::Result<T, SourceError>;
+
+/// アクティビティの取得元とのやり取りで起こるエラー。
+#[derive(Debug)]
+pub enum SourceError {
+    /// 問い合わせたアカウントが存在しない。
+    NotFound,
+
+    /// 時間を置いて再試行すれば成功する可能性があるエラー。
+    Transient(Box<dyn error::Error + Send + Sync>),
+
+    /// その他のエラー。
+    Other(Box<dyn error::Error + Send + Sync>),
+}
+
+impl Display for SourceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => f.write_str("アカウントが見つかりません"),
+            Self::Transient(e) | Self::Other(e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for SourceError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::NotFound => None,
+            Self::Transient(e) | Self::Other(e) => Some(e.as_ref()),
+        }
+    }
+}
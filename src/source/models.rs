@@ -0,0 +1,221 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+/// 取得元によらない共通のアクティビティ。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Activity {
+    /// 取得元でのアクティビティの ID。新しいアクティビティほど大きい。
+    pub id: i64,
+    pub created_at: DateTime<Local>,
+    /// アクティビティの中身。まとめて記録されたものは複数になる。
+    pub items: Vec<ActivityItem>,
+}
+
+/// 新しく取得したアクティビティ。
+#[derive(Debug, Clone, Default)]
+pub struct NewActivities {
+    /// 新しいアクティビティ。古い順に並ぶ。
+    pub activities: Vec<Activity>,
+    /// 次回の取得を始める位置。`None` の場合は今の位置から変えない。
+    pub end_cursor: Option<String>,
+}
+
+/// 最近のアクティビティ。
+#[derive(Debug, Clone, Default)]
+pub struct RecentActivities {
+    /// 中身を取得できたアクティビティ。古い順に並ぶ。
+    pub activities: Vec<Activity>,
+    /// 中身が `null` で取得できなかったアクティビティの ID。
+    /// 削除されたとは限らないので、存在するものとして扱う。
+    pub unknown_ids: Vec<i64>,
+}
+
+impl RecentActivities {
+    /// 中身を取得できなかったものも含めたアクティビティの数。
+    pub fn len(&self) -> usize {
+        self.activities.len() + self.unknown_ids.len()
+    }
+
+    /// アクティビティが 1 件もなければ `true` を返す。
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// ID が `id` のアクティビティが含まれていれば `true` を返す。
+    pub fn contains(&self, id: i64) -> bool {
+        self.unknown_ids.contains(&id) || self.activities.iter().any(|activity| activity.id == id)
+    }
+
+    /// 含まれるアクティビティの ID の最小値。
+    pub fn min_id(&self) -> Option<i64> {
+        self.activities
+            .iter()
+            .map(|activity| activity.id)
+            .chain(self.unknown_ids.iter().copied())
+            .min()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ActivityItem {
+    /// 1 話ごとの記録。
+    Record(Record),
+    /// 作品全体の感想。
+    Review(Review),
+    /// 視聴状況の変更。
+    Status(Status),
+}
+
+impl ActivityItem {
+    /// 記録・感想・視聴状況の対象の作品。
+    pub fn work(&self) -> &Work {
+        match self {
+            Self::Record(record) => &record.work,
+            Self::Review(review) => &review.work,
+            Self::Status(status) => &status.work,
+        }
+    }
+}
+
+/// 作品の情報。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Work {
+    pub title: String,
+    /// 取得元の作品ページの URL。
+    pub url: String,
+    pub image_url: Option<String>,
+    /// `2024年秋` のような放送・公開時期。
+    pub season: Option<String>,
+    /// `TV` のようなメディアの種類。
+    pub media: Option<String>,
+    pub official_site_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub work: Work,
+    pub episode: Episode,
+    /// `5/12 話` のような進み具合。
+    pub progress: Option<String>,
+    pub rating: Option<Rating>,
+    pub comment: Option<String>,
+}
+
+/// 記録したエピソード。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Episode {
+    /// 取得元のエピソードページの URL。
+    pub url: String,
+    /// `第5話` のような番号。
+    pub number: Option<String>,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Review {
+    pub work: Work,
+    /// 全体の評価。
+    pub rating: Option<Rating>,
+    /// `映像` のような項目ごとの評価。
+    pub ratings: Vec<(String, Rating)>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    pub work: Work,
+    pub state: StatusState,
+    /// `見てる` のような、取得元での状態の呼び方。
+    pub label: String,
+}
+
+/// 遡って取得する過去のアクティビティの範囲。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum History {
+    /// 最近の指定した件数。
+    Last(usize),
+    /// 指定した日時以降。
+    Since(DateTime<Local>),
+}
+
+impl History {
+    /// 新しい方から `count` 件を取得した後の、日時が `created_at` のアクティビティが範囲に含まれるか返す。
+    pub fn includes(&self, created_at: DateTime<Local>, count: usize) -> bool {
+        match self {
+            Self::Last(n) => count < *n,
+            Self::Since(since) => created_at >= *since,
+        }
+    }
+}
+
+impl FromStr for History {
+    type Err = String;
+
+    /// `20` のような件数か、`2024-10-01` のような日付を解釈する。
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(n) = s.parse() {
+            return match n {
+                0 => Err("件数は 1 以上にしてください".into()),
+                n => Ok(Self::Last(n)),
+            };
+        }
+        NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| {
+                date.and_hms_opt(0, 0, 0)?
+                    .and_local_timezone(Local)
+                    .earliest()
+            })
+            .map(Self::Since)
+            .ok_or_else(|| {
+                format!(
+                    "範囲 (\"{}\") は件数か YYYY-MM-DD 形式の日付にしてください",
+                    s
+                )
+            })
+    }
+}
+
+/// 評価。良いものほど大きい。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Rating {
+    Bad,
+    Average,
+    Good,
+    Great,
+}
+
+impl Display for Rating {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Rating::Bad => "良くない",
+            Rating::Average => "普通",
+            Rating::Good => "良い",
+            Rating::Great => "とても良い",
+        };
+        f.write_str(s)
+    }
+}
+
+/// 視聴・読書などの状況。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StatusState {
+    /// 未選択。
+    None,
+    /// これから見る・読む。
+    Planning,
+    /// 見ている・読んでいる。
+    Current,
+    /// 一時中断。
+    Paused,
+    /// 中止。
+    Dropped,
+    /// 見終えた・読み終えた。
+    Completed,
+}
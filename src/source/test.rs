@@ -0,0 +1,30 @@
+use chrono::{Local, TimeZone};
+
+use super::History;
+
+#[test]
+fn history_from_str_test() {
+    assert_eq!("20".parse(), Ok(History::Last(20)));
+    assert_eq!(" 5 ".parse(), Ok(History::Last(5)));
+    assert_eq!(
+        "2024-10-01".parse(),
+        Ok(History::Since(
+            Local.with_ymd_and_hms(2024, 10, 1, 0, 0, 0).unwrap()
+        ))
+    );
+    assert!("0".parse::<History>().is_err());
+    assert!("-1".parse::<History>().is_err());
+    assert!("2024/10/01".parse::<History>().is_err());
+    assert!("".parse::<History>().is_err());
+}
+
+#[test]
+fn history_includes_test() {
+    let now = Local::now();
+    assert!(History::Last(2).includes(now, 1));
+    assert!(!History::Last(2).includes(now, 2));
+
+    let since = Local.with_ymd_and_hms(2024, 10, 1, 0, 0, 0).unwrap();
+    assert!(History::Since(since).includes(since, 100));
+    assert!(!History::Since(since).includes(since - chrono::Duration::seconds(1), 0));
+}
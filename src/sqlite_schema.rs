@@ -0,0 +1,91 @@
+//! SQLite 用のスキーマ。
+//!
+//! [crate::schema] と同じテーブル・カラムを、SQLite で扱える型で定義する。
+//! `migrations_sqlite/` のマイグレーションと合わせて、手で更新すること。
+
+diesel::table! {
+    channels (guild_id, channel_id) {
+        guild_id -> BigInt,
+        channel_id -> BigInt,
+        notify_flag -> Integer,
+        deleted_action -> Integer,
+    }
+}
+
+diesel::table! {
+    delivered_activities (subscriber_id, channel_id, activity_id) {
+        subscriber_id -> Integer,
+        channel_id -> BigInt,
+        activity_id -> BigInt,
+        delivered_at -> TimestamptzSqlite,
+        /// JSON の配列。
+        message_ids -> Text,
+        content -> Nullable<Text>,
+        deleted -> Bool,
+        digest_message_id -> Nullable<BigInt>,
+    }
+}
+
+diesel::table! {
+    guilds (guild_id) {
+        guild_id -> BigInt,
+        locale -> Text,
+        timezone -> Text,
+        default_notify_flag -> Integer,
+        digest_threshold -> Nullable<Integer>,
+        digest_hour -> Nullable<Integer>,
+        link_role_id -> Nullable<BigInt>,
+    }
+}
+
+diesel::table! {
+    notifications (id) {
+        id -> Integer,
+        subscriber_id -> Integer,
+        channel_id -> BigInt,
+        activity_id -> BigInt,
+        message_id -> BigInt,
+        activity_type -> Integer,
+        work_title -> Text,
+        work_url -> Text,
+        notified_at -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    outbox (subscriber_id, activity_id) {
+        subscriber_id -> Integer,
+        activity_id -> BigInt,
+        payload -> Text,
+        created_at -> TimestamptzSqlite,
+        sent_at -> Nullable<TimestamptzSqlite>,
+    }
+}
+
+diesel::table! {
+    subscribers (id) {
+        id -> Integer,
+        user_id -> BigInt,
+        guild_id -> BigInt,
+        annict_name -> Text,
+        end_cursor -> Nullable<Text>,
+        access_token -> Nullable<Text>,
+        annict_id -> Nullable<BigInt>,
+        last_activity_id -> Nullable<BigInt>,
+    }
+}
+
+diesel::joinable!(channels -> guilds (guild_id));
+diesel::joinable!(delivered_activities -> subscribers (subscriber_id));
+diesel::joinable!(notifications -> subscribers (subscriber_id));
+diesel::joinable!(outbox -> subscribers (subscriber_id));
+diesel::joinable!(subscribers -> guilds (guild_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    channels,
+    delivered_activities,
+    guilds,
+    notifications,
+    outbox,
+    subscribers,
+);
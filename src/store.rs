@@ -0,0 +1,234 @@
+//! 通知設定・購読者・通知の記録の保存先。
+//!
+//! 保存先ごとに [Store] を実装する。データベースには [crate::db::DbPool] を、
+//! データベースを用意できないテストなどでは [MemoryStore] を使う。
+
+use std::fmt;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::{
+    discord::{DeletedAction, NotifyFlag},
+    models::{
+        Channel, DeliveredActivity, Guild, NewNotification, Notification, OutboxActivity,
+        Subscriber,
+    },
+    snapshot::Snapshot,
+    Result,
+};
+
+pub use memory::*;
+
+mod memory;
+#[cfg(test)]
+pub(crate) mod test;
+
+/// 通知の履歴を絞り込む条件。`None` の条件では絞り込まない。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NotificationFilter {
+    /// 購読者の Discord のユーザー ID。
+    pub user_id: Option<u64>,
+    /// 作品のタイトルに含まれる文字列。
+    pub work_title: Option<String>,
+    /// 通知したチャンネルの ID の候補。
+    pub channel_ids: Option<Vec<u64>>,
+    /// この日時以降に通知したもの。
+    pub since: Option<DateTime<Utc>>,
+    /// この日時より前に通知したもの。
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// 通知設定・購読者・通知の記録の保存先。
+#[async_trait]
+pub trait Store: fmt::Debug + Send + Sync {
+    /// 保存先の使用状況をログに出力する。
+    fn log_state(&self) {}
+
+    /// サーバー `guild_id` のチャンネル `channel_id` の通知設定を `notify_flag` にする。
+    /// 通知設定がなかった場合は作成する。サーバーの設定がなかった場合は既定の設定で作成する。
+    async fn insert_or_update_channel(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        notify_flag: NotifyFlag,
+    ) -> Result<Channel>;
+
+    /// Annict でアクティビティが削除されたときの、通知したメッセージの扱いを更新する。
+    async fn update_deleted_action(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        deleted_action: DeletedAction,
+    ) -> Result<Channel>;
+
+    /// チャンネルの通知設定を削除し、削除したか返す。
+    async fn remove_channel(&self, guild_id: u64, channel_id: u64) -> Result<bool>;
+
+    /// サーバー `guild_id` のチャンネル `channel_id` の通知設定を返す。
+    async fn get_channel(&self, guild_id: u64, channel_id: u64) -> Result<Option<Channel>>;
+
+    async fn get_channels(&self) -> Result<Vec<Channel>>;
+
+    /// サーバー `guild_id` のユーザー `user_id` を購読者として登録する。
+    /// 既に登録されている場合は Annict のユーザーの情報とアクセストークンを更新する。
+    /// サーバーの設定がなかった場合は既定の設定で作成する。
+    #[allow(clippy::too_many_arguments)]
+    async fn insert_or_update_subscriber(
+        &self,
+        user_id: u64,
+        guild_id: u64,
+        annict_id: i64,
+        annict_name: &str,
+        end_cursor: Option<&str>,
+        last_activity_id: Option<i64>,
+        access_token: Option<&str>,
+    ) -> Result<Subscriber>;
+
+    async fn update_access_token(&self, id: i32, access_token: Option<&str>) -> Result<Subscriber>;
+
+    /// Annict のユーザー ID とユーザー名を更新する。
+    async fn update_annict_user(
+        &self,
+        id: i32,
+        annict_id: i64,
+        annict_name: &str,
+    ) -> Result<Subscriber>;
+
+    async fn get_subscribers(&self) -> Result<Vec<Subscriber>>;
+
+    /// Annict のユーザー ID が分かっていない購読者を返す。
+    async fn get_subscribers_without_annict_id(&self) -> Result<Vec<Subscriber>>;
+
+    /// サーバー `guild_id` のユーザー `user_id` の購読者を返す。
+    async fn get_subscriber(&self, user_id: u64, guild_id: u64) -> Result<Option<Subscriber>>;
+
+    async fn get_subscribers_by_guild(&self, guild_id: u64) -> Result<Vec<Subscriber>>;
+
+    /// 購読者 `subscriber_id` のアクティビティ `activity_id` をチャンネル `channel_id` に通知済みか返す。
+    async fn is_delivered(
+        &self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+    ) -> Result<bool>;
+
+    /// 購読者 `subscriber_id` のアクティビティ `activity_id` をチャンネル `channel_id` に通知したことを、
+    /// 送信したメッセージ `message_ids` とその内容 `content` と共に記録する。
+    /// 同時に、投稿した通知のメッセージ `notifications` を履歴に記録する。
+    /// 既に記録されている場合は何もしない。
+    async fn insert_delivered(
+        &self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+        message_ids: &[i64],
+        content: &str,
+        notifications: &[NewNotification],
+    ) -> Result<Option<DeliveredActivity>>;
+
+    /// 購読者 `subscriber_id` のアクティビティ `activity_id` をチャンネル `channel_id` に、
+    /// 要約のメッセージ `digest_message_id` でまとめて通知したことを、その内容 `content` と共に記録する。
+    /// 同時に、要約に含めたアクティビティ `notifications` を履歴に記録する。
+    /// 既に記録されている場合は何もしない。
+    async fn insert_digested(
+        &self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+        digest_message_id: i64,
+        content: &str,
+        notifications: &[NewNotification],
+    ) -> Result<Option<DeliveredActivity>>;
+
+    /// チャンネル `channel_id` の要約のメッセージ `digest_message_id` でまとめて通知した記録を、
+    /// アクティビティの古い順に返す。
+    async fn get_digested(
+        &self,
+        channel_id: u64,
+        digest_message_id: i64,
+    ) -> Result<Vec<DeliveredActivity>>;
+
+    /// 購読者 `subscriber_id` のアクティビティ `activity_id` を通知した記録を、チャンネルごとに返す。
+    async fn get_delivered(
+        &self,
+        subscriber_id: i32,
+        activity_id: i64,
+    ) -> Result<Vec<DeliveredActivity>>;
+
+    /// 購読者 `subscriber_id` のアクティビティのうち、`since` 以降に通知した、
+    /// Annict で削除されていないものの記録を返す。
+    async fn get_delivered_since(
+        &self,
+        subscriber_id: i32,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DeliveredActivity>>;
+
+    /// 通知したアクティビティが Annict で削除されたことを記録する。
+    async fn mark_delivered_deleted(
+        &self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+    ) -> Result<DeliveredActivity>;
+
+    /// 通知したメッセージを編集したときに、その内容 `content` を記録する。
+    async fn update_delivered_content(
+        &self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+        content: &str,
+    ) -> Result<DeliveredActivity>;
+
+    /// 同じアカウントの購読者それぞれの新しいアクティビティ `activities`
+    /// (購読者の ID と、アクティビティの ID と JSON の列) を送信待ちに加える。
+    /// 同時に、それぞれの購読者の `last_activity_id` を最も新しいものの ID に進め、
+    /// `end_cursor` を次回の取得を始める位置にする。`end_cursor` が `None` の場合は変えない。
+    /// 既に加えたアクティビティは加えない。
+    async fn enqueue_activities(
+        &self,
+        activities: &[(i32, Vec<(i64, String)>)],
+        end_cursor: Option<&str>,
+    ) -> Result<()>;
+
+    /// 購読者 `subscriber_id` の送信し終えていないアクティビティを古い順に返す。
+    async fn get_unsent(&self, subscriber_id: i32) -> Result<Vec<OutboxActivity>>;
+
+    /// 購読者 `subscriber_id` のアクティビティ `activity_ids` を送信し終えたことを記録し、記録した数を返す。
+    async fn mark_sent(&self, subscriber_id: i32, activity_ids: &[i64]) -> Result<usize>;
+
+    /// `before` より前に送信し終えたアクティビティを送信待ちから取り除き、取り除いた数を返す。
+    async fn prune_outbox(&self, before: DateTime<Utc>) -> Result<usize>;
+
+    /// サーバー `guild_id` の設定を返す。設定を一度も保存していない場合は `None` を返す。
+    async fn get_guild(&self, guild_id: u64) -> Result<Option<Guild>>;
+
+    async fn get_guilds(&self) -> Result<Vec<Guild>>;
+
+    /// サーバーの設定を `guild` にする。設定がなかった場合は作成する。
+    async fn update_guild(&self, guild: &Guild) -> Result<Guild>;
+
+    /// サーバーの設定を削除し、削除したか返す。
+    /// サーバーのチャンネルの通知設定と購読者、購読者に関する記録もすべて削除する。
+    async fn remove_guild(&self, guild_id: u64) -> Result<bool>;
+
+    /// サーバー `guild_id` の通知の履歴のうち `filter` に合うものを、新しい順に `offset` 件目から `limit` 件返す。
+    async fn get_notifications(
+        &self,
+        guild_id: u64,
+        filter: &NotificationFilter,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Notification>>;
+
+    /// すべての状態を書き出す。`guild_id` を指定した場合は、そのサーバーに関するものだけを書き出す。
+    async fn export(&self, guild_id: Option<u64>) -> Result<Snapshot>;
+
+    /// [Store::export] で書き出した `snapshot` を読み込む。
+    ///
+    /// 既にある行は文書の内容で上書きし、通知の記録や送信待ちのアクティビティは重複して加えないので、
+    /// 同じ文書を何度読み込んでも結果は変わらない。文書に含まれない行はそのまま残す。
+    /// 文書が不正な場合は何も変更せずにエラーを返す。
+    async fn import(&self, snapshot: &Snapshot) -> Result<()>;
+}
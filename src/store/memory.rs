@@ -0,0 +1,786 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::{
+    discord::{ActivityType, DeletedAction, NotifyFlag},
+    models::{
+        Channel, DeliveredActivity, Guild, NewNotification, Notification, OutboxActivity,
+        Subscriber,
+    },
+    snapshot::Snapshot,
+    Result,
+};
+
+use super::{NotificationFilter, Store};
+
+/// メモリ上の [Store]。プロセスを終了すると内容は失われる。
+///
+/// データベースを用意できないテストで使う。
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    tables: Mutex<Tables>,
+}
+
+#[derive(Debug, Default)]
+struct Tables {
+    guilds: Vec<Guild>,
+    channels: Vec<Channel>,
+    subscribers: Vec<Subscriber>,
+    /// 最後に割り当てた購読者の ID。
+    last_subscriber_id: i32,
+    delivered_activities: Vec<DeliveredActivity>,
+    outbox: Vec<OutboxActivity>,
+    notifications: Vec<Notification>,
+    /// 最後に割り当てた通知の履歴の ID。
+    last_notification_id: i32,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap()
+    }
+}
+
+impl Tables {
+    /// サーバー `guild_id` の設定がなければ既定の設定で作る。
+    fn ensure_guild(&mut self, guild_id: u64) {
+        if !self
+            .guilds
+            .iter()
+            .any(|guild| guild.guild_id == guild_id as i64)
+        {
+            self.guilds.push(Guild::new(guild_id));
+        }
+    }
+
+    fn channel_mut(&mut self, guild_id: u64, channel_id: u64) -> Option<&mut Channel> {
+        self.channels.iter_mut().find(|channel| {
+            channel.guild_id == guild_id as i64 && channel.channel_id == channel_id as i64
+        })
+    }
+
+    fn subscriber_mut(&mut self, id: i32) -> Result<&mut Subscriber> {
+        self.subscribers
+            .iter_mut()
+            .find(|subscriber| subscriber.id == id)
+            .ok_or_else(|| format!("購読者 (ID = {}) が見つかりません", id).into())
+    }
+
+    fn delivered_mut(
+        &mut self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+    ) -> Result<&mut DeliveredActivity> {
+        self.delivered_activities
+            .iter_mut()
+            .find(|delivered| {
+                delivered.subscriber_id == subscriber_id
+                    && delivered.channel_id == channel_id as i64
+                    && delivered.activity_id == activity_id
+            })
+            .ok_or_else(|| {
+                format!(
+                    "アクティビティ (ID = {}) の通知の記録が見つかりません",
+                    activity_id
+                )
+                .into()
+            })
+    }
+
+    fn insert_delivered(&mut self, delivered: DeliveredActivity) -> Option<DeliveredActivity> {
+        let exists = self.delivered_activities.iter().any(|other| {
+            other.subscriber_id == delivered.subscriber_id
+                && other.channel_id == delivered.channel_id
+                && other.activity_id == delivered.activity_id
+        });
+        if exists {
+            return None;
+        }
+        self.delivered_activities.push(delivered.clone());
+        Some(delivered)
+    }
+
+    fn insert_notifications(&mut self, notifications: &[NewNotification]) {
+        let now = Utc::now();
+        for notification in notifications {
+            self.last_notification_id += 1;
+            self.notifications.push(Notification {
+                id: self.last_notification_id,
+                subscriber_id: notification.subscriber_id,
+                channel_id: notification.channel_id,
+                activity_id: notification.activity_id,
+                message_id: notification.message_id,
+                activity_type: notification.activity_type,
+                work_title: notification.work_title.clone(),
+                work_url: notification.work_url.clone(),
+                notified_at: now,
+            });
+        }
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn insert_or_update_channel(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        notify_flag: NotifyFlag,
+    ) -> Result<Channel> {
+        let mut tables = self.tables();
+        tables.ensure_guild(guild_id);
+        if let Some(channel) = tables.channel_mut(guild_id, channel_id) {
+            channel.notify_flag = notify_flag;
+            return Ok(channel.clone());
+        }
+        let channel = Channel {
+            guild_id: guild_id as _,
+            channel_id: channel_id as _,
+            notify_flag,
+            deleted_action: DeletedAction::default(),
+        };
+        tables.channels.push(channel.clone());
+        Ok(channel)
+    }
+
+    async fn update_deleted_action(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        deleted_action: DeletedAction,
+    ) -> Result<Channel> {
+        let mut tables = self.tables();
+        let channel = tables
+            .channel_mut(guild_id, channel_id)
+            .ok_or_else(|| format!("チャンネル (ID = {}) が見つかりません", channel_id))?;
+        channel.deleted_action = deleted_action;
+        Ok(channel.clone())
+    }
+
+    async fn remove_channel(&self, guild_id: u64, channel_id: u64) -> Result<bool> {
+        let mut tables = self.tables();
+        let len = tables.channels.len();
+        tables.channels.retain(|channel| {
+            channel.guild_id != guild_id as i64 || channel.channel_id != channel_id as i64
+        });
+        Ok(tables.channels.len() < len)
+    }
+
+    async fn get_channel(&self, guild_id: u64, channel_id: u64) -> Result<Option<Channel>> {
+        Ok(self
+            .tables()
+            .channel_mut(guild_id, channel_id)
+            .map(|channel| channel.clone()))
+    }
+
+    async fn get_channels(&self) -> Result<Vec<Channel>> {
+        Ok(self.tables().channels.clone())
+    }
+
+    async fn insert_or_update_subscriber(
+        &self,
+        user_id: u64,
+        guild_id: u64,
+        annict_id: i64,
+        annict_name: &str,
+        end_cursor: Option<&str>,
+        last_activity_id: Option<i64>,
+        access_token: Option<&str>,
+    ) -> Result<Subscriber> {
+        let mut tables = self.tables();
+        tables.ensure_guild(guild_id);
+        let existing = tables.subscribers.iter_mut().find(|subscriber| {
+            subscriber.user_id == user_id as i64 && subscriber.guild_id == guild_id as i64
+        });
+        if let Some(subscriber) = existing {
+            subscriber.annict_id = Some(annict_id);
+            subscriber.annict_name = annict_name.into();
+            subscriber.end_cursor = end_cursor.map(Into::into);
+            subscriber.last_activity_id = last_activity_id;
+            subscriber.access_token = access_token.map(Into::into);
+            return Ok(subscriber.clone());
+        }
+
+        tables.last_subscriber_id += 1;
+        let subscriber = Subscriber {
+            id: tables.last_subscriber_id,
+            user_id: user_id as _,
+            guild_id: guild_id as _,
+            annict_name: annict_name.into(),
+            end_cursor: end_cursor.map(Into::into),
+            access_token: access_token.map(Into::into),
+            annict_id: Some(annict_id),
+            last_activity_id,
+        };
+        tables.subscribers.push(subscriber.clone());
+        Ok(subscriber)
+    }
+
+    async fn update_access_token(&self, id: i32, access_token: Option<&str>) -> Result<Subscriber> {
+        let mut tables = self.tables();
+        let subscriber = tables.subscriber_mut(id)?;
+        subscriber.access_token = access_token.map(Into::into);
+        Ok(subscriber.clone())
+    }
+
+    async fn update_annict_user(
+        &self,
+        id: i32,
+        annict_id: i64,
+        annict_name: &str,
+    ) -> Result<Subscriber> {
+        let mut tables = self.tables();
+        let subscriber = tables.subscriber_mut(id)?;
+        subscriber.annict_id = Some(annict_id);
+        subscriber.annict_name = annict_name.into();
+        Ok(subscriber.clone())
+    }
+
+    async fn get_subscribers(&self) -> Result<Vec<Subscriber>> {
+        Ok(self.tables().subscribers.clone())
+    }
+
+    async fn get_subscribers_without_annict_id(&self) -> Result<Vec<Subscriber>> {
+        Ok(self
+            .tables()
+            .subscribers
+            .iter()
+            .filter(|subscriber| subscriber.annict_id.is_none())
+            .cloned()
+            .collect())
+    }
+
+    async fn get_subscriber(&self, user_id: u64, guild_id: u64) -> Result<Option<Subscriber>> {
+        Ok(self
+            .tables()
+            .subscribers
+            .iter()
+            .find(|subscriber| {
+                subscriber.user_id == user_id as i64 && subscriber.guild_id == guild_id as i64
+            })
+            .cloned())
+    }
+
+    async fn get_subscribers_by_guild(&self, guild_id: u64) -> Result<Vec<Subscriber>> {
+        Ok(self
+            .tables()
+            .subscribers
+            .iter()
+            .filter(|subscriber| subscriber.guild_id == guild_id as i64)
+            .cloned()
+            .collect())
+    }
+
+    async fn is_delivered(
+        &self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+    ) -> Result<bool> {
+        Ok(self
+            .tables()
+            .delivered_mut(subscriber_id, channel_id, activity_id)
+            .is_ok())
+    }
+
+    async fn insert_delivered(
+        &self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+        message_ids: &[i64],
+        content: &str,
+        notifications: &[NewNotification],
+    ) -> Result<Option<DeliveredActivity>> {
+        let mut tables = self.tables();
+        let delivered = tables.insert_delivered(DeliveredActivity {
+            subscriber_id,
+            channel_id: channel_id as _,
+            activity_id,
+            delivered_at: Utc::now(),
+            message_ids: message_ids.to_vec(),
+            content: Some(content.into()),
+            deleted: false,
+            digest_message_id: None,
+        });
+        if delivered.is_some() {
+            tables.insert_notifications(notifications);
+        }
+        Ok(delivered)
+    }
+
+    async fn insert_digested(
+        &self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+        digest_message_id: i64,
+        content: &str,
+        notifications: &[NewNotification],
+    ) -> Result<Option<DeliveredActivity>> {
+        let mut tables = self.tables();
+        let delivered = tables.insert_delivered(DeliveredActivity {
+            subscriber_id,
+            channel_id: channel_id as _,
+            activity_id,
+            delivered_at: Utc::now(),
+            message_ids: vec![],
+            content: Some(content.into()),
+            deleted: false,
+            digest_message_id: Some(digest_message_id),
+        });
+        if delivered.is_some() {
+            tables.insert_notifications(notifications);
+        }
+        Ok(delivered)
+    }
+
+    async fn get_digested(
+        &self,
+        channel_id: u64,
+        digest_message_id: i64,
+    ) -> Result<Vec<DeliveredActivity>> {
+        let mut digested: Vec<_> = self
+            .tables()
+            .delivered_activities
+            .iter()
+            .filter(|delivered| {
+                delivered.channel_id == channel_id as i64
+                    && delivered.digest_message_id == Some(digest_message_id)
+            })
+            .cloned()
+            .collect();
+        digested.sort_by_key(|delivered| delivered.activity_id);
+        Ok(digested)
+    }
+
+    async fn get_delivered(
+        &self,
+        subscriber_id: i32,
+        activity_id: i64,
+    ) -> Result<Vec<DeliveredActivity>> {
+        Ok(self
+            .tables()
+            .delivered_activities
+            .iter()
+            .filter(|delivered| {
+                delivered.subscriber_id == subscriber_id && delivered.activity_id == activity_id
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn get_delivered_since(
+        &self,
+        subscriber_id: i32,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DeliveredActivity>> {
+        Ok(self
+            .tables()
+            .delivered_activities
+            .iter()
+            .filter(|delivered| {
+                delivered.subscriber_id == subscriber_id
+                    && delivered.delivered_at >= since
+                    && !delivered.deleted
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn mark_delivered_deleted(
+        &self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+    ) -> Result<DeliveredActivity> {
+        let mut tables = self.tables();
+        let delivered = tables.delivered_mut(subscriber_id, channel_id, activity_id)?;
+        delivered.deleted = true;
+        Ok(delivered.clone())
+    }
+
+    async fn update_delivered_content(
+        &self,
+        subscriber_id: i32,
+        channel_id: u64,
+        activity_id: i64,
+        content: &str,
+    ) -> Result<DeliveredActivity> {
+        let mut tables = self.tables();
+        let delivered = tables.delivered_mut(subscriber_id, channel_id, activity_id)?;
+        delivered.content = Some(content.into());
+        Ok(delivered.clone())
+    }
+
+    async fn enqueue_activities(
+        &self,
+        activities: &[(i32, Vec<(i64, String)>)],
+        end_cursor: Option<&str>,
+    ) -> Result<()> {
+        // ロックを取ったまま全て更新するので、データベースのトランザクションと同じく途中の状態は見えない
+        let mut tables = self.tables();
+        // 途中で失敗しても何も変えないように、先に購読者がいることを確かめる
+        for (subscriber_id, _) in activities {
+            tables.subscriber_mut(*subscriber_id)?;
+        }
+        for (subscriber_id, activities) in activities {
+            let subscriber_id = *subscriber_id;
+            let subscriber = tables.subscriber_mut(subscriber_id)?;
+            if let Some(last_activity_id) = activities.iter().map(|(id, _)| *id).max() {
+                subscriber.last_activity_id = Some(last_activity_id);
+            }
+            if let Some(end_cursor) = end_cursor {
+                subscriber.end_cursor = Some(end_cursor.into());
+            }
+            for (activity_id, payload) in activities {
+                let exists = tables.outbox.iter().any(|row| {
+                    row.subscriber_id == subscriber_id && row.activity_id == *activity_id
+                });
+                if !exists {
+                    tables.outbox.push(OutboxActivity {
+                        subscriber_id,
+                        activity_id: *activity_id,
+                        payload: payload.clone(),
+                        created_at: Utc::now(),
+                        sent_at: None,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_unsent(&self, subscriber_id: i32) -> Result<Vec<OutboxActivity>> {
+        let mut unsent: Vec<_> = self
+            .tables()
+            .outbox
+            .iter()
+            .filter(|row| row.subscriber_id == subscriber_id && row.sent_at.is_none())
+            .cloned()
+            .collect();
+        unsent.sort_by_key(|row| row.activity_id);
+        Ok(unsent)
+    }
+
+    async fn mark_sent(&self, subscriber_id: i32, activity_ids: &[i64]) -> Result<usize> {
+        let now = Utc::now();
+        let mut count = 0;
+        for row in self.tables().outbox.iter_mut().filter(|row| {
+            row.subscriber_id == subscriber_id && activity_ids.contains(&row.activity_id)
+        }) {
+            row.sent_at = Some(now);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    async fn prune_outbox(&self, before: DateTime<Utc>) -> Result<usize> {
+        let mut tables = self.tables();
+        let len = tables.outbox.len();
+        tables
+            .outbox
+            .retain(|row| row.sent_at.is_none_or(|sent_at| sent_at >= before));
+        Ok(len - tables.outbox.len())
+    }
+
+    async fn get_guild(&self, guild_id: u64) -> Result<Option<Guild>> {
+        Ok(self
+            .tables()
+            .guilds
+            .iter()
+            .find(|guild| guild.guild_id == guild_id as i64)
+            .cloned())
+    }
+
+    async fn get_guilds(&self) -> Result<Vec<Guild>> {
+        Ok(self.tables().guilds.clone())
+    }
+
+    async fn update_guild(&self, guild: &Guild) -> Result<Guild> {
+        let mut tables = self.tables();
+        match tables
+            .guilds
+            .iter_mut()
+            .find(|other| other.guild_id == guild.guild_id)
+        {
+            Some(other) => *other = guild.clone(),
+            None => tables.guilds.push(guild.clone()),
+        }
+        Ok(guild.clone())
+    }
+
+    async fn remove_guild(&self, guild_id: u64) -> Result<bool> {
+        let mut tables = self.tables();
+        let guild_id = guild_id as i64;
+        let len = tables.guilds.len();
+        tables.guilds.retain(|guild| guild.guild_id != guild_id);
+        if tables.guilds.len() == len {
+            return Ok(false);
+        }
+
+        // データベースの ON DELETE CASCADE と同じく、参照している行も削除する
+        tables
+            .channels
+            .retain(|channel| channel.guild_id != guild_id);
+        let removed: Vec<_> = tables
+            .subscribers
+            .iter()
+            .filter(|subscriber| subscriber.guild_id == guild_id)
+            .map(|subscriber| subscriber.id)
+            .collect();
+        tables
+            .subscribers
+            .retain(|subscriber| !removed.contains(&subscriber.id));
+        tables
+            .delivered_activities
+            .retain(|delivered| !removed.contains(&delivered.subscriber_id));
+        tables
+            .outbox
+            .retain(|row| !removed.contains(&row.subscriber_id));
+        tables
+            .notifications
+            .retain(|notification| !removed.contains(&notification.subscriber_id));
+        Ok(true)
+    }
+
+    async fn get_notifications(
+        &self,
+        guild_id: u64,
+        filter: &NotificationFilter,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Notification>> {
+        let tables = self.tables();
+        let subscriber_ids: Vec<_> = tables
+            .subscribers
+            .iter()
+            .filter(|subscriber| {
+                subscriber.guild_id == guild_id as i64
+                    && filter
+                        .user_id
+                        .is_none_or(|user_id| subscriber.user_id == user_id as i64)
+            })
+            .map(|subscriber| subscriber.id)
+            .collect();
+
+        let mut notifications: Vec<_> = tables
+            .notifications
+            .iter()
+            .filter(|notification| {
+                subscriber_ids.contains(&notification.subscriber_id)
+                    && filter
+                        .work_title
+                        .as_ref()
+                        .is_none_or(|title| notification.work_title.contains(title.as_str()))
+                    && filter.channel_ids.as_ref().is_none_or(|channel_ids| {
+                        channel_ids.contains(&(notification.channel_id as u64))
+                    })
+                    && filter
+                        .since
+                        .is_none_or(|since| notification.notified_at >= since)
+                    && filter
+                        .until
+                        .is_none_or(|until| notification.notified_at < until)
+            })
+            .cloned()
+            .collect();
+        notifications.sort_by_key(|notification| {
+            std::cmp::Reverse((notification.notified_at, notification.id))
+        });
+        Ok(notifications
+            .into_iter()
+            .skip(offset as _)
+            .take(limit as _)
+            .collect())
+    }
+
+    async fn export(&self, guild_id: Option<u64>) -> Result<Snapshot> {
+        let tables = self.tables();
+        let in_guild = |id: i64| guild_id.is_none_or(|guild_id| id == guild_id as i64);
+        let subscribers: Vec<_> = tables
+            .subscribers
+            .iter()
+            .filter(|subscriber| in_guild(subscriber.guild_id))
+            .cloned()
+            .collect();
+        let subscriber_ids: Vec<_> = subscribers.iter().map(|subscriber| subscriber.id).collect();
+        Ok(Snapshot::new(
+            tables
+                .guilds
+                .iter()
+                .filter(|guild| in_guild(guild.guild_id))
+                .cloned()
+                .collect(),
+            tables
+                .channels
+                .iter()
+                .filter(|channel| in_guild(channel.guild_id))
+                .cloned()
+                .collect(),
+            subscribers,
+            tables
+                .delivered_activities
+                .iter()
+                .filter(|delivered| subscriber_ids.contains(&delivered.subscriber_id))
+                .cloned()
+                .collect(),
+            tables
+                .outbox
+                .iter()
+                .filter(|row| subscriber_ids.contains(&row.subscriber_id))
+                .cloned()
+                .collect(),
+            tables
+                .notifications
+                .iter()
+                .filter(|notification| subscriber_ids.contains(&notification.subscriber_id))
+                .cloned()
+                .collect(),
+        ))
+    }
+
+    async fn import(&self, snapshot: &Snapshot) -> Result<()> {
+        snapshot.validate()?;
+        // ロックを取ったまま読み込むので、データベースのトランザクションと同じく途中の状態は見えない
+        let mut tables = self.tables();
+
+        for record in &snapshot.guilds {
+            let guild = Guild {
+                guild_id: record.guild_id,
+                locale: record.locale.clone(),
+                timezone: record.timezone.clone(),
+                default_notify_flag: NotifyFlag::try_from(record.default_notify_flag)?,
+                digest_threshold: record.digest_threshold,
+                digest_hour: record.digest_hour,
+                link_role_id: record.link_role_id,
+            };
+            match tables
+                .guilds
+                .iter_mut()
+                .find(|other| other.guild_id == guild.guild_id)
+            {
+                Some(other) => *other = guild,
+                None => tables.guilds.push(guild),
+            }
+        }
+
+        for record in &snapshot.channels {
+            tables.ensure_guild(record.guild_id as _);
+            let channel = Channel {
+                guild_id: record.guild_id,
+                channel_id: record.channel_id,
+                notify_flag: NotifyFlag::try_from(record.notify_flag)?,
+                deleted_action: DeletedAction::try_from(record.deleted_action)?,
+            };
+            match tables.channel_mut(record.guild_id as _, record.channel_id as _) {
+                Some(other) => *other = channel,
+                None => tables.channels.push(channel),
+            }
+        }
+
+        // 文書の購読者を、この保存先での購読者の ID に対応させる
+        let mut ids = HashMap::new();
+        for record in &snapshot.subscribers {
+            tables.ensure_guild(record.guild_id as _);
+            let existing = tables.subscribers.iter_mut().find(|subscriber| {
+                subscriber.user_id == record.user_id && subscriber.guild_id == record.guild_id
+            });
+            let id = match existing {
+                Some(subscriber) => {
+                    subscriber.annict_id = record.annict_id;
+                    subscriber.annict_name = record.annict_name.clone();
+                    subscriber.end_cursor = record.end_cursor.clone();
+                    subscriber.last_activity_id = record.last_activity_id;
+                    if let Some(access_token) = &record.access_token {
+                        subscriber.access_token = Some(access_token.clone());
+                    }
+                    subscriber.id
+                }
+                None => {
+                    tables.last_subscriber_id += 1;
+                    let id = tables.last_subscriber_id;
+                    tables.subscribers.push(Subscriber {
+                        id,
+                        user_id: record.user_id,
+                        guild_id: record.guild_id,
+                        annict_name: record.annict_name.clone(),
+                        end_cursor: record.end_cursor.clone(),
+                        access_token: record.access_token.clone(),
+                        annict_id: record.annict_id,
+                        last_activity_id: record.last_activity_id,
+                    });
+                    id
+                }
+            };
+            ids.insert((record.guild_id, record.user_id), id);
+        }
+
+        // 検証済みなので、記録の購読者は必ず見つかる
+        for record in &snapshot.delivered_activities {
+            tables.insert_delivered(DeliveredActivity {
+                subscriber_id: ids[&(record.guild_id, record.user_id)],
+                channel_id: record.channel_id,
+                activity_id: record.activity_id,
+                delivered_at: record.delivered_at,
+                message_ids: record.message_ids.clone(),
+                content: record.content.clone(),
+                deleted: record.deleted,
+                digest_message_id: record.digest_message_id,
+            });
+        }
+
+        for record in &snapshot.outbox {
+            let subscriber_id = ids[&(record.guild_id, record.user_id)];
+            let exists = tables.outbox.iter().any(|row| {
+                row.subscriber_id == subscriber_id && row.activity_id == record.activity_id
+            });
+            if !exists {
+                tables.outbox.push(OutboxActivity {
+                    subscriber_id,
+                    activity_id: record.activity_id,
+                    payload: record.payload.clone(),
+                    created_at: record.created_at,
+                    sent_at: record.sent_at,
+                });
+            }
+        }
+
+        for record in &snapshot.notifications {
+            let subscriber_id = ids[&(record.guild_id, record.user_id)];
+            let exists = tables.notifications.iter().any(|notification| {
+                notification.subscriber_id == subscriber_id
+                    && notification.channel_id == record.channel_id
+                    && notification.activity_id == record.activity_id
+                    && notification.message_id == record.message_id
+            });
+            if !exists {
+                tables.last_notification_id += 1;
+                let id = tables.last_notification_id;
+                tables.notifications.push(Notification {
+                    id,
+                    subscriber_id,
+                    channel_id: record.channel_id,
+                    activity_id: record.activity_id,
+                    message_id: record.message_id,
+                    activity_type: ActivityType::try_from(record.activity_type)?,
+                    work_title: record.work_title.clone(),
+                    work_url: record.work_url.clone(),
+                    notified_at: record.notified_at,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,648 @@
+use crate::{
+    discord::{ActivityType, DeletedAction, NotifyFlag},
+    models::{Guild, NewNotification},
+    snapshot::{Snapshot, VERSION},
+    Result,
+};
+
+use super::{MemoryStore, NotificationFilter, Store};
+
+/// テストを [MemoryStore] で実行する。
+/// 同じテストをデータベースでも実行するので、本体は `pub(crate)` にしておく。
+macro_rules! memory_tests {
+    ($($name:ident),* $(,)?) => {
+        mod memory {
+            $(
+                #[tokio::test]
+                async fn $name() -> crate::Result<()> {
+                    super::$name(&super::MemoryStore::new()).await
+                }
+            )*
+        }
+    };
+}
+
+memory_tests!(
+    channels_test,
+    subscribers_test,
+    annict_user_test,
+    delivered_activities_test,
+    delivered_notifications_test,
+    outbox_test,
+    notifications_test,
+    guilds_test,
+    snapshot_test,
+);
+
+pub(crate) async fn channels_test(store: &dyn Store) -> Result<()> {
+    let channel = store
+        .insert_or_update_channel(1, 32, NotifyFlag::default())
+        .await?;
+    assert_eq!(channel.guild_id, 1);
+    assert_eq!(channel.channel_id, 32);
+    assert_eq!(channel.notify_flag, NotifyFlag::default());
+
+    let channels = store.get_channels().await?;
+    assert_eq!(channels.len(), 1);
+    assert_eq!(channels[0], channel);
+
+    let flag = NotifyFlag::RECORD | NotifyFlag::REVIEW | NotifyFlag::WITH_COMMENT;
+    let channel = store.insert_or_update_channel(1, 32, flag).await?;
+    assert_eq!(channel.guild_id, 1);
+    assert_eq!(channel.channel_id, 32);
+    assert_eq!(channel.notify_flag, flag);
+
+    let channels = store.get_channels().await?;
+    assert_eq!(channels.len(), 1);
+    assert_eq!(channels[0], channel);
+
+    assert_eq!(channel.deleted_action, DeletedAction::Keep);
+    let channel = store
+        .update_deleted_action(1, 32, DeletedAction::Strike)
+        .await?;
+    assert_eq!(channel.deleted_action, DeletedAction::Strike);
+    // 通知設定を変えても削除時の扱いは変わらない
+    let channel = store
+        .insert_or_update_channel(1, 32, NotifyFlag::default())
+        .await?;
+    assert_eq!(channel.deleted_action, DeletedAction::Strike);
+    assert_eq!(store.get_channel(1, 32).await?, Some(channel));
+    assert!(store.get_channel(1, 33).await?.is_none());
+
+    Ok(())
+}
+
+pub(crate) async fn subscribers_test(store: &dyn Store) -> Result<()> {
+    let subscriber = store
+        .insert_or_update_subscriber(1, 1, 100, "kei519", None, None, None)
+        .await?;
+    assert_eq!(subscriber.user_id, 1);
+    assert_eq!(subscriber.guild_id, 1);
+    assert_eq!(subscriber.annict_id, Some(100));
+    assert_eq!(subscriber.annict_name, "kei519");
+    assert!(subscriber.end_cursor.is_none());
+    assert!(subscriber.last_activity_id.is_none());
+
+    let subscribers = store
+        .get_subscribers_by_guild(subscriber.guild_id as _)
+        .await?;
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0], subscriber);
+    assert_eq!(
+        store.get_subscriber(1, 1).await?.as_ref(),
+        Some(&subscriber)
+    );
+    assert!(store.get_subscriber(2, 1).await?.is_none());
+
+    let subscriber = store
+        .insert_or_update_subscriber(1, 1, 100, "hoge", Some("fuga"), Some(10), Some("token"))
+        .await?;
+
+    assert_eq!(subscriber.user_id, 1);
+    assert_eq!(subscriber.guild_id, 1);
+    assert_eq!(subscriber.annict_name, "hoge");
+    assert_eq!(subscriber.end_cursor.as_ref().unwrap(), "fuga");
+    assert_eq!(subscriber.last_activity_id, Some(10));
+    assert_eq!(subscriber.access_token.as_deref(), Some("token"));
+
+    let subscribers = store
+        .get_subscribers_by_guild(subscriber.guild_id as _)
+        .await?;
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0], subscriber);
+
+    Ok(())
+}
+
+pub(crate) async fn annict_user_test(store: &dyn Store) -> Result<()> {
+    let subscriber = store
+        .insert_or_update_subscriber(1, 1, 100, "kei519", None, None, None)
+        .await?;
+    assert!(store.get_subscribers_without_annict_id().await?.is_empty());
+
+    // ユーザー名の変更
+    let subscriber = store.update_annict_user(subscriber.id, 100, "kei").await?;
+    assert_eq!(subscriber.annict_id, Some(100));
+    assert_eq!(subscriber.annict_name, "kei");
+
+    Ok(())
+}
+
+pub(crate) async fn delivered_activities_test(store: &dyn Store) -> Result<()> {
+    let subscriber = store
+        .insert_or_update_subscriber(1, 1, 100, "kei519", None, None, None)
+        .await?;
+    assert!(!store.is_delivered(subscriber.id, 32, 1000).await?);
+
+    let delivered = store
+        .insert_delivered(subscriber.id, 32, 1000, &[1, 2], "[]", &[])
+        .await?
+        .unwrap();
+    assert_eq!(delivered.subscriber_id, subscriber.id);
+    assert_eq!(delivered.channel_id, 32);
+    assert_eq!(delivered.activity_id, 1000);
+    assert_eq!(delivered.message_ids, [1, 2]);
+    assert_eq!(delivered.content.as_deref(), Some("[]"));
+    assert!(store.is_delivered(subscriber.id, 32, 1000).await?);
+
+    // 同じものは記録されない
+    assert!(store
+        .insert_delivered(subscriber.id, 32, 1000, &[3], "[]", &[])
+        .await?
+        .is_none());
+
+    // チャンネルごとに記録する
+    assert!(!store.is_delivered(subscriber.id, 33, 1000).await?);
+    store
+        .insert_delivered(subscriber.id, 33, 1000, &[4], "[]", &[])
+        .await?;
+    assert_eq!(store.get_delivered(subscriber.id, 1000).await?.len(), 2);
+
+    let delivered = store
+        .update_delivered_content(subscriber.id, 32, 1000, "[{}]")
+        .await?;
+    assert_eq!(delivered.message_ids, [1, 2]);
+    assert_eq!(delivered.content.as_deref(), Some("[{}]"));
+
+    let since = chrono::Utc::now() - chrono::Duration::hours(1);
+    assert_eq!(
+        store.get_delivered_since(subscriber.id, since).await?.len(),
+        2
+    );
+    let delivered = store
+        .mark_delivered_deleted(subscriber.id, 32, 1000)
+        .await?;
+    assert!(delivered.deleted);
+    // 削除されたものは返さない
+    let delivered = store.get_delivered_since(subscriber.id, since).await?;
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].channel_id, 33);
+    let since = chrono::Utc::now() + chrono::Duration::hours(1);
+    assert!(store
+        .get_delivered_since(subscriber.id, since)
+        .await?
+        .is_empty());
+
+    // まとめて通知したものは要約のメッセージから引ける
+    let digested = store
+        .insert_digested(subscriber.id, 32, 1002, 5, "[]", &[])
+        .await?
+        .unwrap();
+    assert!(digested.message_ids.is_empty());
+    assert_eq!(digested.digest_message_id, Some(5));
+    store
+        .insert_digested(subscriber.id, 32, 1001, 5, "[]", &[])
+        .await?;
+    let digested = store.get_digested(32, 5).await?;
+    let ids: Vec<_> = digested.iter().map(|d| d.activity_id).collect();
+    assert_eq!(ids, [1001, 1002]);
+    assert!(store.get_digested(33, 5).await?.is_empty());
+
+    Ok(())
+}
+
+pub(crate) async fn delivered_notifications_test(store: &dyn Store) -> Result<()> {
+    let subscriber = store
+        .insert_or_update_subscriber(1, 1, 100, "kei519", None, None, None)
+        .await?;
+    let notification = |activity_id, message_id| NewNotification {
+        subscriber_id: subscriber.id,
+        channel_id: 32,
+        activity_id,
+        message_id,
+        activity_type: ActivityType::Record,
+        work_title: "作品".into(),
+        work_url: "https://annict.com/works/1".into(),
+    };
+    let message_ids = |notifications: Vec<crate::models::Notification>| -> Vec<i64> {
+        notifications.iter().map(|n| n.message_id).collect()
+    };
+    let filter = NotificationFilter::default();
+
+    // 通知の記録と同時に履歴を記録する
+    store
+        .insert_delivered(
+            subscriber.id,
+            32,
+            1000,
+            &[1],
+            "[]",
+            &[notification(1000, 1)],
+        )
+        .await?;
+    assert_eq!(
+        message_ids(store.get_notifications(1, &filter, 0, 10).await?),
+        [1]
+    );
+    // 既に記録されていた場合は履歴も記録しない
+    store
+        .insert_delivered(
+            subscriber.id,
+            32,
+            1000,
+            &[2],
+            "[]",
+            &[notification(1000, 2)],
+        )
+        .await?;
+    assert_eq!(store.get_notifications(1, &filter, 0, 10).await?.len(), 1);
+
+    // 要約でまとめて通知したものも履歴に記録する
+    store
+        .insert_digested(subscriber.id, 32, 1001, 5, "[]", &[notification(1001, 5)])
+        .await?;
+    store
+        .insert_digested(subscriber.id, 32, 1001, 5, "[]", &[notification(1001, 5)])
+        .await?;
+    let notifications = store.get_notifications(1, &filter, 0, 10).await?;
+    let activity_ids: Vec<_> = notifications.iter().map(|n| n.activity_id).collect();
+    assert_eq!(activity_ids.len(), 2);
+    assert!(activity_ids.contains(&1001));
+
+    Ok(())
+}
+
+pub(crate) async fn outbox_test(store: &dyn Store) -> Result<()> {
+    let subscriber = store
+        .insert_or_update_subscriber(1, 1, 100, "kei519", None, None, None)
+        .await?;
+
+    let other_guild = store
+        .insert_or_update_subscriber(1, 2, 100, "kei519", None, None, None)
+        .await?;
+
+    let activities = vec![(1001, "b".to_string()), (1000, "a".to_string())];
+    store
+        .enqueue_activities(
+            &[(subscriber.id, activities), (other_guild.id, vec![])],
+            Some("cursor"),
+        )
+        .await?;
+    // 送信待ちに加えると同時に、加え終えたところと取得したところまで進める
+    let subscriber = &store.get_subscribers_by_guild(1).await?[0];
+    assert_eq!(subscriber.last_activity_id, Some(1001));
+    assert_eq!(subscriber.end_cursor.as_deref(), Some("cursor"));
+    let other_guild = &store.get_subscribers_by_guild(2).await?[0];
+    assert_eq!(other_guild.last_activity_id, None);
+    assert_eq!(other_guild.end_cursor.as_deref(), Some("cursor"));
+
+    // 同じものは加えず、カーソルが無い場合はカーソルを変えない
+    store
+        .enqueue_activities(&[(subscriber.id, vec![(1001, "c".to_string())])], None)
+        .await?;
+    assert_eq!(
+        store.get_subscribers_by_guild(1).await?[0]
+            .end_cursor
+            .as_deref(),
+        Some("cursor")
+    );
+    // 途中で失敗した場合は何も変えない
+    assert!(store
+        .enqueue_activities(
+            &[
+                (subscriber.id, vec![(1002, "d".to_string())]),
+                (-1, vec![(1002, "d".to_string())]),
+            ],
+            Some("other"),
+        )
+        .await
+        .is_err());
+    let subscriber = &store.get_subscribers_by_guild(1).await?[0];
+    assert_eq!(subscriber.last_activity_id, Some(1001));
+    assert_eq!(subscriber.end_cursor.as_deref(), Some("cursor"));
+    let unsent = store.get_unsent(subscriber.id).await?;
+    let rows: Vec<_> = unsent
+        .iter()
+        .map(|row| (row.activity_id, row.payload.as_str()))
+        .collect();
+    assert_eq!(rows, [(1000, "a"), (1001, "b")]);
+
+    assert_eq!(store.mark_sent(subscriber.id, &[1000]).await?, 1);
+    let unsent = store.get_unsent(subscriber.id).await?;
+    assert_eq!(unsent.len(), 1);
+    assert_eq!(unsent[0].activity_id, 1001);
+
+    // 送信し終えたものだけを取り除く
+    assert_eq!(
+        store
+            .prune_outbox(chrono::Utc::now() + chrono::Duration::hours(1))
+            .await?,
+        1
+    );
+    assert_eq!(store.get_unsent(subscriber.id).await?.len(), 1);
+
+    Ok(())
+}
+
+pub(crate) async fn notifications_test(store: &dyn Store) -> Result<()> {
+    let first = store
+        .insert_or_update_subscriber(1, 1, 100, "kei519", None, None, None)
+        .await?;
+    let second = store
+        .insert_or_update_subscriber(2, 1, 200, "hoge", None, None, None)
+        .await?;
+    let other_guild = store
+        .insert_or_update_subscriber(1, 2, 100, "kei519", None, None, None)
+        .await?;
+
+    let notification = |subscriber_id, message_id, work_title: &str| NewNotification {
+        subscriber_id,
+        channel_id: 32,
+        activity_id: message_id,
+        message_id,
+        activity_type: ActivityType::Record,
+        work_title: work_title.into(),
+        work_url: "https://annict.com/works/1".into(),
+    };
+    // 履歴は通知の記録と同時に記録する
+    for notification in [
+        notification(first.id, 1, "作品 100%"),
+        notification(second.id, 2, "作品 B"),
+        notification(first.id, 3, "作品 B"),
+        notification(other_guild.id, 4, "作品 B"),
+    ] {
+        store
+            .insert_delivered(
+                notification.subscriber_id,
+                notification.channel_id as _,
+                notification.activity_id,
+                &[notification.message_id],
+                "",
+                std::slice::from_ref(&notification),
+            )
+            .await?;
+    }
+
+    let message_ids = |notifications: Vec<crate::models::Notification>| -> Vec<i64> {
+        notifications.iter().map(|n| n.message_id).collect()
+    };
+
+    // サーバーの中で新しい順に返す
+    let filter = NotificationFilter::default();
+    let notifications = store.get_notifications(1, &filter, 0, 10).await?;
+    assert_eq!(notifications[0].activity_type, ActivityType::Record);
+    assert_eq!(notifications[0].work_url, "https://annict.com/works/1");
+    assert_eq!(message_ids(notifications), [3, 2, 1]);
+    assert_eq!(
+        message_ids(store.get_notifications(1, &filter, 1, 1).await?),
+        [2]
+    );
+
+    // メンバーや作品で絞り込む
+    let filter = NotificationFilter {
+        user_id: Some(1),
+        ..Default::default()
+    };
+    assert_eq!(
+        message_ids(store.get_notifications(1, &filter, 0, 10).await?),
+        [3, 1]
+    );
+    let filter = NotificationFilter {
+        work_title: Some("B".into()),
+        ..Default::default()
+    };
+    assert_eq!(
+        message_ids(store.get_notifications(1, &filter, 0, 10).await?),
+        [3, 2]
+    );
+    // % はそのまま一致させる
+    let filter = NotificationFilter {
+        work_title: Some("0%".into()),
+        ..Default::default()
+    };
+    assert_eq!(
+        message_ids(store.get_notifications(1, &filter, 0, 10).await?),
+        [1]
+    );
+
+    // 期間で絞り込む
+    let now = chrono::Utc::now();
+    let filter = NotificationFilter {
+        since: Some(now - chrono::Duration::hours(1)),
+        until: Some(now + chrono::Duration::hours(1)),
+        ..Default::default()
+    };
+    assert_eq!(store.get_notifications(1, &filter, 0, 10).await?.len(), 3);
+    let filter = NotificationFilter {
+        until: Some(now - chrono::Duration::hours(1)),
+        ..Default::default()
+    };
+    assert!(store.get_notifications(1, &filter, 0, 10).await?.is_empty());
+
+    // 通知したチャンネルで絞り込む
+    let filter = NotificationFilter {
+        channel_ids: Some(vec![32, 33]),
+        ..Default::default()
+    };
+    assert_eq!(store.get_notifications(1, &filter, 0, 10).await?.len(), 3);
+    let filter = NotificationFilter {
+        channel_ids: Some(vec![33]),
+        ..Default::default()
+    };
+    assert!(store.get_notifications(1, &filter, 0, 10).await?.is_empty());
+
+    Ok(())
+}
+
+pub(crate) async fn guilds_test(store: &dyn Store) -> Result<()> {
+    assert!(store.get_guild(1).await?.is_none());
+
+    // 通知設定や購読者の登録で既定の設定が作られる
+    store
+        .insert_or_update_channel(1, 32, NotifyFlag::default())
+        .await?;
+    store
+        .insert_or_update_subscriber(1, 1, 100, "kei519", None, None, None)
+        .await?;
+    store
+        .insert_or_update_subscriber(2, 2, 200, "other", None, None, None)
+        .await?;
+    assert_eq!(store.get_guild(1).await?, Some(Guild::new(1)));
+    assert_eq!(store.get_guilds().await?.len(), 2);
+
+    let guild = Guild {
+        timezone: "-05:00".into(),
+        default_notify_flag: NotifyFlag::RECORD | NotifyFlag::WITH_COMMENT,
+        digest_threshold: Some(0),
+        digest_hour: Some(7),
+        link_role_id: Some(64),
+        ..Guild::new(1)
+    };
+    assert_eq!(store.update_guild(&guild).await?, guild);
+    assert_eq!(store.get_guild(1).await?, Some(guild.clone()));
+    assert_eq!(guild.utc_offset().local_minus_utc(), -5 * 60 * 60);
+    // 要約の時刻はサーバーのタイムゾーンで判定する
+    let at = |time: &str| time.parse::<chrono::DateTime<chrono::Utc>>();
+    assert_eq!(
+        guild.is_digest_hour(at("2024-10-16T12:30:00Z")?),
+        Some(true)
+    );
+    assert_eq!(
+        guild.is_digest_hour(at("2024-10-16T07:30:00Z")?),
+        Some(false)
+    );
+    assert_eq!(
+        Guild::new(1).is_digest_hour(at("2024-10-16T12:30:00Z")?),
+        None
+    );
+    // 既存の設定を変えない
+    store
+        .insert_or_update_channel(1, 33, NotifyFlag::default())
+        .await?;
+    assert_eq!(store.get_guild(1).await?, Some(guild));
+
+    // サーバーを削除すると、そのサーバーの通知設定と購読者も削除される
+    assert!(store.remove_guild(1).await?);
+    assert!(!store.remove_guild(1).await?);
+    assert!(store.get_guild(1).await?.is_none());
+    assert!(store.get_channels().await?.is_empty());
+    let subscribers = store.get_subscribers().await?;
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0].guild_id, 2);
+
+    Ok(())
+}
+
+pub(crate) async fn snapshot_test(store: &dyn Store) -> Result<()> {
+    store
+        .insert_or_update_channel(1, 32, NotifyFlag::STATUS)
+        .await?;
+    store
+        .update_deleted_action(1, 32, DeletedAction::Mark)
+        .await?;
+    store
+        .insert_or_update_channel(2, 64, NotifyFlag::default())
+        .await?;
+    store
+        .update_guild(&Guild {
+            timezone: "-05:00".into(),
+            digest_hour: Some(7),
+            ..Guild::new(1)
+        })
+        .await?;
+    let first = store
+        .insert_or_update_subscriber(1, 1, 100, "kei519", Some("cursor"), None, Some("token"))
+        .await?;
+    let other_guild = store
+        .insert_or_update_subscriber(1, 2, 100, "kei519", None, None, None)
+        .await?;
+    store
+        .enqueue_activities(
+            &[(first.id, vec![(1000, "a".into()), (1001, "b".into())])],
+            None,
+        )
+        .await?;
+    store.mark_sent(first.id, &[1000]).await?;
+    let notification = |subscriber_id, channel_id| NewNotification {
+        subscriber_id,
+        channel_id,
+        activity_id: 1000,
+        message_id: 5,
+        activity_type: ActivityType::Review,
+        work_title: "作品".into(),
+        work_url: "https://annict.com/works/1".into(),
+    };
+    store
+        .insert_delivered(
+            first.id,
+            32,
+            1000,
+            &[5, 6],
+            "記録",
+            &[notification(first.id, 32)],
+        )
+        .await?;
+    store
+        .insert_digested(first.id, 32, 1001, 7, "要約", &[])
+        .await?;
+    store.mark_delivered_deleted(first.id, 32, 1000).await?;
+    store
+        .insert_delivered(
+            other_guild.id,
+            64,
+            1000,
+            &[5],
+            "記録",
+            &[notification(other_guild.id, 64)],
+        )
+        .await?;
+
+    let snapshot = store.export(None).await?;
+    assert_eq!(snapshot.version, VERSION);
+    assert_eq!(snapshot.guilds.len(), 2);
+    assert_eq!(snapshot.guilds[0].timezone, "-05:00");
+    assert_eq!(snapshot.channels.len(), 2);
+    assert_eq!(snapshot.subscribers.len(), 2);
+    assert_eq!(
+        snapshot.subscribers[0].end_cursor.as_deref(),
+        Some("cursor")
+    );
+    assert_eq!(
+        snapshot.subscribers[0].access_token.as_deref(),
+        Some("token")
+    );
+    assert_eq!(snapshot.subscribers[0].last_activity_id, Some(1001));
+    assert_eq!(snapshot.delivered_activities.len(), 3);
+    assert!(snapshot.delivered_activities[0].deleted);
+    assert_eq!(snapshot.delivered_activities[0].message_ids, [5, 6]);
+    assert_eq!(snapshot.outbox.len(), 2);
+    assert_eq!(snapshot.notifications.len(), 2);
+
+    // JSON を通しても変わらない
+    let json = serde_json::to_string(&snapshot)?;
+    assert_eq!(serde_json::from_str::<Snapshot>(&json)?, snapshot);
+    // 以前の last_activity_date が残った文書も読み込める
+    let mut value = serde_json::to_value(&snapshot)?;
+    for subscriber in value["subscribers"].as_array_mut().unwrap() {
+        subscriber["last_activity_date"] = "2024-10-01T00:00:00Z".into();
+    }
+    assert_eq!(serde_json::from_value::<Snapshot>(value)?, snapshot);
+
+    // 何度読み込んでも変わらない
+    let exported_now = || async {
+        store.export(None).await.map(|exported| Snapshot {
+            exported_at: snapshot.exported_at,
+            ..exported
+        })
+    };
+    store.import(&snapshot).await?;
+    store.import(&snapshot).await?;
+    assert_eq!(exported_now().await?, snapshot);
+
+    // 消えた後に読み込むと元に戻る
+    store.remove_guild(1).await?;
+    store.remove_guild(2).await?;
+    assert!(store.get_subscribers().await?.is_empty());
+    store.import(&snapshot).await?;
+    assert_eq!(exported_now().await?, snapshot);
+
+    // サーバーごとに書き出す
+    let mut guild = store.export(Some(1)).await?;
+    assert_eq!(guild.guilds.len(), 1);
+    assert_eq!(guild.channels.len(), 1);
+    assert_eq!(guild.subscribers.len(), 1);
+    assert_eq!(guild.delivered_activities.len(), 2);
+    assert_eq!(guild.notifications.len(), 1);
+    assert_eq!(guild.notifications[0].channel_id, 32);
+    // アクセストークンを除いた文書を読み込んでも、アクセストークンは消えない
+    guild.remove_access_tokens();
+    store.import(&guild).await?;
+    assert_eq!(exported_now().await?, snapshot);
+
+    // 不正な文書は何も変更せずにエラーにする
+    let mut invalid = guild.clone();
+    invalid.version = VERSION + 1;
+    assert!(store.import(&invalid).await.is_err());
+    let mut invalid = guild.clone();
+    invalid.channels[0].notify_flag = 1 << 10;
+    assert!(store.import(&invalid).await.is_err());
+    let mut invalid = guild.clone();
+    invalid.guilds[0].digest_hour = Some(24);
+    assert!(store.import(&invalid).await.is_err());
+    let mut invalid = guild;
+    invalid.subscribers.clear();
+    assert!(store.import(&invalid).await.is_err());
+    assert_eq!(exported_now().await?, snapshot);
+
+    Ok(())
+}